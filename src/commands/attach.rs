@@ -3,57 +3,267 @@
 //! This command is called by wsl.conf at boot time to ensure the Btrfs VHDX
 //! is attached before systemd mount units try to mount it.
 
-use anyhow::Result;
-use std::process::Command;
+use anyhow::{bail, Context, Result};
+use log::{debug, warn};
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use crate::config::Config;
-use crate::utils::cli::find_btrfs_device_by_label;
+use crate::utils::cli::{find_btrfs_devices_by_label, find_command, BlockDevice};
+use crate::utils::shell::run_with_timeout;
+use crate::utils::wsl::{ensure_interop, interop_available, mount_bare_vhd, to_windows_path};
 
-/// Check if a Btrfs filesystem with the given label is available
-fn is_btrfs_available(label: &str) -> bool {
-    find_btrfs_device_by_label(label).unwrap_or(None).is_some()
+/// Device with the configured label, or None if no such device is attached
+///
+/// When several devices share the label (e.g. a cloned VHDX), the one whose
+/// UUID matches `uuid` wins; without a single match the choice would be a
+/// guess, since a byte-for-byte clone shares the UUID as well.
+fn find_labeled_device(label: &str, uuid: Option<&str>) -> Result<Option<String>> {
+    let devices = find_btrfs_devices_by_label(label).unwrap_or_default();
+    pick_device(label, uuid, &devices)
 }
 
-/// Ensure binfmt_misc is configured so wsl.exe can be executed
-fn setup_binfmt() -> Result<()> {
-    Command::new("/usr/lib/systemd/systemd-binfmt")
-        .status()
-        .map_err(|e| anyhow::anyhow!("Failed to run systemd-binfmt: {}", e))?;
+fn pick_device(label: &str, uuid: Option<&str>, devices: &[BlockDevice]) -> Result<Option<String>> {
+    let path = |device: &BlockDevice| format!("/dev/{}", device.name);
+    match devices {
+        [] => Ok(None),
+        [device] => {
+            if let (Some(expected), Some(found)) = (uuid, device.uuid.as_deref()) {
+                if expected != found {
+                    warn!(
+                        "{} has label '{}' but UUID {} (config: {}); mount units will not find it",
+                        path(device),
+                        label,
+                        found,
+                        expected
+                    );
+                }
+            }
+            Ok(Some(path(device)))
+        }
+        several => {
+            let names: Vec<String> = several.iter().map(path).collect();
+            let matching: Vec<&BlockDevice> = several
+                .iter()
+                .filter(|device| uuid.is_some() && device.uuid.as_deref() == uuid)
+                .collect();
+            match matching.as_slice() {
+                [device] => {
+                    warn!(
+                        "Label '{}' is on several devices ({}); using {} which matches the configured UUID",
+                        label,
+                        names.join(", "),
+                        path(device)
+                    );
+                    Ok(Some(path(device)))
+                }
+                [] => bail!(
+                    "Label '{}' is on several devices ({}) and none matches the configured UUID. \
+                     Detach the clone or give it a different label (wslarc relabel).",
+                    label,
+                    names.join(", ")
+                ),
+                clones => bail!(
+                    "Label '{}' and the configured UUID are on several devices ({}). \
+                     Detach the clone: mount units find the disk by UUID, which a copied VHDX shares.",
+                    label,
+                    clones
+                        .iter()
+                        .map(|device| path(device))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            }
+        }
+    }
+}
+
+/// Shared by every distro in the WSL VM, unlike /run which is per distro
+const OWNER_DIR: &str = "/mnt/wsl/wslarc";
+
+/// Marker file recording which distro attached the disk with this label
+fn owner_marker(label: &str) -> PathBuf {
+    PathBuf::from(OWNER_DIR).join(format!("{}.owner", label.replace('/', "_")))
+}
+
+fn read_owner(label: &str) -> Option<String> {
+    fs::read_to_string(owner_marker(label))
+        .ok()
+        .map(|owner| owner.trim().to_string())
+        .filter(|owner| !owner.is_empty())
+}
+
+fn write_owner(label: &str, distro: &str) -> Result<()> {
+    fs::create_dir_all(OWNER_DIR).with_context(|| format!("Failed to create {}", OWNER_DIR))?;
+    let marker = owner_marker(label);
+    fs::write(&marker, format!("{}\n", distro))
+        .with_context(|| format!("Failed to write {}", marker.display()))
+}
+
+/// Refuse to share a bare-mounted disk that another distro attached
+fn check_owner(label: &str, owner: Option<&str>, distro: Option<&str>) -> Result<()> {
+    if let (Some(owner), Some(distro)) = (owner, distro) {
+        if owner != distro {
+            bail!(
+                "Btrfs disk '{}' is attached by WSL distro '{}' (this is '{}'). \
+                 Detach it there or set a different vhdx.label.",
+                label,
+                owner,
+                distro
+            );
+        }
+    }
     Ok(())
 }
 
+/// Where distros install systemd-binfmt; PATH is searched as a fallback
+const BINFMT_CANDIDATES: &[&str] = &[
+    "/usr/lib/systemd/systemd-binfmt",
+    "/lib/systemd/systemd-binfmt",
+];
+const BINFMT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Register binfmt_misc so wsl.exe can be executed, without failing the boot path
+///
+/// Interop is often registered already, and a missing or hanging
+/// systemd-binfmt should not stop the attach; `ensure_interop` reports the
+/// real problem if wsl.exe still cannot run.
+fn setup_binfmt() {
+    if interop_available() {
+        debug!("WSL interop already registered, skipping systemd-binfmt");
+        return;
+    }
+    let Some(binfmt) = find_binfmt() else {
+        warn!("systemd-binfmt not found; trying to attach anyway");
+        return;
+    };
+    let binfmt = binfmt.to_string_lossy().to_string();
+    if let Err(e) = run_with_timeout(&binfmt, &[], BINFMT_TIMEOUT) {
+        warn!("{}; trying to attach anyway", e);
+    }
+}
+
+fn find_binfmt() -> Option<PathBuf> {
+    BINFMT_CANDIDATES
+        .iter()
+        .map(PathBuf::from)
+        .find(|path| path.is_file())
+        .or_else(|| find_command("systemd-binfmt"))
+}
+
 /// Attach the VHDX using wsl.exe
 fn attach_vhdx(vhdx_path: &str) -> Result<()> {
-    // Convert path: forward slashes to backslashes for Windows
-    let windows_path = vhdx_path.replace('/', "\\");
-
-    let status = Command::new("/mnt/c/Windows/System32/wsl.exe")
-        .args(["--mount", "--vhd", &windows_path, "--bare"])
-        .status()
-        .map_err(|e| anyhow::anyhow!("Failed to run wsl.exe: {}", e))?;
+    mount_bare_vhd(&to_windows_path(vhdx_path)).context("wsl.exe --mount failed")
+}
 
-    if !status.success() {
-        anyhow::bail!("wsl.exe --mount failed with exit code: {:?}", status.code());
+/// Poll until the labeled Btrfs device is enumerated so boot-time mount units find it
+fn wait_for_device(label: &str, uuid: Option<&str>, timeout: Duration) -> Result<()> {
+    let deadline = Instant::now() + timeout;
+    while find_labeled_device(label, uuid)?.is_none() {
+        if Instant::now() >= deadline {
+            bail!(
+                "Btrfs device with label '{}' did not appear within {}s",
+                label,
+                timeout.as_secs()
+            );
+        }
+        thread::sleep(Duration::from_millis(250));
     }
-
     Ok(())
 }
 
-pub fn run(config: &Config) -> Result<()> {
+/// Attach the VHDX; with `wait_timeout`, block until the device is visible
+pub fn run(config: &Config, wait_timeout: Option<Duration>) -> Result<()> {
     // Ensure binfmt_misc is configured so wsl.exe can be executed
-    setup_binfmt()?;
+    setup_binfmt();
 
     let label = &config.vhdx.label;
     let vhdx_path = &config.vhdx.path;
+    let uuid = config.uuid.as_deref();
+    let distro = config.distro_name();
 
     // Check if Btrfs with this label is already available
-    if is_btrfs_available(label) {
-        // Already mounted, nothing to do
-        return Ok(());
+    if find_labeled_device(label, uuid)?.is_some() {
+        // Already mounted; only fine if it was not claimed by another distro
+        return check_owner(label, read_owner(label).as_deref(), distro.as_deref());
     }
 
-    // Attach the VHDX
+    // Attach the VHDX; systemd-binfmt above should have registered interop
+    ensure_interop()?;
     attach_vhdx(vhdx_path)?;
+    if let Some(distro) = &distro {
+        write_owner(label, distro)?;
+    }
+
+    if let Some(timeout) = wait_timeout {
+        wait_for_device(label, uuid, timeout)?;
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_owner_rejects_other_distro() {
+        assert!(check_owner("ArchBtrfs", Some("Arch"), Some("Arch")).is_ok());
+        assert!(check_owner("ArchBtrfs", None, Some("Arch")).is_ok());
+        assert!(check_owner("ArchBtrfs", Some("Arch"), None).is_ok());
+
+        let err = check_owner("ArchBtrfs", Some("Ubuntu"), Some("Arch")).unwrap_err();
+        assert!(err.to_string().contains("attached by WSL distro 'Ubuntu'"));
+    }
+
+    fn device(name: &str, uuid: &str) -> BlockDevice {
+        BlockDevice {
+            name: name.to_string(),
+            label: Some("ArchBtrfs".to_string()),
+            fstype: Some("btrfs".to_string()),
+            size: None,
+            uuid: Some(uuid.to_string()),
+        }
+    }
+
+    #[test]
+    fn pick_device_prefers_configured_uuid_among_duplicates() {
+        let devices = vec![device("sdc", "aaaa"), device("sdd", "bbbb")];
+
+        assert_eq!(pick_device("ArchBtrfs", Some("bbbb"), &[]).unwrap(), None);
+        assert_eq!(
+            pick_device("ArchBtrfs", Some("bbbb"), &devices).unwrap(),
+            Some("/dev/sdd".to_string())
+        );
+        assert_eq!(
+            pick_device("ArchBtrfs", None, &devices[..1]).unwrap(),
+            Some("/dev/sdc".to_string())
+        );
+
+        let err = pick_device("ArchBtrfs", None, &devices).unwrap_err();
+        assert!(err.to_string().contains("/dev/sdc, /dev/sdd"));
+        assert!(pick_device("ArchBtrfs", Some("cccc"), &devices).is_err());
+    }
+
+    #[test]
+    fn pick_device_rejects_clones_sharing_the_uuid() {
+        let devices = vec![
+            device("sdc", "bbbb"),
+            device("sdd", "aaaa"),
+            device("sde", "bbbb"),
+        ];
+
+        let err = pick_device("ArchBtrfs", Some("bbbb"), &devices).unwrap_err();
+        assert!(err.to_string().contains("(/dev/sdc, /dev/sde)"), "{}", err);
+    }
+
+    #[test]
+    fn owner_marker_is_namespaced_by_label() {
+        assert_eq!(
+            owner_marker("work/disk"),
+            PathBuf::from("/mnt/wsl/wslarc/work_disk.owner")
+        );
+    }
+}
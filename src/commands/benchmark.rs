@@ -0,0 +1,220 @@
+//! Compare zstd levels on a sample of real data
+//!
+//! Copies files from an existing directory into a sample, compresses it at each
+//! level with the zstd CLI and reports ratio and throughput, so the
+//! `compress=zstd:N` mount option can be chosen from measurements.
+
+use anyhow::{bail, Context, Result};
+use console::style;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::utils::cli::{ensure_dependencies, format_bytes, Dependency};
+use crate::utils::prompt::{info, section, success, warn};
+use crate::utils::shell::run as shell_run;
+
+#[derive(Debug)]
+struct LevelResult {
+    level: u32,
+    ratio: f64,
+    /// Compression throughput in MiB/s
+    throughput: f64,
+}
+
+pub fn run(path: &str, sample_mib: u64, levels: &[u32]) -> Result<()> {
+    println!("{}", style("Compression Benchmark").bold().cyan());
+    println!();
+
+    if levels.is_empty() {
+        bail!("No zstd levels given");
+    }
+    if let Some(level) = levels.iter().find(|level| !(1..=15).contains(*level)) {
+        bail!(
+            "zstd level {} is outside the range btrfs accepts (1-15)",
+            level
+        );
+    }
+
+    ensure_dependencies(&[Dependency::new("zstd", &["zstd"])])?;
+
+    let workdir = std::env::temp_dir().join(format!("wslarc-benchmark-{}", std::process::id()));
+    fs::create_dir_all(&workdir)
+        .with_context(|| format!("Failed to create {}", workdir.display()))?;
+    let result = benchmark(Path::new(path), &workdir, sample_mib, levels);
+    let _ = fs::remove_dir_all(&workdir);
+    let results = result?;
+
+    section("Results");
+    println!("  {:<8} {:>8} {:>12}", "Level", "Ratio", "Throughput");
+    for result in &results {
+        println!(
+            "  {:<8} {:>7.2}x {:>7.1} MiB/s",
+            format!("zstd:{}", result.level),
+            result.ratio,
+            result.throughput
+        );
+    }
+
+    println!();
+    match suggest_level(&results) {
+        Some(level) => {
+            success(&format!("Suggested mount option: compress=zstd:{}", level));
+            info(&format!(
+                "Set it with 'wslarc config set mount.compression zstd:{}' and rerun 'wslarc mount'",
+                level
+            ));
+        }
+        None => warn("Sample did not compress; consider compress=no for this data"),
+    }
+
+    Ok(())
+}
+
+fn benchmark(
+    source: &Path,
+    workdir: &Path,
+    sample_mib: u64,
+    levels: &[u32],
+) -> Result<Vec<LevelResult>> {
+    let sample = workdir.join("sample");
+    info(&format!(
+        "Collecting up to {} MiB from {}",
+        sample_mib,
+        source.display()
+    ));
+    let sample_size = collect_sample(source, &sample, sample_mib * 1024 * 1024)?;
+    if sample_size == 0 {
+        bail!("No readable files found in {}", source.display());
+    }
+    info(&format!("Sample size: {}", format_bytes(sample_size)));
+
+    let sample_arg = sample.to_string_lossy().to_string();
+    let output = workdir.join("sample.zst");
+    let output_arg = output.to_string_lossy().to_string();
+
+    let mut results = Vec::new();
+    for level in levels {
+        info(&format!("Compressing with zstd -{}...", level));
+        let level_arg = format!("-{}", level);
+        let started = Instant::now();
+        shell_run(
+            "zstd",
+            &[
+                "-q",
+                "-f",
+                "--single-thread",
+                &level_arg,
+                &sample_arg,
+                "-o",
+                &output_arg,
+            ],
+        )?;
+        let elapsed = started.elapsed().as_secs_f64().max(f64::EPSILON);
+        let compressed = fs::metadata(&output)?.len().max(1);
+
+        results.push(LevelResult {
+            level: *level,
+            ratio: sample_size as f64 / compressed as f64,
+            throughput: sample_size as f64 / (1024.0 * 1024.0) / elapsed,
+        });
+    }
+
+    Ok(results)
+}
+
+/// Concatenate regular files under `source` into `sample`, up to `limit` bytes
+fn collect_sample(source: &Path, sample: &Path, limit: u64) -> Result<u64> {
+    let mut out =
+        File::create(sample).with_context(|| format!("Failed to create {}", sample.display()))?;
+    let mut pending: Vec<PathBuf> = vec![source.to_path_buf()];
+    let mut written = 0;
+
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut entries: Vec<_> = entries.filter_map(|entry| entry.ok()).collect();
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                pending.push(entry.path());
+            } else if file_type.is_file() {
+                // Unreadable files (permissions, sockets in disguise) are skipped
+                let Ok(file) = File::open(entry.path()) else {
+                    continue;
+                };
+                written += io::copy(&mut file.take(limit - written), &mut out)?;
+                if written >= limit {
+                    out.flush()?;
+                    return Ok(written);
+                }
+            }
+        }
+    }
+
+    out.flush()?;
+    Ok(written)
+}
+
+/// Best ratio among levels that keep at least half the fastest level's throughput
+fn suggest_level(results: &[LevelResult]) -> Option<u32> {
+    let fastest = results
+        .iter()
+        .map(|result| result.throughput)
+        .fold(0.0, f64::max);
+
+    results
+        .iter()
+        .filter(|result| result.ratio > 1.05 && result.throughput >= fastest / 2.0)
+        .max_by(|a, b| a.ratio.total_cmp(&b.ratio))
+        .map(|result| result.level)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(level: u32, ratio: f64, throughput: f64) -> LevelResult {
+        LevelResult {
+            level,
+            ratio,
+            throughput,
+        }
+    }
+
+    #[test]
+    fn suggest_level_trades_ratio_against_throughput() {
+        let results = vec![
+            result(1, 2.8, 400.0),
+            result(3, 3.0, 250.0),
+            result(9, 3.3, 60.0),
+        ];
+        assert_eq!(suggest_level(&results), Some(3));
+    }
+
+    #[test]
+    fn suggest_level_skips_incompressible_data() {
+        let results = vec![result(1, 1.01, 400.0), result(3, 1.02, 300.0)];
+        assert_eq!(suggest_level(&results), None);
+    }
+
+    #[test]
+    fn collect_sample_stops_at_limit() {
+        let source = tempfile::tempdir().unwrap();
+        fs::create_dir(source.path().join("nested")).unwrap();
+        fs::write(source.path().join("a"), vec![b'a'; 600]).unwrap();
+        fs::write(source.path().join("nested/b"), vec![b'b'; 600]).unwrap();
+
+        let out = tempfile::tempdir().unwrap();
+        let sample = out.path().join("sample");
+
+        assert_eq!(collect_sample(source.path(), &sample, 1000).unwrap(), 1000);
+        assert_eq!(fs::metadata(&sample).unwrap().len(), 1000);
+    }
+}
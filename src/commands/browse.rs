@@ -0,0 +1,167 @@
+use anyhow::{bail, Result};
+use chrono::Local;
+use console::style;
+use std::fs;
+use std::io::BufRead;
+use std::path::Path;
+
+use crate::commands::snapshot::snapshots_newest_first;
+use crate::config::Config;
+use crate::utils::cli::{is_mountpoint, list_directory_names};
+use crate::utils::prompt::{info, is_interactive, success, warn};
+use crate::utils::shell::run as shell_run;
+
+/// Each `browse` run mounts its snapshots under a timestamped tree in here
+pub const BROWSE_ROOT: &str = "/mnt/wslarc-browse";
+
+/// Mount every snapshot of `subvolume` read-only side by side for browsing
+///
+/// Unmounts again once Enter is pressed, unless `keep` is set or stdin is not
+/// a terminal; the tree then stays until `browse --cleanup`.
+pub fn run(config: &Config, subvolume: &str, keep: bool) -> Result<()> {
+    println!("{}", style("Browse Snapshots").bold().cyan());
+    println!();
+
+    let Some(uuid) = config.uuid.as_deref() else {
+        bail!("UUID not set. Run 'wslarc init' first.");
+    };
+    let subvol = format!("@{}", subvolume.trim_start_matches('@'));
+    if subvol != "@etc" && !config.subvolumes.backup.contains_key(&subvol) {
+        bail!("{} is not a backup subvolume", subvol);
+    }
+
+    let snapshot_dir = format!("{}/{}", config.mount.base, config.btrbk.snapshot_dir);
+    let snapshots = snapshots_newest_first(
+        &list_directory_names(&snapshot_dir)?,
+        subvol.trim_start_matches('@'),
+    );
+    if snapshots.is_empty() {
+        bail!("No snapshots of {} found in {}", subvol, snapshot_dir);
+    }
+
+    let tree = format!("{}/{}", BROWSE_ROOT, Local::now().format("%Y%m%dT%H%M%S"));
+    let source = format!("UUID={}", uuid);
+    info(&format!(
+        "Mounting {} snapshots of {} under {}",
+        snapshots.len(),
+        subvol,
+        tree
+    ));
+    for (target, options) in mount_plan(config, &snapshots, &tree) {
+        fs::create_dir_all(&target)?;
+        if let Err(e) = shell_run("mount", &["-t", "btrfs", "-o", &options, &source, &target]) {
+            warn(&format!("Failed to mount {}: {}", target, e));
+            unmount_tree(&tree)?;
+            return Err(e);
+        }
+    }
+
+    println!();
+    println!("{}", style(&tree).bold());
+    for name in &snapshots {
+        println!("  {}/", name);
+    }
+    println!();
+    success(&format!("{} snapshots mounted read-only", snapshots.len()));
+
+    if keep || !is_interactive() {
+        info("Run 'wslarc browse --cleanup' to unmount them");
+        return Ok(());
+    }
+    info("Press Enter to unmount and exit");
+    std::io::stdin().lock().read_line(&mut String::new())?;
+    unmount_tree(&tree)?;
+    success(&format!("Unmounted and removed {}", tree));
+    Ok(())
+}
+
+/// Unmount and remove every tree left under BROWSE_ROOT by earlier runs
+pub fn cleanup() -> Result<()> {
+    println!("{}", style("Clean Up Snapshot Browsing").bold().cyan());
+    println!();
+
+    if !Path::new(BROWSE_ROOT).is_dir() {
+        info(&format!(
+            "{} does not exist, nothing to clean up",
+            BROWSE_ROOT
+        ));
+        return Ok(());
+    }
+    let mut failed = Vec::new();
+    for name in list_directory_names(BROWSE_ROOT)? {
+        let tree = format!("{}/{}", BROWSE_ROOT, name);
+        match unmount_tree(&tree) {
+            Ok(()) => success(&format!("Removed {}", tree)),
+            Err(e) => {
+                warn(&format!("{}", e));
+                failed.push(tree);
+            }
+        }
+    }
+    if !failed.is_empty() {
+        bail!("Could not clean up: {}", failed.join(", "));
+    }
+    fs::remove_dir(BROWSE_ROOT)?;
+    Ok(())
+}
+
+/// (mount point, options) for each snapshot, mounted by subvolume path
+fn mount_plan(config: &Config, snapshots: &[String], tree: &str) -> Vec<(String, String)> {
+    snapshots
+        .iter()
+        .map(|name| {
+            (
+                format!("{}/{}", tree, name),
+                format!("ro,subvol={}/{}", config.btrbk.snapshot_dir, name),
+            )
+        })
+        .collect()
+}
+
+/// Unmount each snapshot mounted in `tree`, then remove the tree
+///
+/// Keeps going past a busy mount so one open shell doesn't leave the rest
+/// mounted; the tree itself is only removed once everything is unmounted.
+fn unmount_tree(tree: &str) -> Result<()> {
+    let mut busy = Vec::new();
+    for name in list_directory_names(tree)? {
+        let target = format!("{}/{}", tree, name);
+        if is_mountpoint(&target) {
+            if let Err(e) = shell_run("umount", &[&target]) {
+                warn(&format!("Failed to unmount {}: {}", target, e));
+                busy.push(target);
+                continue;
+            }
+        }
+        fs::remove_dir(&target)?;
+    }
+    if !busy.is_empty() {
+        bail!("{} still mounted (in use?)", busy.join(", "));
+    }
+    fs::remove_dir(tree)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mount_plan_mounts_each_snapshot_read_only_by_path() {
+        let cfg = Config::default();
+        let snapshots = vec![
+            "home.20240115T0300".to_string(),
+            "home.20240114T0300".to_string(),
+        ];
+
+        let plan = mount_plan(&cfg, &snapshots, "/mnt/wslarc-browse/20240116T1200");
+        assert_eq!(
+            plan[0],
+            (
+                "/mnt/wslarc-browse/20240116T1200/home.20240115T0300".to_string(),
+                format!("ro,subvol={}/home.20240115T0300", cfg.btrbk.snapshot_dir)
+            )
+        );
+        assert_eq!(plan.len(), 2);
+    }
+}
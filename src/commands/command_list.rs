@@ -0,0 +1,126 @@
+//! List wslarc subcommands and their flags
+//!
+//! Introspects the clap command tree so wrappers and UIs can discover the CLI
+//! without parsing `--help` output.
+
+use anyhow::Result;
+use clap::Command;
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+struct CommandInfo {
+    name: String,
+    about: Option<String>,
+    args: Vec<ArgInfo>,
+    subcommands: Vec<CommandInfo>,
+}
+
+#[derive(Debug, Serialize)]
+struct ArgInfo {
+    id: String,
+    long: Option<String>,
+    short: Option<char>,
+    help: Option<String>,
+    takes_value: bool,
+    required: bool,
+    global: bool,
+}
+
+pub fn run(command: &Command, json: bool) -> Result<()> {
+    let info = describe(command);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&info)?);
+    } else {
+        for line in command_lines(&info, "") {
+            println!("{}", line);
+        }
+    }
+
+    Ok(())
+}
+
+fn describe(command: &Command) -> CommandInfo {
+    CommandInfo {
+        name: command.get_name().to_string(),
+        about: command.get_about().map(|about| about.to_string()),
+        args: command
+            .get_arguments()
+            .filter(|arg| !arg.is_hide_set())
+            .map(|arg| ArgInfo {
+                id: arg.get_id().to_string(),
+                long: arg.get_long().map(str::to_string),
+                short: arg.get_short(),
+                help: arg.get_help().map(|help| help.to_string()),
+                takes_value: arg.get_action().takes_values(),
+                required: arg.is_required_set(),
+                global: arg.is_global_set(),
+            })
+            .collect(),
+        subcommands: command
+            .get_subcommands()
+            .filter(|sub| !sub.is_hide_set())
+            .map(describe)
+            .collect(),
+    }
+}
+
+fn command_lines(info: &CommandInfo, prefix: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    for sub in &info.subcommands {
+        let name = format!("{}{}", prefix, sub.name);
+        lines.push(format!(
+            "{:<24} {}",
+            name,
+            sub.about.as_deref().unwrap_or("")
+        ));
+        lines.extend(command_lines(sub, &format!("{} ", name)));
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{Arg, ArgAction};
+
+    fn sample_command() -> Command {
+        Command::new("wslarc")
+            .arg(
+                Arg::new("yes")
+                    .long("yes")
+                    .short('y')
+                    .global(true)
+                    .action(ArgAction::SetTrue),
+            )
+            .subcommand(
+                Command::new("snapshot")
+                    .about("Snapshot operations")
+                    .subcommand(Command::new("run").about("Create a new snapshot")),
+            )
+            .subcommand(Command::new("secret").hide(true))
+    }
+
+    #[test]
+    fn describe_skips_hidden_subcommands() {
+        let info = describe(&sample_command());
+
+        assert_eq!(info.subcommands.len(), 1);
+        assert_eq!(info.subcommands[0].name, "snapshot");
+        assert_eq!(info.subcommands[0].subcommands[0].name, "run");
+        assert_eq!(info.args[0].short, Some('y'));
+        assert!(info.args[0].global);
+        assert!(!info.args[0].takes_value);
+    }
+
+    #[test]
+    fn command_lines_nest_subcommand_names() {
+        let lines = command_lines(&describe(&sample_command()), "");
+
+        assert!(lines[0].starts_with("snapshot"));
+        assert!(lines[1].starts_with("snapshot run"));
+        assert!(lines[1].ends_with("Create a new snapshot"));
+    }
+}
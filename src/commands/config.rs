@@ -0,0 +1,382 @@
+//! Edit the wslarc config file from the command line
+//!
+//! Keys are dotted paths into the TOML tree (e.g. `mount.options`). The edited
+//! tree is deserialized back into `Config` before saving, so type errors and
+//! unknown keys are rejected instead of being written.
+
+use anyhow::{bail, Context, Result};
+use console::style;
+use std::collections::BTreeSet;
+use std::path::Path;
+use toml::Value;
+
+use crate::config::{mount_point_problem, Config, MountConflict, STDIN_PATH};
+use crate::utils::prompt::{self, info, input, select, success, warn};
+
+pub fn set(config: &Config, config_path: &str, key: &str, value: &str) -> Result<()> {
+    println!("{}", style("Update Configuration").bold().cyan());
+    println!();
+
+    let mut tree = Value::try_from(config).context("Failed to serialize config")?;
+    let old = lookup(&tree, key).map(display_value);
+    set_dotted(&mut tree, key, value)?;
+
+    let updated: Config = tree
+        .try_into()
+        .with_context(|| format!("Invalid value for {}", key))?;
+
+    // serde ignores unknown fields, so make sure the key survived the round trip
+    let roundtrip = Value::try_from(&updated).context("Failed to serialize config")?;
+    let Some(new) = lookup(&roundtrip, key).map(display_value) else {
+        bail!("Unknown config key: {}", key);
+    };
+    updated
+        .validate()
+        .with_context(|| format!("Invalid value for {}", key))?;
+
+    for warning in updated.warnings() {
+        warn(&warning);
+    }
+
+    updated.save(config_path)?;
+    match old {
+        Some(old) => success(&format!("{}: {} -> {}", key, old, new)),
+        None => success(&format!("{} = {}", key, new)),
+    }
+    println!("Saved to {}", config_path);
+
+    Ok(())
+}
+
+/// Print the loaded config after `$USER` expansion and defaults, as TOML
+pub fn print(config: &Config, config_path: &str) -> Result<()> {
+    let source = if config_path == STDIN_PATH || Path::new(config_path).exists() {
+        config_path.to_string()
+    } else {
+        format!("built-in defaults; {} not found", config_path)
+    };
+    // A comment keeps the output valid TOML, so it can be saved and reused
+    println!("# Effective wslarc config ({})", source);
+    print!(
+        "{}",
+        toml::to_string_pretty(config).context("Failed to serialize config")?
+    );
+
+    Ok(())
+}
+
+/// Print every field that differs between the loaded config and `other_path`,
+/// grouped by top-level section (mount, subvolumes, btrbk, ...)
+pub fn diff(config: &Config, config_path: &str, other_path: &str) -> Result<()> {
+    println!("{}", style("Compare Configurations").bold().cyan());
+    let other = Config::load(other_path)?;
+    let changes = config.diff(&other)?;
+
+    println!();
+    println!("  {} {}", style("-").red(), config_path);
+    println!("  {} {}", style("+").green(), other_path);
+
+    if changes.is_empty() {
+        println!();
+        success("No differences");
+        return Ok(());
+    }
+
+    let set_changes = subvolume_set_changes(config, &other);
+    if !set_changes.is_empty() {
+        prompt::section("Subvolume sets");
+        for line in &set_changes {
+            println!("  {}", line);
+        }
+    }
+
+    let mut current_section = None;
+    for change in &changes {
+        let section = change.key.split('.').next().unwrap_or_default();
+        if current_section != Some(section) {
+            prompt::section(section);
+            current_section = Some(section);
+        }
+        prompt::change(&change.key, change.old.as_deref(), change.new.as_deref());
+    }
+
+    println!();
+    info(&format!("{} field(s) differ", changes.len()));
+    Ok(())
+}
+
+/// Subvolumes present in only one of the configs, per class
+fn subvolume_set_changes(old: &Config, new: &Config) -> Vec<String> {
+    let backup = |config: &Config| -> BTreeSet<String> {
+        config.subvolumes.backup.keys().cloned().collect()
+    };
+    let transfer = |config: &Config| -> BTreeSet<String> {
+        config.subvolumes.transfer.keys().cloned().collect()
+    };
+
+    let mut lines = Vec::new();
+    for (class, old_set, new_set) in [
+        ("backup", backup(old), backup(new)),
+        ("transfer", transfer(old), transfer(new)),
+    ] {
+        let removed: Vec<&str> = old_set.difference(&new_set).map(String::as_str).collect();
+        let added: Vec<&str> = new_set.difference(&old_set).map(String::as_str).collect();
+        if !removed.is_empty() {
+            lines.push(format!(
+                "{} {}: {}",
+                style("-").red(),
+                class,
+                removed.join(", ")
+            ));
+        }
+        if !added.is_empty() {
+            lines.push(format!(
+                "{} {}: {}",
+                style("+").green(),
+                class,
+                added.join(", ")
+            ));
+        }
+    }
+    lines
+}
+
+/// Find overlapping mount points and let the user resolve each one.
+///
+/// Returns whether the config changed. With `--yes`, or without a terminal,
+/// any conflict is an error listing all of them.
+pub fn resolve_mount_conflicts(config: &mut Config, yes: bool) -> Result<bool> {
+    let conflicts = config.mount_conflicts();
+    if conflicts.is_empty() {
+        return Ok(false);
+    }
+    if yes || !prompt::is_interactive() {
+        let listing: Vec<String> = conflicts
+            .iter()
+            .map(|conflict| format!("  - {}", conflict.describe()))
+            .collect();
+        bail!(
+            "Overlapping mount points in config:\n{}\nEdit subvolumes.backup / subvolumes.transfer \
+             or rerun without --yes to resolve them interactively",
+            listing.join("\n")
+        );
+    }
+
+    prompt::section("Overlapping mount points");
+    let mut changed = false;
+    // Each fix can create or clear other conflicts, so re-check after every one
+    while let Some(conflict) = config.mount_conflicts().into_iter().next() {
+        warn(&conflict.describe());
+        resolve_conflict(config, &conflict)?;
+        changed = true;
+    }
+    success("Mount points no longer overlap");
+    Ok(changed)
+}
+
+fn resolve_conflict(config: &mut Config, conflict: &MountConflict) -> Result<()> {
+    let subvol = &conflict.subvol;
+    let mut choices = Vec::new();
+    if let Some(other) = &conflict.other {
+        choices.push(format!("Keep {}, drop {}", other, subvol));
+        choices.push(format!("Keep {}, drop {}", subvol, other));
+        choices.push(format!(
+            "Nest {} inside {} at {}",
+            subvol,
+            other,
+            nested_mount(&conflict.other_mount, subvol)
+        ));
+    } else {
+        choices.push(format!("Drop {}", subvol));
+    }
+    choices.push(format!("Move {} to another mount point", subvol));
+    let choice_refs: Vec<&str> = choices.iter().map(String::as_str).collect();
+
+    let idx = select(
+        &format!("Resolve {}", subvol),
+        &choice_refs,
+        choices.len() - 1,
+    )?;
+    match (&conflict.other, idx) {
+        (_, 0) => config.remove_subvolume(subvol),
+        (Some(other), 1) => config.remove_subvolume(other),
+        (Some(_), 2) => {
+            config.set_subvolume_mount(subvol, &nested_mount(&conflict.other_mount, subvol));
+        }
+        _ => {
+            let mount = input(&format!("New mount point for {}", subvol), &conflict.mount)?;
+            if let Some(problem) = mount_point_problem(mount.trim()) {
+                bail!("{}", problem);
+            }
+            config.set_subvolume_mount(subvol, mount.trim());
+        }
+    }
+    info(&format!("Resolved: {}", choices[idx]));
+    Ok(())
+}
+
+/// Mount point for `subvol` nested inside `parent`, e.g. /home/alice/cache for @cache
+fn nested_mount(parent: &str, subvol: &str) -> String {
+    format!(
+        "{}/{}",
+        parent.trim_end_matches('/'),
+        subvol.trim_start_matches('@')
+    )
+}
+
+fn lookup<'a>(tree: &'a Value, key: &str) -> Option<&'a Value> {
+    key.split('.')
+        .try_fold(tree, |node, segment| node.as_table()?.get(segment))
+}
+
+/// Set `key` in `tree`, converting `raw` to the type of the current value
+fn set_dotted(tree: &mut Value, key: &str, raw: &str) -> Result<()> {
+    let segments: Vec<&str> = key.split('.').collect();
+    if segments.iter().any(|segment| segment.is_empty()) {
+        bail!("Invalid config key: '{}'", key);
+    }
+    let (last, parents) = segments.split_last().expect("split never yields nothing");
+
+    let mut node = tree;
+    for segment in parents {
+        let table = node
+            .as_table_mut()
+            .with_context(|| format!("'{}' is not a table in {}", segment, key))?;
+        node = table
+            .entry(segment.to_string())
+            .or_insert_with(|| Value::Table(Default::default()));
+    }
+
+    let table = node
+        .as_table_mut()
+        .with_context(|| format!("Cannot set {}: parent is not a table", key))?;
+    let value = parse_value(table.get(*last), raw)?;
+    table.insert(last.to_string(), value);
+
+    Ok(())
+}
+
+fn parse_value(existing: Option<&Value>, raw: &str) -> Result<Value> {
+    Ok(match existing {
+        Some(Value::String(_)) => Value::String(raw.to_string()),
+        Some(Value::Integer(_)) => Value::Integer(
+            raw.parse()
+                .with_context(|| format!("Expected an integer, got '{}'", raw))?,
+        ),
+        Some(Value::Float(_)) => Value::Float(
+            raw.parse()
+                .with_context(|| format!("Expected a number, got '{}'", raw))?,
+        ),
+        Some(Value::Boolean(_)) => Value::Boolean(
+            raw.parse()
+                .with_context(|| format!("Expected true or false, got '{}'", raw))?,
+        ),
+        // Arrays, tables and unset keys take a TOML literal; bare words stay strings
+        Some(_) => parse_literal(raw)?,
+        None => parse_literal(raw).unwrap_or_else(|_| Value::String(raw.to_string())),
+    })
+}
+
+fn parse_literal(raw: &str) -> Result<Value> {
+    let mut doc: toml::Table = toml::from_str(&format!("value = {}", raw))
+        .with_context(|| format!("Expected a TOML value, got '{}'", raw))?;
+    Ok(doc.remove("value").expect("parsed document has the key"))
+}
+
+fn display_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("\"{}\"", s),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn default_tree() -> Value {
+        Value::try_from(Config::default()).unwrap()
+    }
+
+    #[test]
+    fn set_dotted_keeps_string_type() {
+        let mut tree = default_tree();
+        set_dotted(&mut tree, "mount.options", "compress=zstd:1,noatime").unwrap();
+
+        let cfg: Config = tree.try_into().unwrap();
+        assert_eq!(
+            cfg.mount.options.as_deref(),
+            Some("compress=zstd:1,noatime")
+        );
+    }
+
+    #[test]
+    fn set_dotted_parses_arrays_and_new_keys() {
+        let mut tree = default_tree();
+        set_dotted(&mut tree, "btrbk.targets", r#"["ssh://backup/srv/btrbk"]"#).unwrap();
+        set_dotted(&mut tree, "wsl.distro_name", "Arch").unwrap();
+
+        let cfg: Config = tree.try_into().unwrap();
+        assert_eq!(cfg.btrbk.targets, vec!["ssh://backup/srv/btrbk"]);
+        assert_eq!(cfg.wsl.distro_name.as_deref(), Some("Arch"));
+    }
+
+    #[test]
+    fn set_dotted_rejects_type_mismatch_and_bad_keys() {
+        let mut tree = default_tree();
+        assert!(set_dotted(&mut tree, "subvolumes.exclude.paths", "[1, ").is_err());
+        assert!(set_dotted(&mut tree, "mount..options", "x").is_err());
+        assert!(set_dotted(&mut tree, "mount.base.inner", "x").is_err());
+    }
+
+    #[test]
+    fn resolve_mount_conflicts_fails_with_listing_in_yes_mode() {
+        let mut cfg = Config::default();
+        cfg.set_user("alice");
+        assert!(!resolve_mount_conflicts(&mut cfg, true).unwrap());
+
+        cfg.set_subvolume_mount("@opt", "/usr");
+        let err = resolve_mount_conflicts(&mut cfg, true).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("@usr and @opt (/usr) both mount at /usr"));
+    }
+
+    #[test]
+    fn subvolume_set_changes_lists_removed_and_added_per_class() {
+        let old = Config::default();
+        let mut new = old.clone();
+        new.remove_subvolume("@opt");
+        new.remove_subvolume("@var_tmp");
+        new.subvolumes.backup.insert(
+            "@srv".to_string(),
+            crate::config::BackupSubvol::Simple("/srv".to_string()),
+        );
+
+        let lines: Vec<String> = subvolume_set_changes(&old, &new)
+            .iter()
+            .map(|line| console::strip_ansi_codes(line).to_string())
+            .collect();
+        assert_eq!(
+            lines,
+            vec!["- backup: @opt", "+ backup: @srv", "- transfer: @var_tmp"]
+        );
+        assert!(subvolume_set_changes(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn nested_mount_strips_subvolume_prefix() {
+        assert_eq!(nested_mount("/home/alice/", "@cache"), "/home/alice/cache");
+    }
+
+    #[test]
+    fn unknown_keys_do_not_survive_roundtrip() {
+        let mut tree = default_tree();
+        set_dotted(&mut tree, "mount.optoins", "noatime").unwrap();
+
+        let cfg: Config = tree.try_into().unwrap();
+        let roundtrip = Value::try_from(&cfg).unwrap();
+        assert!(lookup(&roundtrip, "mount.optoins").is_none());
+        assert!(lookup(&roundtrip, "mount.base").is_some());
+    }
+}
@@ -0,0 +1,127 @@
+//! Collect a diagnostics bundle for bug reports
+//!
+//! Everything goes into one text file: the redacted config, state and recent
+//! journal of every managed unit, and the block device and Btrfs overview.
+//! Commands that fail are recorded in the bundle instead of aborting.
+
+use anyhow::{Context, Result};
+use console::style;
+use std::fs;
+
+use crate::config::Config;
+use crate::generators::systemd;
+use crate::utils::cli::read_unit_property;
+use crate::utils::prompt::{info, success};
+use crate::utils::shell::run_capture;
+
+const JOURNAL_LINES: &str = "50";
+const REDACTED: &str = "<redacted>";
+
+pub fn run(config: &Config, out: &str) -> Result<()> {
+    println!("{}", style("Collect Diagnostics").bold().cyan());
+    println!();
+
+    let units: Vec<String> = systemd::wanted_units(config)
+        .into_iter()
+        .map(|(unit, _)| unit)
+        .chain(["btrbk.service".to_string()])
+        .collect();
+
+    let mut bundle = String::new();
+    add_section(
+        &mut bundle,
+        "wslarc version",
+        env!("CARGO_PKG_VERSION").to_string(),
+    );
+    add_section(&mut bundle, "config (redacted)", redact_config(config)?);
+
+    add_command(&mut bundle, "systemctl", &["--failed", "--no-pager"]);
+    for unit in &units {
+        info(&format!("Collecting {}", unit));
+        add_command(&mut bundle, "systemctl", &["status", "--no-pager", unit]);
+        add_command(
+            &mut bundle,
+            "journalctl",
+            &["-u", unit, "-n", JOURNAL_LINES, "--no-pager"],
+        );
+    }
+
+    add_command(&mut bundle, "lsblk", &["-f"]);
+    add_command(&mut bundle, "btrfs", &["filesystem", "show"]);
+    add_command(
+        &mut bundle,
+        "btrfs",
+        &["filesystem", "usage", &config.mount.base],
+    );
+
+    fs::write(out, &bundle).with_context(|| format!("Failed to write {}", out))?;
+
+    println!();
+    success(&format!("Diagnostics written to {}", out));
+    println!("Review the file before attaching it to an issue.");
+
+    Ok(())
+}
+
+/// Print managed units that systemd reports as failed
+pub fn list_failed(config: &Config) -> Result<()> {
+    let failed: Vec<String> = systemd::wanted_units(config)
+        .into_iter()
+        .map(|(unit, _)| unit)
+        .chain(["btrbk.service".to_string()])
+        .filter(|unit| read_unit_property(unit, "ActiveState").as_deref() == Some("failed"))
+        .collect();
+
+    if failed.is_empty() {
+        success("No failed units");
+    }
+    for unit in failed {
+        println!("{}", unit);
+    }
+
+    Ok(())
+}
+
+fn add_section(bundle: &mut String, title: &str, body: String) {
+    bundle.push_str(&format!("===== {} =====\n{}\n\n", title, body.trim_end()));
+}
+
+fn add_command(bundle: &mut String, cmd: &str, args: &[&str]) {
+    let title = format!("{} {}", cmd, args.join(" "));
+    let body = run_capture(cmd, args).unwrap_or_else(|e| format!("(failed: {})", e));
+    add_section(bundle, &title, body);
+}
+
+/// Config as TOML with the user name, VHDX path and backup targets masked
+fn redact_config(config: &Config) -> Result<String> {
+    let mut redacted = config.clone();
+    redacted.vhdx.path = REDACTED.to_string();
+    redacted.btrbk.targets = vec![REDACTED.to_string(); redacted.btrbk.targets.len()];
+
+    let text = toml::to_string_pretty(&redacted).context("Failed to serialize config")?;
+    let user = config.get_user();
+    if user.is_empty() {
+        return Ok(text);
+    }
+    // The user name also appears in home mount paths
+    Ok(text.replace(&user, "<user>"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact_config_masks_user_path_and_targets() {
+        let mut cfg = Config::default();
+        cfg.set_user("alice");
+        cfg.vhdx.path = r"C:\Users\alice\btrfs.vhdx".to_string();
+        cfg.btrbk.targets = vec!["ssh://root@backup/srv/btrbk".to_string()];
+
+        let text = redact_config(&cfg).unwrap();
+        assert!(!text.contains("alice"));
+        assert!(!text.contains("ssh://"));
+        assert!(text.contains("/home/<user>"));
+        assert!(text.contains(REDACTED));
+    }
+}
@@ -0,0 +1,163 @@
+//! Check the environment wslarc depends on before anything fails halfway
+//!
+//! Only looks: external tools, wsl.exe and interop, and systemd as PID 1.
+//! Missing mandatory pieces make the command fail; optional ones are warned about.
+
+use anyhow::{bail, Result};
+use console::style;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::generators::btrbk;
+use crate::utils::cli::find_command;
+use crate::utils::prompt::{failure, section, success, warn};
+use crate::utils::wsl::{interop_available, WSL_EXE};
+
+/// (command, package, mandatory); btrbk comes from the config instead
+const TOOLS: &[(&str, &str, bool)] = &[
+    ("btrfs", "btrfs-progs", true),
+    ("mkfs.btrfs", "btrfs-progs", true),
+    ("blkid", "util-linux", true),
+    ("lsblk", "util-linux", true),
+    ("rsync", "rsync", true),
+    ("systemd-analyze", "systemd", false),
+];
+
+/// Result of one check, printed as one ✓/✗/⚠ line
+#[derive(Debug, PartialEq, Eq)]
+struct Check {
+    name: String,
+    detail: String,
+    ok: bool,
+    mandatory: bool,
+}
+
+impl Check {
+    /// A mandatory check, described by `ok_detail` or `fail_detail`
+    fn new(name: &str, ok: bool, ok_detail: &str, fail_detail: &str) -> Self {
+        Check {
+            name: name.to_string(),
+            detail: if ok { ok_detail } else { fail_detail }.to_string(),
+            ok,
+            mandatory: true,
+        }
+    }
+}
+
+pub fn run(config: &Config) -> Result<()> {
+    println!("{}", style("wslarc Doctor").bold().cyan());
+
+    section("Tools");
+    let btrbk = btrbk::binary(config);
+    let tools = tool_checks(&btrbk, find_command);
+    print_checks(&tools);
+
+    section("WSL");
+    let wsl = vec![
+        Check::new(
+            "wsl.exe",
+            Path::new(WSL_EXE).exists(),
+            WSL_EXE,
+            &format!("{} not found; is C: mounted under /mnt/c?", WSL_EXE),
+        ),
+        Check::new(
+            "interop",
+            interop_available(),
+            "enabled",
+            "disabled; set [interop] enabled=true in /etc/wsl.conf",
+        ),
+        Check::new(
+            "systemd",
+            systemd_is_pid1(),
+            "running as PID 1",
+            "not PID 1; set [boot] systemd=true in /etc/wsl.conf",
+        ),
+    ];
+    print_checks(&wsl);
+
+    println!();
+    let failed: Vec<&str> = tools
+        .iter()
+        .chain(&wsl)
+        .filter(|check| check.mandatory && !check.ok)
+        .map(|check| check.name.as_str())
+        .collect();
+    if !failed.is_empty() {
+        bail!("Missing or disabled: {}", failed.join(", "));
+    }
+    println!(
+        "{}",
+        style("Everything wslarc needs is in place").green().bold()
+    );
+    Ok(())
+}
+
+/// One check per tool; `btrbk` is the binary the config resolves to
+fn tool_checks(btrbk: &str, find: impl Fn(&str) -> Option<PathBuf>) -> Vec<Check> {
+    TOOLS
+        .iter()
+        .map(|&(command, package, mandatory)| (command, command, package, mandatory))
+        .chain([("btrbk", btrbk, "btrbk", true)])
+        .map(|(name, command, package, mandatory)| {
+            let found = find(command);
+            Check {
+                name: name.to_string(),
+                detail: match &found {
+                    Some(path) => path.display().to_string(),
+                    None => format!(
+                        "{} not found; install with: sudo pacman -S {}",
+                        command, package
+                    ),
+                },
+                ok: found.is_some(),
+                mandatory,
+            }
+        })
+        .collect()
+}
+
+fn print_checks(checks: &[Check]) {
+    for check in checks {
+        let line = format!("{:<16} {}", check.name, check.detail);
+        match (check.ok, check.mandatory) {
+            (true, _) => success(&line),
+            (false, true) => failure(&line),
+            (false, false) => warn(&format!("{} (optional)", line)),
+        }
+    }
+}
+
+/// WSL starts systemd as PID 1 only with [boot] systemd=true
+fn systemd_is_pid1() -> bool {
+    fs::read_to_string("/proc/1/comm")
+        .map(|comm| comm.trim() == "systemd")
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tool_checks_mark_missing_mandatory_and_optional_tools() {
+        let checks = tool_checks("/usr/local/bin/btrbk", |command| {
+            (command != "rsync" && command != "systemd-analyze")
+                .then(|| PathBuf::from("/usr/bin").join(command))
+        });
+
+        let rsync = checks.iter().find(|check| check.name == "rsync").unwrap();
+        assert!(!rsync.ok && rsync.mandatory);
+        assert!(rsync.detail.contains("pacman -S rsync"));
+
+        let analyze = checks
+            .iter()
+            .find(|check| check.name == "systemd-analyze")
+            .unwrap();
+        assert!(!analyze.ok && !analyze.mandatory);
+
+        let btrbk = checks.last().unwrap();
+        assert_eq!(btrbk.name, "btrbk");
+        assert_eq!(btrbk.detail, "/usr/local/bin/btrbk");
+    }
+}
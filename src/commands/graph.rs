@@ -0,0 +1,176 @@
+//! Show ordering dependencies between the units wslarc generates
+//!
+//! Edges come from the same derivation the unit generators use, so the graph
+//! matches what `wslarc mount` writes without reading any files.
+
+use anyhow::Result;
+use console::style;
+
+use crate::config::Config;
+use crate::generators::{btrbk, systemd};
+use crate::utils::prompt::section;
+
+#[derive(Debug, PartialEq)]
+struct Edge {
+    from: String,
+    to: String,
+    /// Directive(s) on `from` that reference `to`
+    kind: &'static str,
+}
+
+pub fn run(config: &Config, dot: bool) -> Result<()> {
+    let edges = build_edges(config);
+
+    if dot {
+        print!("{}", format_dot(&edges));
+        return Ok(());
+    }
+
+    println!("{}", style("Mount Dependency Graph").bold().cyan());
+    section("Edges (unit -> dependency)");
+    for line in format_text(&edges) {
+        println!("  {}", line);
+    }
+    println!();
+    println!(
+        "Render with: {}",
+        style("wslarc graph --dot | dot -Tsvg > wslarc.svg").cyan()
+    );
+
+    Ok(())
+}
+
+fn build_edges(config: &Config) -> Vec<Edge> {
+    let mut edges = Vec::new();
+
+    let mounts = config
+        .sorted_backup()
+        .into_iter()
+        .map(|(_, backup)| backup.mount().to_string())
+        .chain(
+            config
+                .sorted_transfer()
+                .into_iter()
+                .map(|(_, transfer)| transfer.mount.clone()),
+        );
+
+    for mount_point in mounts {
+        let unit = systemd::mount_unit_filename(&mount_point);
+        let dependencies = systemd::subvol_mount_dependencies(config, &mount_point);
+        let kind = if config.mount.requires_mounts_for {
+            "RequiresMountsFor"
+        } else {
+            "Requires+After"
+        };
+        for required in dependencies.requires {
+            edges.push(Edge {
+                from: unit.clone(),
+                to: required,
+                kind,
+            });
+        }
+        // Before= is the reverse ordering: the other unit waits for this mount
+        for later in dependencies.before {
+            edges.push(Edge {
+                from: later,
+                to: unit.clone(),
+                kind: "After (via Before)",
+            });
+        }
+    }
+
+    edges.push(Edge {
+        from: "btrbk.service".to_string(),
+        to: btrbk::service_base_mount_unit(config),
+        kind: "Requires+After",
+    });
+    edges.push(Edge {
+        from: "btrbk.timer".to_string(),
+        to: "btrbk.service".to_string(),
+        kind: "Triggers",
+    });
+
+    edges
+}
+
+fn format_text(edges: &[Edge]) -> Vec<String> {
+    edges
+        .iter()
+        .map(|edge| format!("{} -> {} [{}]", edge.from, edge.to, edge.kind))
+        .collect()
+}
+
+fn format_dot(edges: &[Edge]) -> String {
+    let mut lines = vec!["digraph wslarc {".to_string(), "  rankdir=LR;".to_string()];
+    for edge in edges {
+        lines.push(format!(
+            "  \"{}\" -> \"{}\" [label=\"{}\"];",
+            edge.from, edge.to, edge.kind
+        ));
+    }
+    lines.push("}".to_string());
+    lines.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config() -> Config {
+        let mut cfg = Config::default();
+        cfg.set_user("alice");
+        cfg.subvolumes.transfer.insert(
+            "@containers_user".to_string(),
+            crate::config::TransferSubvol {
+                mount: "/home/alice/.local/share/containers".to_string(),
+                nodatacow: true,
+                options: None,
+                options_append: None,
+            },
+        );
+        cfg
+    }
+
+    #[test]
+    fn build_edges_includes_nested_home_and_user_service() {
+        let edges = build_edges(&test_config());
+        let base = systemd::mount_unit_filename("/mnt/btrfs");
+        let home = systemd::mount_unit_filename("/home/alice");
+        let nested = systemd::mount_unit_filename("/home/alice/.local/share/containers");
+
+        assert!(edges.contains(&Edge {
+            from: nested.clone(),
+            to: home.clone(),
+            kind: "Requires+After",
+        }));
+        assert!(edges.contains(&Edge {
+            from: nested,
+            to: base.clone(),
+            kind: "Requires+After",
+        }));
+        assert!(edges.contains(&Edge {
+            from: "user@.service".to_string(),
+            to: home,
+            kind: "After (via Before)",
+        }));
+        assert!(edges.contains(&Edge {
+            from: "btrbk.service".to_string(),
+            to: base,
+            kind: "Requires+After",
+        }));
+    }
+
+    #[test]
+    fn format_dot_quotes_unit_names() {
+        let edges = vec![Edge {
+            from: "usr.mount".to_string(),
+            to: "mnt-btrfs.mount".to_string(),
+            kind: "Requires+After",
+        }];
+
+        assert_eq!(
+            format_dot(&edges),
+            "digraph wslarc {\n  rankdir=LR;\n  \"usr.mount\" -> \"mnt-btrfs.mount\" [label=\"Requires+After\"];\n}\n"
+        );
+    }
+}
@@ -29,7 +29,8 @@ pub fn run(config: &Config, dry_run: bool) -> Result<()> {
     Ok(())
 }
 
-fn ensure_mounted(mount_point: &str, dry_run: bool) -> Result<()> {
+/// Mount the ext4 root at `mount_point` unless it already is
+pub fn ensure_mounted(mount_point: &str, dry_run: bool) -> Result<()> {
     if is_mountpoint(mount_point) {
         info(&format!("{} already mounted", mount_point));
         return Ok(());
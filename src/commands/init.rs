@@ -1,89 +1,409 @@
 use anyhow::{bail, Context, Result};
 use console::style;
+use log::debug;
 use std::fs;
+use std::os::unix::fs::MetadataExt;
 use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::config::Config;
+use crate::commands::config::resolve_mount_conflicts;
+use crate::config::{parse_size, Config, DeviceDetect, SubvolumeLayout, VhdxConfig};
 use crate::utils::cli::{
-    ensure_dependencies, find_btrfs_device_by_label, is_mountpoint, list_block_device_names,
-    read_block_device, Dependency,
+    btrfs_free_bytes, ensure_dependencies, find_btrfs_device_by_label, find_btrfs_devices_by_label,
+    format_bytes, has_nocow_attr, is_executable, is_mountpoint, list_block_device_names,
+    list_block_devices, list_subvolumes, read_block_device, shadowed_entry_count, user_ids,
+    BlockDevice, Dependency,
 };
-use crate::utils::prompt::{self, confirm_or_yes, info, input, step, success, warn};
-use crate::utils::shell::{run as shell_run, run_or_dry};
+use crate::utils::prompt::{
+    self, confirm_destructive, confirm_or_yes, info, input, step, success, warn,
+};
+use crate::utils::shell::{run_unchecked, CommandRunner};
+use crate::utils::wsl::{ensure_interop, mount_bare_vhd, to_windows_path};
 
 const CONFIG_PATH: &str = "/etc/wslarc/config.toml";
+/// Answers collected for a run that has not reached step 6 yet, for --resume
+const PARTIAL_CONFIG_PATH: &str = "/etc/wslarc/config.toml.partial";
+const SETUP_MOUNT: &str = "/mnt/btrfs-setup";
+/// Login shell offered when the configured one is not installed
+const FALLBACK_SHELL: &str = "/bin/bash";
+/// How often lsblk is polled while waiting for the attached VHDX
+const ATTACH_POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// Titles of the init steps, in order
+const STEPS: [&str; 7] = [
+    "Ensure user exists",
+    "Mount VHDX to WSL",
+    "Format as Btrfs",
+    "Get filesystem UUID",
+    "Create subvolumes",
+    "Save configuration",
+    "Mount base volume",
+];
+
+pub struct InitOptions {
+    /// Only show what would be done
+    pub dry_run: bool,
+    /// In a dry run, attach the VHDX and inspect the real device
+    pub probe_device: bool,
+    /// Adopt an existing disk and subvolumes without formatting
+    pub reuse: bool,
+    /// Only compare the disk against the config
+    pub format_check_only: bool,
+    /// TOML file whose [subvolumes] section replaces the configured layout
+    pub layout: Option<String>,
+    /// Treat every rsync failure while seeding subvolumes as a warning
+    pub continue_on_copy_error: bool,
+    /// Login shell for created users (sets user.shell)
+    pub user_shell: Option<String>,
+    /// Allow erasing another filesystem or using a Btrfs disk with another label
+    pub force_format: bool,
+    /// How long to wait for the attached VHDX to show up in lsblk
+    pub attach_timeout: Duration,
+    /// Skip the steps an interrupted init already finished
+    pub resume: bool,
+}
+
+pub fn run(
+    config: &Config,
+    yes: bool,
+    options: &InitOptions,
+    runner: &dyn CommandRunner,
+) -> Result<()> {
+    let dry_run = options.dry_run;
+    let reuse = options.reuse;
+    let format_check_only = options.format_check_only;
+    let layout = options.layout.as_deref();
 
-pub fn run(config: &Config, yes: bool, dry_run: bool) -> Result<()> {
     println!("{}", style("WSL Btrfs Initialization").bold().cyan());
 
-    // Check if already initialized
-    if Path::new(CONFIG_PATH).exists() && config.uuid.is_some() {
+    let mut base = config.clone();
+    if let Some(layout) = layout {
+        base.apply_layout(SubvolumeLayout::load(layout)?);
+        info(&format!("Using subvolume layout from {}", layout));
+    }
+
+    // Check if already initialized (check-only never writes the config)
+    if !format_check_only
+        && !options.resume
+        && Path::new(CONFIG_PATH).exists()
+        && config.uuid.is_some()
+    {
         warn("Configuration already exists with UUID. Re-running will overwrite.");
         if !confirm_or_yes("Continue anyway?", false, yes)? {
             return Ok(());
         }
     }
 
-    // Collect configuration (interactive or from file)
-    let mut cfg = if yes {
-        config.clone()
+    // Collect configuration (interactive or from file); a resumed run keeps
+    // the answers the interrupted one saved, if it got that far
+    let saved_answers = if options.resume {
+        resume_config(config, PARTIAL_CONFIG_PATH)?
     } else {
-        collect_config(config)?
+        None
     };
+    let mut cfg = match saved_answers {
+        Some(saved) => {
+            info("Resuming with the answers of the interrupted run");
+            saved
+        }
+        None if yes => base,
+        None => collect_config(&base, layout.is_some())?,
+    };
+    if cfg.user.name.is_empty() {
+        bail!("User is required. Set it in config file or run without --yes for interactive mode.");
+    }
+    // Paths from a layout file still contain $USER until the user is known
+    cfg.expand_variables();
 
-    // Validate required fields
+    // Fixes are saved with the rest of the config
+    resolve_mount_conflicts(&mut cfg, yes)?;
+    if let Some(shell) = &options.user_shell {
+        cfg.user.shell = Some(shell.clone());
+    }
+    check_login_shell(&mut cfg, yes)?;
+
+    // Validate required fields; answers typed in above are checked like a loaded config
+    cfg.validate()?;
     if cfg.vhdx.path.is_empty() {
         bail!("VHDX path is required. Set it in config file or run without --yes for interactive mode.");
     }
-    if cfg.user.name.is_empty() {
-        bail!("User is required. Set it in config file or run without --yes for interactive mode.");
+
+    if format_check_only {
+        return check_existing(runner, &cfg, options.attach_timeout);
+    }
+    if !options.resume && Path::new(CONFIG_PATH).exists() && config.uuid.is_some() {
+        show_config_changes(&cfg, reuse)?;
+    }
+    if reuse {
+        return adopt_existing(runner, cfg, yes, dry_run, options.attach_timeout);
     }
 
     check_runtime_dependencies(&cfg)?;
 
+    let total_steps = STEPS.len() as u32;
+    let (resume_from, resumed_device) = if options.resume {
+        resume_point(
+            &find_btrfs_devices_by_label(&cfg.vhdx.label)?,
+            cfg.uuid.as_deref(),
+            is_mountpoint(&cfg.mount.base),
+        )?
+    } else {
+        (1, None)
+    };
+    if resume_from > total_steps {
+        success("Every init step is already complete; nothing to resume");
+        return Ok(());
+    }
+
     // Show summary
     show_summary(&cfg);
+    for (num, title) in (1..resume_from).zip(STEPS) {
+        info(&format!("Skipping step {} ({}): already done", num, title));
+    }
 
     // Confirm before proceeding
-    if !confirm_or_yes("Proceed with initialization?", true, yes)? {
+    if !confirm_destructive("Proceed with initialization?", yes)? {
         println!("Aborted.");
         return Ok(());
     }
 
-    let total_steps = 7;
+    // Keep the answers until step 6 saves them, so --resume can pick them up
+    if resume_from == 1 && !dry_run {
+        let mut answers = cfg.clone();
+        answers.uuid = None;
+        answers.save(PARTIAL_CONFIG_PATH)?;
+    }
+
+    let title = |num: u32| STEPS[num as usize - 1];
+    let mut device = resumed_device.unwrap_or_default();
+    let mut keeps_filesystem = false;
+
+    if resume_from <= 3 {
+        step(1, total_steps, title(1));
+        ensure_user(runner, &cfg, dry_run)?;
 
-    step(1, total_steps, "Ensure user exists");
-    ensure_user(&cfg, dry_run)?;
+        // Attaching is not destructive, so a probing dry run does it for real
+        let probe = dry_run && options.probe_device;
+        step(2, total_steps, title(2));
+        device = mount_vhdx(&cfg, dry_run && !probe, options.attach_timeout)?;
+        info(&format!("Device: {}", device));
+
+        step(3, total_steps, title(3));
+        keeps_filesystem = if probe {
+            preview_format(&cfg, &device, options.force_format)?
+        } else {
+            format_btrfs(
+                runner,
+                &mut cfg,
+                &device,
+                dry_run,
+                yes,
+                options.force_format,
+            )?;
+            false
+        };
+    }
 
-    step(2, total_steps, "Mount VHDX to WSL");
-    let device = mount_vhdx(&cfg, dry_run)?;
+    if resume_from <= 6 {
+        // A resumed run reads the UUID of the device it found, even when dry
+        step(4, total_steps, title(4));
+        let uuid = get_uuid(
+            runner,
+            &device,
+            dry_run && !keeps_filesystem && resume_from == 1,
+        )?;
+        cfg.uuid = Some(uuid.clone());
+        success(&format!("UUID: {}", uuid));
+
+        step(5, total_steps, title(5));
+        create_subvolumes(
+            runner,
+            &cfg,
+            &device,
+            dry_run,
+            options.continue_on_copy_error,
+        )?;
+
+        step(6, total_steps, title(6));
+        if !dry_run {
+            cfg.save(CONFIG_PATH)?;
+            success(&format!("Saved to {}", CONFIG_PATH));
+            if let Err(e) = fs::remove_file(PARTIAL_CONFIG_PATH) {
+                debug!("Could not remove {}: {}", PARTIAL_CONFIG_PATH, e);
+            }
+        } else {
+            info(&format!("[dry-run] Would save to {}", CONFIG_PATH));
+        }
+    }
+
+    step(7, total_steps, title(7));
+    mount_base(runner, &cfg, &device, dry_run)?;
+
+    // Done
+    println!();
+    println!("{}", style("Initialization complete!").green().bold());
+    println!();
+    println!(
+        "Next step: {} to set up systemd mounts",
+        style("wslarc mount").cyan()
+    );
+
+    Ok(())
+}
+
+/// Answers to resume with: those an interrupted run saved to `partial_path`
+/// before step 1, else the saved config once step 6 wrote it with a UUID.
+/// None when neither exists and the answers have to be collected again
+fn resume_config(config: &Config, partial_path: &str) -> Result<Option<Config>> {
+    if Path::new(partial_path).exists() {
+        let partial = Config::load(partial_path)
+            .with_context(|| format!("Failed to load the saved answers: {}", partial_path))?;
+        return Ok(Some(partial));
+    }
+    Ok(config.uuid.is_some().then(|| config.clone()))
+}
+
+/// First step an interrupted init still has to run, with the device it
+/// already attached and formatted; past the last step when nothing is left
+///
+/// The config is saved only after the subvolumes were created, so a saved
+/// UUID that matches an attached device means steps 1 to 6 finished.
+fn resume_point(
+    devices: &[BlockDevice],
+    saved_uuid: Option<&str>,
+    base_mounted: bool,
+) -> Result<(u32, Option<String>)> {
+    let saved: Vec<&BlockDevice> = devices
+        .iter()
+        .filter(|device| saved_uuid.is_some() && device.uuid.as_deref() == saved_uuid)
+        .collect();
+    // Without the saved disk, any other labeled disk could be the one an
+    // interrupted run formatted; clones even share the saved UUID
+    let found_saved = !saved.is_empty();
+    let candidates = if found_saved {
+        saved
+    } else {
+        devices.iter().collect()
+    };
+    let device = match candidates.as_slice() {
+        [] => return Ok((1, None)),
+        [device] => device,
+        _ => bail!(
+            "Cannot tell which disk to resume with: {}. Detach the others and retry.",
+            candidates
+                .iter()
+                .map(|device| format!("/dev/{}", device.name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let path = Some(format!("/dev/{}", device.name));
+
+    Ok(match (found_saved, base_mounted) {
+        (false, _) => (4, path),
+        (true, false) => (7, path),
+        (true, true) => (STEPS.len() as u32 + 1, path),
+    })
+}
+
+/// Show what overwriting the saved config will change
+fn show_config_changes(cfg: &Config, reuse: bool) -> Result<()> {
+    let existing = Config::load(CONFIG_PATH)?;
+    let changes = existing.diff(cfg)?;
+
+    prompt::section("Changes to existing configuration");
+    if changes.is_empty() {
+        info("None");
+    }
+    for change in &changes {
+        prompt::change(&change.key, change.old.as_deref(), change.new.as_deref());
+    }
+    if !reuse {
+        info("Formatting gives the disk a new filesystem UUID");
+    }
+    Ok(())
+}
+
+/// Adopt an existing Btrfs disk and subvolume layout without formatting or creating anything
+fn adopt_existing(
+    runner: &dyn CommandRunner,
+    mut cfg: Config,
+    yes: bool,
+    dry_run: bool,
+    attach_timeout: Duration,
+) -> Result<()> {
+    ensure_dependencies(&[Dependency::new("btrfs-progs", &["btrfs"])])?;
+
+    show_summary(&cfg);
+    info("Reuse mode: the disk will not be formatted and no subvolumes will be created");
+
+    if !confirm_destructive("Adopt the existing Btrfs disk?", yes)? {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    let total_steps = 5;
+
+    step(1, total_steps, "Mount VHDX to WSL");
+    let device = mount_vhdx(&cfg, dry_run, attach_timeout)?;
     info(&format!("Device: {}", device));
 
-    step(3, total_steps, "Format as Btrfs");
-    format_btrfs(&mut cfg, &device, dry_run, yes)?;
+    step(2, total_steps, "Check existing filesystem");
+    if dry_run {
+        info("[dry-run] Would check that the device is Btrfs");
+    } else {
+        let block_device = read_block_device(&device)?;
+        let fstype = block_device.as_ref().and_then(|d| d.fstype.as_deref());
+        if fstype != Some("btrfs") {
+            bail!(
+                "{} is not a Btrfs filesystem (found: {}). Run init without --reuse to format it.",
+                device,
+                fstype.unwrap_or("none")
+            );
+        }
+        match block_device.and_then(|d| d.label) {
+            Some(label) if label != cfg.vhdx.label => {
+                warn(&format!(
+                    "Using on-disk label '{}' instead of '{}'",
+                    label, cfg.vhdx.label
+                ));
+                cfg.vhdx.label = label;
+            }
+            Some(label) => success(&format!("Btrfs with label '{}'", label)),
+            None => warn("Device label is empty; attach by label may fail until you set a label."),
+        }
+    }
 
-    step(4, total_steps, "Get filesystem UUID");
-    let uuid = get_uuid(&device, dry_run)?;
+    step(3, total_steps, "Get filesystem UUID");
+    let uuid = get_uuid(runner, &device, dry_run)?;
     cfg.uuid = Some(uuid.clone());
     success(&format!("UUID: {}", uuid));
 
-    step(5, total_steps, "Create subvolumes");
-    create_subvolumes(&cfg, &device, dry_run)?;
+    step(4, total_steps, "Inspect subvolumes");
+    if dry_run {
+        info(&format!(
+            "[dry-run] Would mount {} to {} (subvolid=5) and list subvolumes",
+            device, SETUP_MOUNT
+        ));
+        info(&format!("[dry-run] Would save to {}", CONFIG_PATH));
+    } else {
+        fs::create_dir_all(SETUP_MOUNT)?;
+        runner.run("mount", &["-o", "subvolid=5", &device, SETUP_MOUNT])?;
+        let present = list_subvolumes(SETUP_MOUNT);
+        runner.run("umount", &[SETUP_MOUNT])?;
+        fs::remove_dir(SETUP_MOUNT)?;
+
+        report_adoption(&classify_subvolumes(&cfg, &present?));
 
-    step(6, total_steps, "Save configuration");
-    if !dry_run {
         cfg.save(CONFIG_PATH)?;
         success(&format!("Saved to {}", CONFIG_PATH));
-    } else {
-        info(&format!("[dry-run] Would save to {}", CONFIG_PATH));
     }
 
-    step(7, total_steps, "Mount base volume");
-    mount_base(&cfg, &device, dry_run)?;
+    step(5, total_steps, "Mount base volume");
+    mount_base(runner, &cfg, &device, dry_run)?;
 
-    // Done
     println!();
-    println!("{}", style("Initialization complete!").green().bold());
+    println!("{}", style("Existing disk adopted!").green().bold());
     println!();
     println!(
         "Next step: {} to set up systemd mounts",
@@ -93,6 +413,178 @@ pub fn run(config: &Config, yes: bool, dry_run: bool) -> Result<()> {
     Ok(())
 }
 
+/// Validate an existing disk against the config without formatting or creating anything
+fn check_existing(
+    runner: &dyn CommandRunner,
+    cfg: &Config,
+    attach_timeout: Duration,
+) -> Result<()> {
+    ensure_dependencies(&[Dependency::new("btrfs-progs", &["btrfs"])])?;
+
+    show_summary(cfg);
+    info("Check-only mode: the disk will not be formatted and nothing will be created");
+
+    let total_steps = 3;
+
+    step(1, total_steps, "Mount VHDX to WSL");
+    let device = mount_vhdx(cfg, false, attach_timeout)?;
+    info(&format!("Device: {}", device));
+
+    step(2, total_steps, "Check filesystem and label");
+    let check = check_device(read_block_device(&device)?.as_ref(), &cfg.vhdx.label);
+    match &check {
+        DeviceCheck::Matching => success(&format!("Btrfs with label '{}'", cfg.vhdx.label)),
+        DeviceCheck::Blank => warn("Device has no filesystem; init would format it"),
+        other => warn(&other.describe(&cfg.vhdx.label)),
+    }
+    if !matches!(check, DeviceCheck::Matching | DeviceCheck::LabelMismatch(_)) {
+        bail!("{} does not match the config", device);
+    }
+
+    step(3, total_steps, "Inspect subvolumes and free space");
+    // Reuse the live mount if the base volume is already up
+    let inspect_mount = if is_mountpoint(&cfg.mount.base) {
+        cfg.mount.base.clone()
+    } else {
+        fs::create_dir_all(SETUP_MOUNT)?;
+        runner.run("mount", &["-o", "ro,subvolid=5", &device, SETUP_MOUNT])?;
+        SETUP_MOUNT.to_string()
+    };
+    let present = list_subvolumes(&inspect_mount);
+    let free = btrfs_free_bytes(&inspect_mount);
+    if inspect_mount == SETUP_MOUNT {
+        runner.run("umount", &[SETUP_MOUNT])?;
+        fs::remove_dir(SETUP_MOUNT)?;
+    }
+
+    let adoption = classify_subvolumes(cfg, &present?);
+    report_adoption(&adoption);
+    match free {
+        Ok(bytes) => info(&format!("Free space (estimated): {}", format_bytes(bytes))),
+        Err(e) => warn(&format!("Could not read free space: {}", e)),
+    }
+
+    println!();
+    if matches!(check, DeviceCheck::LabelMismatch(_)) || !adoption.missing.is_empty() {
+        bail!("Disk check found differences from the config");
+    }
+    println!("{}", style("Disk check complete!").green().bold());
+
+    Ok(())
+}
+
+/// What `format_btrfs` finds on a device before deciding to run mkfs
+#[derive(Debug, PartialEq, Eq)]
+enum DeviceCheck {
+    /// No filesystem; safe to format
+    Blank,
+    /// Btrfs with the configured label
+    Matching,
+    /// Btrfs with a different label
+    LabelMismatch(String),
+    /// Btrfs without a label
+    Unlabeled,
+    /// Some other filesystem
+    OtherFilesystem(String),
+}
+
+impl DeviceCheck {
+    fn describe(&self, expected_label: &str) -> String {
+        match self {
+            DeviceCheck::Blank => "Device has no filesystem".to_string(),
+            DeviceCheck::Matching => format!("Btrfs with label '{}'", expected_label),
+            DeviceCheck::LabelMismatch(label) => format!(
+                "Device is Btrfs with label '{}' (expected '{}')",
+                label, expected_label
+            ),
+            DeviceCheck::Unlabeled => format!(
+                "Device is Btrfs but has no label (expected '{}')",
+                expected_label
+            ),
+            DeviceCheck::OtherFilesystem(fstype) => {
+                format!("Device has a {} filesystem, not Btrfs", fstype)
+            }
+        }
+    }
+}
+
+fn check_device(block_device: Option<&BlockDevice>, expected_label: &str) -> DeviceCheck {
+    let Some(block_device) = block_device else {
+        return DeviceCheck::Blank;
+    };
+    match block_device.fstype.as_deref() {
+        None | Some("") => DeviceCheck::Blank,
+        Some("btrfs") => match block_device.label.as_deref() {
+            None | Some("") => DeviceCheck::Unlabeled,
+            Some(label) if label == expected_label => DeviceCheck::Matching,
+            Some(label) => DeviceCheck::LabelMismatch(label.to_string()),
+        },
+        Some(other) => DeviceCheck::OtherFilesystem(other.to_string()),
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct SubvolumeAdoption {
+    /// (subvolume, class) pairs present on disk and in config
+    matched: Vec<(String, &'static str)>,
+    /// Configured but not present on disk
+    missing: Vec<String>,
+    /// Present on disk but not managed by the config
+    unexpected: Vec<String>,
+}
+
+/// Map on-disk subvolumes to the configured classes
+fn classify_subvolumes(cfg: &Config, present: &[String]) -> SubvolumeAdoption {
+    let mut expected: Vec<(String, &'static str)> = Vec::new();
+    for subvol in cfg.subvolumes.backup.keys() {
+        expected.push((subvol.clone(), "backup"));
+    }
+    expected.push(("@etc".to_string(), "snapshot-only"));
+    for (_, home) in cfg.home_subvolumes() {
+        for path in &cfg.subvolumes.exclude.paths {
+            expected.push((format!("{}/{}", home, path), "exclude"));
+        }
+    }
+    for subvol in cfg.subvolumes.transfer.keys() {
+        expected.push((subvol.clone(), "transfer"));
+    }
+    expected.push((cfg.btrbk.snapshot_dir.clone(), "snapshots"));
+    expected.sort();
+
+    let snapshot_prefix = format!("{}/", cfg.btrbk.snapshot_dir);
+    let mut adoption = SubvolumeAdoption::default();
+    for (name, class) in expected {
+        if present.contains(&name) {
+            adoption.matched.push((name, class));
+        } else {
+            adoption.missing.push(name);
+        }
+    }
+    for name in present {
+        let known = adoption.matched.iter().any(|(matched, _)| matched == name);
+        if !known && !name.starts_with(&snapshot_prefix) {
+            adoption.unexpected.push(name.clone());
+        }
+    }
+    adoption.unexpected.sort();
+    adoption
+}
+
+fn report_adoption(adoption: &SubvolumeAdoption) {
+    for (name, class) in &adoption.matched {
+        success(&format!("{} [{}]", name, class));
+    }
+    for name in &adoption.missing {
+        warn(&format!("{} is configured but missing on disk", name));
+    }
+    for name in &adoption.unexpected {
+        warn(&format!("{} exists on disk but is not in the config", name));
+    }
+    if !adoption.missing.is_empty() {
+        info("Create missing subvolumes by running 'wslarc init' without --reuse");
+    }
+}
+
 fn check_runtime_dependencies(config: &Config) -> Result<()> {
     let mut dependencies = vec![
         Dependency::new("btrfs-progs", &["mkfs.btrfs", "btrfs"]),
@@ -112,23 +604,35 @@ fn check_runtime_dependencies(config: &Config) -> Result<()> {
 }
 
 /// Interactive configuration collection
-fn collect_config(base: &Config) -> Result<Config> {
+fn collect_config(base: &Config, from_layout: bool) -> Result<Config> {
     let mut cfg = base.clone();
 
     prompt::section("User Configuration");
     let username = input("Target Linux username", &cfg.user.name)?;
+    let extra = input(
+        "Additional users with their own home (space-separated)",
+        &cfg.user.extra.join(" "),
+    )?;
+    cfg.user.extra = extra.split_whitespace().map(str::to_string).collect();
 
     // Set user and update paths
     cfg.set_user(&username);
 
     prompt::section("VHDX Configuration");
-    cfg.vhdx.path = input("VHDX path (Windows, full path)", &cfg.vhdx.path)?;
+    cfg.vhdx.path = input(
+        "VHDX path (Windows or /mnt/<drive>, full path)",
+        &cfg.vhdx.path,
+    )?;
     cfg.vhdx.label = input("Btrfs label", &cfg.vhdx.label)?;
 
     prompt::section("Mount Configuration");
     cfg.mount.base = input("Mount base", &cfg.mount.base)?;
 
     prompt::section("Subvolumes");
+    if from_layout {
+        println!("  Using the subvolume layout file (see summary below)");
+        return Ok(cfg);
+    }
     println!("  Using default subvolume configuration:");
     println!("  A-class (backup): @usr, @opt, @home, @var_lib_pacman");
     println!("  Snapshot-only: @etc (not mounted, for btrbk backup)");
@@ -144,7 +648,7 @@ fn show_summary(cfg: &Config) {
     prompt::kv("VHDX", &cfg.vhdx.path);
     prompt::kv("Label", &cfg.vhdx.label);
     prompt::kv("Mount base", &cfg.mount.base);
-    prompt::kv("User", &cfg.get_user());
+    prompt::kv("User", &cfg.users().join(", "));
 
     let backup_count = cfg.subvolumes.backup.len();
     let exclude_count = cfg.subvolumes.exclude.paths.len();
@@ -159,35 +663,67 @@ fn show_summary(cfg: &Config) {
     if !cfg.user.options.is_empty() {
         prompt::kv("User options", &cfg.user.options);
     }
-}
+    if let Some(shell) = cfg.user.login_shell() {
+        prompt::kv("Login shell", &shell);
+    }
 
-/// Ensure target user exists, create if not
-fn ensure_user(cfg: &Config, dry_run: bool) -> Result<()> {
-    let user = cfg.get_user();
+    if cfg.mount.quotas {
+        prompt::kv("Quotas", "enabled");
+        warn("Btrfs quotas slow down snapshot deletion and balance as snapshots accumulate");
+    }
 
-    // Check if user already exists
-    let user_exists = shell_run("id", &[&user]).is_ok();
+    for warning in cfg.warnings() {
+        warn(&warning);
+    }
+}
 
-    if user_exists {
-        success(&format!("User '{}' already exists", user));
+/// useradd accepts a shell that is not installed, leaving a user that cannot
+/// log in; catch that before any user is created
+fn check_login_shell(cfg: &mut Config, yes: bool) -> Result<()> {
+    let Some(shell) = cfg.user.login_shell() else {
+        return Ok(());
+    };
+    if is_executable(&shell) {
         return Ok(());
     }
 
-    // Create user with configured options
-    info(&format!("Creating user '{}'...", user));
+    warn(&format!("Login shell {} is not installed", shell));
+    if shell == FALLBACK_SHELL
+        || !confirm_or_yes(&format!("Use {} instead?", FALLBACK_SHELL), true, yes)?
+    {
+        bail!(
+            "Login shell {} does not exist; install it or change user.shell",
+            shell
+        );
+    }
+    cfg.user.shell = Some(FALLBACK_SHELL.to_string());
+    info(&format!("Using {} as the login shell", FALLBACK_SHELL));
+    Ok(())
+}
+
+/// Ensure every configured user exists, create if not
+fn ensure_user(runner: &dyn CommandRunner, cfg: &Config, dry_run: bool) -> Result<()> {
+    for user in cfg.users() {
+        // Check if user already exists
+        if runner.run("id", &[&user]).is_ok() {
+            success(&format!("User '{}' already exists", user));
+            continue;
+        }
 
-    // Parse options string into args
-    let mut args: Vec<&str> = cfg.user.options.split_whitespace().collect();
-    args.push(&user);
+        // Create user with configured options
+        info(&format!("Creating user '{}'...", user));
 
-    run_or_dry("useradd", &args, dry_run)?;
+        let args = cfg.user.useradd_args(&user);
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        runner.run_or_dry("useradd", &args, dry_run)?;
 
-    success(&format!("User '{}' created", user));
+        success(&format!("User '{}' created", user));
+    }
     Ok(())
 }
 
 /// Mount VHDX to WSL and return device path
-fn mount_vhdx(cfg: &Config, dry_run: bool) -> Result<String> {
+fn mount_vhdx(cfg: &Config, dry_run: bool, timeout: Duration) -> Result<String> {
     if dry_run {
         info("[dry-run] Would mount VHDX");
         return Ok("<device>".to_string());
@@ -202,99 +738,228 @@ fn mount_vhdx(cfg: &Config, dry_run: bool) -> Result<String> {
         return Ok(device);
     }
 
+    ensure_interop()?;
+
     // Get current block devices
     let before_devs = list_block_device_names()?;
 
     // Mount VHDX
-    // Normalize path: wsl.exe accepts both / and \, but we standardize to \
-    let vhdx_path = cfg.vhdx.path.replace('/', "\\");
-    shell_run(
-        "/mnt/c/Windows/System32/wsl.exe",
-        &["--mount", "--vhd", &vhdx_path, "--bare"],
-    )
-    .context("Failed to mount VHDX. Make sure the VHDX exists and WSL interop is enabled.")?;
-
-    // Find the new device
-    std::thread::sleep(std::time::Duration::from_millis(500));
-    let after_devs = list_block_device_names()?;
-
-    let new_dev = after_devs
-        .iter()
-        .find(|device| !before_devs.contains(device))
-        .ok_or_else(|| anyhow::anyhow!("Could not find new device after mounting VHDX"))?;
+    // wsl.exe wants a Windows path; /mnt/<drive>/... input is translated
+    let vhdx_path = to_windows_path(&cfg.vhdx.path);
+    mount_bare_vhd(&vhdx_path).context("Failed to mount VHDX. Make sure the VHDX exists.")?;
+
+    // Find the new device; slow hosts take a while to enumerate it
+    let after_devs = wait_for_new_device(&before_devs, timeout, list_block_devices)?;
+    let new_dev = select_new_device(&cfg.vhdx, &before_devs, &after_devs)?;
 
     let device = format!("/dev/{}", new_dev);
     success(&format!("Mounted as {}", device));
     Ok(device)
 }
 
+/// Poll `list` until a device not in `before` shows up, or `timeout` passes;
+/// returns the last listing either way so detection can report what it saw
+fn wait_for_new_device(
+    before: &[String],
+    timeout: Duration,
+    mut list: impl FnMut() -> Result<Vec<BlockDevice>>,
+) -> Result<Vec<BlockDevice>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let after = list()?;
+        if after.iter().any(|device| !before.contains(&device.name)) {
+            return Ok(after);
+        }
+        if Instant::now() >= deadline {
+            debug!("No new block device after {}s", timeout.as_secs_f32());
+            return Ok(after);
+        }
+        thread::sleep(ATTACH_POLL_INTERVAL);
+    }
+}
+
+/// Pick the freshly attached device according to `vhdx.detect`
+fn select_new_device(
+    vhdx: &VhdxConfig,
+    before: &[String],
+    after: &[BlockDevice],
+) -> Result<String> {
+    let expected_size = match (vhdx.detect, vhdx.size.as_deref()) {
+        (DeviceDetect::BySize, None) => bail!("vhdx.detect = \"by-size\" requires vhdx.size"),
+        (DeviceDetect::BySize, Some(size)) => {
+            Some(parse_size(size).with_context(|| format!("Invalid vhdx.size: '{}'", size))?)
+        }
+        _ => None,
+    };
+
+    let candidates: Vec<&BlockDevice> = after
+        .iter()
+        .filter(|device| !before.contains(&device.name))
+        .filter(|device| match vhdx.detect {
+            DeviceDetect::LsblkDiff => true,
+            DeviceDetect::BlankDevice => device.fstype.as_deref().unwrap_or("").is_empty(),
+            DeviceDetect::BySize => device.size == expected_size,
+        })
+        .collect();
+
+    match candidates.as_slice() {
+        [device] => Ok(device.name.clone()),
+        [] => bail!(
+            "Could not find new device after mounting VHDX (detect: {}); \
+             on a slow host, raise --attach-timeout",
+            detect_name(vhdx.detect)
+        ),
+        // lsblk-diff keeps its old behavior of taking the first new device
+        [first, ..] if vhdx.detect == DeviceDetect::LsblkDiff => Ok(first.name.clone()),
+        several => bail!(
+            "Several new devices match (detect: {}): {}",
+            detect_name(vhdx.detect),
+            several
+                .iter()
+                .map(|device| device.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+fn detect_name(detect: DeviceDetect) -> &'static str {
+    match detect {
+        DeviceDetect::LsblkDiff => "lsblk-diff",
+        DeviceDetect::BlankDevice => "blank-device",
+        DeviceDetect::BySize => "by-size",
+    }
+}
+
 /// Format device as Btrfs
-fn format_btrfs(cfg: &mut Config, device: &str, dry_run: bool, yes: bool) -> Result<()> {
+fn format_btrfs(
+    runner: &dyn CommandRunner,
+    cfg: &mut Config,
+    device: &str,
+    dry_run: bool,
+    yes: bool,
+    force: bool,
+) -> Result<()> {
     if dry_run {
         info("[dry-run] Would format as Btrfs");
         return Ok(());
     }
 
     // Check if already formatted
-    let block_device = read_block_device(device)?.unwrap_or(crate::utils::cli::BlockDevice {
-        name: device.trim_start_matches("/dev/").to_string(),
-        label: None,
-        fstype: None,
-    });
-
-    if block_device.fstype.as_deref() == Some("btrfs") {
-        // Check label
-        let current_label = block_device.label.as_deref().unwrap_or("");
-
-        if current_label == cfg.vhdx.label {
+    let check = check_device(read_block_device(device)?.as_ref(), &cfg.vhdx.label);
+    // --yes alone must never wipe or take over a disk that may hold other data
+    if let Some(problem) = force_format_problem(&check, &cfg.vhdx.label, force) {
+        bail!("{}: {}", device, problem);
+    }
+    match &check {
+        DeviceCheck::Blank => {}
+        DeviceCheck::OtherFilesystem(fstype) => {
+            warn(&check.describe(&cfg.vhdx.label));
+            if !confirm_or_yes(
+                &format!("Erase the {} filesystem on {}?", fstype, device),
+                false,
+                yes,
+            )? {
+                bail!("Aborted; {} was left untouched", device);
+            }
+        }
+        DeviceCheck::Matching => {
             success(&format!(
                 "Device already formatted as Btrfs with label '{}'",
-                current_label
+                cfg.vhdx.label
             ));
             return Ok(());
         }
-
         // Label mismatch - this is potentially dangerous
-        if current_label.is_empty() {
-            warn(&format!(
-                "Device is Btrfs but has no label (expected '{}')",
-                cfg.vhdx.label
-            ));
-        } else {
-            warn(&format!(
-                "Device is Btrfs with label '{}' (expected '{}')",
-                current_label, cfg.vhdx.label
-            ));
-        }
-        warn("This may be a different volume! Continuing could corrupt data.");
-
-        if !confirm_or_yes("Continue with this device anyway?", false, yes)? {
-            bail!("Aborted due to label mismatch");
-        }
-        if !current_label.is_empty() && current_label != cfg.vhdx.label {
-            warn(&format!(
-                "Using existing label '{}' and updating config",
-                current_label
-            ));
-            cfg.vhdx.label = current_label.to_string();
-        } else if current_label.is_empty() {
-            warn("Device label is empty; attach by label may fail until you set a label.");
+        DeviceCheck::LabelMismatch(_) | DeviceCheck::Unlabeled => {
+            warn(&check.describe(&cfg.vhdx.label));
+            warn("This may be a different volume! Continuing could corrupt data.");
+
+            if !confirm_or_yes("Continue with this device anyway?", false, yes)? {
+                bail!("Aborted due to label mismatch");
+            }
+            if let DeviceCheck::LabelMismatch(label) = &check {
+                warn(&format!(
+                    "Using existing label '{}' and updating config",
+                    label
+                ));
+                cfg.vhdx.label = label.clone();
+            } else {
+                warn("Device label is empty; attach by label may fail until you set a label.");
+            }
+            return Ok(());
         }
-        return Ok(());
     }
 
-    run_or_dry("mkfs.btrfs", &["-L", &cfg.vhdx.label, device], dry_run)?;
+    runner.run_or_dry("mkfs.btrfs", &["-L", &cfg.vhdx.label, device], dry_run)?;
     success("Formatted as Btrfs");
     Ok(())
 }
 
+/// Why `check` needs --force-format before init may touch the device
+fn force_format_problem(check: &DeviceCheck, expected_label: &str, force: bool) -> Option<String> {
+    if force {
+        return None;
+    }
+    let problem = match check {
+        DeviceCheck::Blank | DeviceCheck::Matching => return None,
+        DeviceCheck::LabelMismatch(label) => format!(
+            "existing Btrfs label is '{}' but the config expects '{}'",
+            label, expected_label
+        ),
+        DeviceCheck::Unlabeled => format!(
+            "existing Btrfs filesystem has no label but the config expects '{}'",
+            expected_label
+        ),
+        DeviceCheck::OtherFilesystem(fstype) => format!(
+            "formatting as Btrfs '{}' would erase the existing {} filesystem",
+            expected_label, fstype
+        ),
+    };
+    Some(format!(
+        "{}. Check vhdx.path, or pass --force-format to proceed anyway",
+        problem
+    ))
+}
+
+/// Report what format_btrfs would do with the real device; returns whether
+/// the existing Btrfs filesystem (and so its UUID) would be kept
+fn preview_format(cfg: &Config, device: &str, force: bool) -> Result<bool> {
+    let check = check_device(read_block_device(device)?.as_ref(), &cfg.vhdx.label);
+    info(&format!("[dry-run] {}", check.describe(&cfg.vhdx.label)));
+    if let Some(problem) = force_format_problem(&check, &cfg.vhdx.label, force) {
+        warn(&format!("[dry-run] A real run would stop: {}", problem));
+        return Ok(true);
+    }
+    match &check {
+        DeviceCheck::Blank => info(&format!(
+            "[dry-run] Would format as Btrfs with label '{}'",
+            cfg.vhdx.label
+        )),
+        DeviceCheck::OtherFilesystem(fstype) => warn(&format!(
+            "[dry-run] Would ERASE the {} filesystem and format as Btrfs",
+            fstype
+        )),
+        DeviceCheck::Matching => info("[dry-run] Would keep the existing Btrfs filesystem"),
+        DeviceCheck::LabelMismatch(_) | DeviceCheck::Unlabeled => {
+            warn(
+                "[dry-run] A real run asks before using this device; it may be a different volume",
+            );
+        }
+    }
+    Ok(matches!(
+        check,
+        DeviceCheck::Matching | DeviceCheck::LabelMismatch(_) | DeviceCheck::Unlabeled
+    ))
+}
+
 /// Get filesystem UUID
-fn get_uuid(device: &str, dry_run: bool) -> Result<String> {
+fn get_uuid(runner: &dyn CommandRunner, device: &str, dry_run: bool) -> Result<String> {
     if dry_run {
         return Ok("<uuid>".to_string());
     }
 
-    let output = shell_run("blkid", &["-s", "UUID", "-o", "value", device])?;
+    let output = runner.run("blkid", &["-s", "UUID", "-o", "value", device])?;
     let uuid = output.trim().to_string();
 
     if uuid.is_empty() {
@@ -305,13 +970,21 @@ fn get_uuid(device: &str, dry_run: bool) -> Result<String> {
 }
 
 /// Create all subvolumes
-fn create_subvolumes(cfg: &Config, device: &str, dry_run: bool) -> Result<()> {
-    let mount_point = "/mnt/btrfs-setup";
-
-    // Mount device
-    if !dry_run {
+fn create_subvolumes(
+    runner: &dyn CommandRunner,
+    cfg: &Config,
+    device: &str,
+    dry_run: bool,
+    continue_on_copy_error: bool,
+) -> Result<()> {
+    let mount_point = SETUP_MOUNT;
+
+    // Mount device; an interrupted run may have left it mounted
+    if is_mountpoint(mount_point) {
+        info(&format!("{} already mounted", mount_point));
+    } else if !dry_run {
         fs::create_dir_all(mount_point)?;
-        shell_run("mount", &["-o", "subvolid=5", device, mount_point])?;
+        runner.run("mount", &["-o", "subvolid=5", device, mount_point])?;
     } else {
         info(&format!(
             "[dry-run] Would mount {} to {} (subvolid=5)",
@@ -320,7 +993,11 @@ fn create_subvolumes(cfg: &Config, device: &str, dry_run: bool) -> Result<()> {
     }
 
     // Create subvolumes
-    let result = create_all_subvolumes(cfg, mount_point, dry_run);
+    let mut result =
+        create_all_subvolumes(runner, cfg, mount_point, dry_run, continue_on_copy_error);
+    if result.is_ok() && cfg.mount.quotas {
+        result = enable_quotas(runner, mount_point, dry_run);
+    }
 
     // Save config to @etc subvolume (before unmount!)
     if !dry_run && result.is_ok() {
@@ -335,90 +1012,299 @@ fn create_subvolumes(cfg: &Config, device: &str, dry_run: bool) -> Result<()> {
 
     // Unmount
     if !dry_run {
-        shell_run("umount", &[mount_point])?;
+        runner.run("umount", &[mount_point])?;
         fs::remove_dir(mount_point)?;
     }
 
     result
 }
 
-fn create_all_subvolumes(cfg: &Config, mount_point: &str, dry_run: bool) -> Result<()> {
+fn create_all_subvolumes(
+    runner: &dyn CommandRunner,
+    cfg: &Config,
+    mount_point: &str,
+    dry_run: bool,
+    continue_on_copy_error: bool,
+) -> Result<()> {
     // A-class: Backup targets
     info("Creating A-class (backup) subvolumes...");
-    for subvol in cfg.subvolumes.backup.keys() {
-        create_subvolume(mount_point, subvol, dry_run)?;
+    for (subvol, _) in cfg.sorted_backup() {
+        create_subvolume(runner, mount_point, subvol, dry_run)?;
     }
 
     // @etc: snapshot-only (not in backup HashMap, but still created for btrbk)
     info("Creating @etc subvolume (snapshot-only)...");
-    create_subvolume(mount_point, "@etc", dry_run)?;
+    create_subvolume(runner, mount_point, "@etc", dry_run)?;
 
     // Copy essential system directories if subvolumes are empty
-    copy_if_empty(mount_point, "@etc", "/etc", dry_run)?;
-    copy_if_empty(mount_point, "@usr", "/usr", dry_run)?;
-    copy_if_empty(mount_point, "@opt", "/opt", dry_run)?;
-    copy_if_empty(mount_point, "@var_lib_pacman", "/var/lib/pacman", dry_run)?;
+    for (subvol, source) in [
+        ("@etc", "/etc"),
+        ("@usr", "/usr"),
+        ("@opt", "/opt"),
+        ("@var_lib_pacman", "/var/lib/pacman"),
+    ] {
+        copy_if_empty(mount_point, subvol, source, dry_run, continue_on_copy_error)?;
+    }
 
     // B-class: Excluded paths (nested under parent)
     info("Creating B-class (exclude) nested subvolumes...");
-    let parent = &cfg.subvolumes.exclude.parent;
-    let user = cfg.get_user();
-    for path in &cfg.subvolumes.exclude.paths {
-        let nested = format!("{}/{}", parent, path);
-        create_subvolume(mount_point, &nested, dry_run)?;
-        // chown to target user (these are in user's home)
-        let nested_path = format!("{}/{}", mount_point, nested);
-        run_or_dry(
-            "chown",
-            &[&format!("{}:{}", user, user), &nested_path],
-            dry_run,
-        )?;
+    for (_, home) in cfg.home_subvolumes() {
+        for path in &cfg.subvolumes.exclude.paths {
+            create_subvolume(runner, mount_point, &format!("{}/{}", home, path), dry_run)?;
+        }
     }
 
-    // Also chown @home itself to target user
-    let home_path = format!("{}/{}", mount_point, parent);
-    run_or_dry(
-        "chown",
-        &[&format!("{}:{}", user, user), &home_path],
-        dry_run,
-    )?;
-
     // C-class: Transfer subvolumes
     info("Creating C-class (transfer) subvolumes...");
-    let mut nodatacow_dirs = Vec::new();
-    for (subvol, transfer) in &cfg.subvolumes.transfer {
-        create_subvolume(mount_point, subvol, dry_run)?;
-        if transfer.nodatacow {
-            nodatacow_dirs.push(format!("{}/{}", mount_point, subvol));
+    for (subvol, _) in cfg.sorted_transfer() {
+        create_subvolume(runner, mount_point, subvol, dry_run)?;
+    }
+
+    // Snapshot directory, before the attributes so nodatacow lands on it empty
+    info("Creating snapshot directory...");
+    if let Some(problem) = cfg.snapshot_dir_location_problem() {
+        bail!(problem);
+    }
+    let snapshot_dir = &cfg.btrbk.snapshot_dir;
+    if let Some(parent) = Path::new(snapshot_dir).parent() {
+        if !dry_run && !parent.as_os_str().is_empty() {
+            fs::create_dir_all(Path::new(mount_point).join(parent))?;
         }
-        // chown subvolumes under user's home to target user
-        if transfer.mount.contains(&format!("/home/{}", user)) {
-            let subvol_path = format!("{}/{}", mount_point, subvol);
-            run_or_dry(
-                "chown",
-                &["-R", &format!("{}:{}", user, user), &subvol_path],
-                dry_run,
-            )?;
+    }
+    create_subvolume(runner, mount_point, snapshot_dir, dry_run)?;
+    if let Some(compression) = &cfg.btrbk.snapshot_dir_options.compression {
+        let path = format!("{}/{}", mount_point, snapshot_dir);
+        runner.run_or_dry(
+            "btrfs",
+            &["property", "set", &path, "compression", compression],
+            dry_run,
+        )?;
+    }
+
+    let plan = attribute_plan(cfg);
+    if dry_run {
+        print_attribute_plan(&plan);
+    }
+    let chown_paths: Vec<(String, &str, bool)> = plan
+        .chown
+        .iter()
+        .map(|entry| {
+            (
+                format!("{}/{}", mount_point, entry.subvol),
+                entry.user.as_str(),
+                entry.recursive,
+            )
+        })
+        .collect();
+    let nodatacow_dirs: Vec<String> = plan
+        .nodatacow
+        .iter()
+        .map(|subvol| format!("{}/{}", mount_point, subvol))
+        .collect();
+
+    info("Setting ownership...");
+    let mut not_owned = Vec::new();
+    for (path, user, recursive) in &chown_paths {
+        let ids = if dry_run { None } else { Some(user_ids(user)?) };
+        if !chown_to_user(runner, path, user, ids, *recursive, dry_run)? {
+            not_owned.push(format!("{} ({})", path, user));
         }
     }
+    if !not_owned.is_empty() {
+        warn(&format!(
+            "Ownership did not change for: {}",
+            not_owned.join(", ")
+        ));
+    }
 
-    // Set nodatacow on transfer subvolumes
+    // Set nodatacow on transfer subvolumes; it only applies to files created
+    // afterwards, so a silent failure here leaves CoW on for good
     if !nodatacow_dirs.is_empty() {
         info("Setting nodatacow attribute...");
-        for dir in nodatacow_dirs {
-            run_or_dry("chattr", &["+C", &dir], dry_run)?;
+        let mut failed = Vec::new();
+        for dir in &nodatacow_dirs {
+            if !set_nocow(runner, dir, dry_run)? {
+                failed.push(dir.as_str());
+            }
+        }
+        if !failed.is_empty() {
+            bail!(
+                "nodatacow (chattr +C) could not be set on: {}. The subvolumes must be empty; \
+                 remove them and re-run init.",
+                failed.join(", ")
+            );
         }
     }
 
-    // Create .snapshots directory
-    info("Creating snapshot directory...");
-    create_subvolume(mount_point, &cfg.btrbk.snapshot_dir, dry_run)?;
-
     success("All subvolumes created");
     Ok(())
 }
 
-fn create_subvolume(mount_point: &str, name: &str, dry_run: bool) -> Result<()> {
+/// Turn on btrfs quotas; each subvolume gets a level-0 qgroup automatically
+fn enable_quotas(runner: &dyn CommandRunner, mount_point: &str, dry_run: bool) -> Result<()> {
+    info("Enabling btrfs quotas...");
+    runner.run_or_dry("btrfs", &["quota", "enable", mount_point], dry_run)?;
+    runner.run_or_dry("btrfs", &["quota", "rescan", "-w", mount_point], dry_run)?;
+    success("Quotas enabled");
+    Ok(())
+}
+
+/// A subvolume init hands over to a user, and why
+#[derive(Debug, PartialEq)]
+struct PlannedChown {
+    subvol: String,
+    user: String,
+    recursive: bool,
+    reason: String,
+}
+
+/// Ownership and attribute changes applied after the subvolumes are created
+#[derive(Debug, Default, PartialEq)]
+struct AttributePlan {
+    chown: Vec<PlannedChown>,
+    /// Transfer subvolumes and the snapshot directory that get chattr +C
+    nodatacow: Vec<String>,
+}
+
+fn attribute_plan(cfg: &Config) -> AttributePlan {
+    let mut plan = AttributePlan::default();
+    for (user, home) in cfg.home_subvolumes() {
+        // Excluded paths appear inside the user's home
+        for path in &cfg.subvolumes.exclude.paths {
+            plan.chown.push(PlannedChown {
+                subvol: format!("{}/{}", home, path),
+                user: user.clone(),
+                recursive: false,
+                reason: format!("excluded path in {}", home),
+            });
+        }
+        plan.chown.push(PlannedChown {
+            subvol: home,
+            reason: format!("home of {}", user),
+            user,
+            recursive: false,
+        });
+    }
+
+    for (subvol, transfer) in cfg.sorted_transfer() {
+        if transfer.nodatacow {
+            plan.nodatacow.push(subvol.clone());
+        }
+        // Transfer subvolumes mounted under a user's home belong to that user
+        let owner = cfg.users().into_iter().find(|user| {
+            let home = format!("/home/{}", user);
+            transfer.mount == home || transfer.mount.starts_with(&format!("{}/", home))
+        });
+        if let Some(user) = owner {
+            plan.chown.push(PlannedChown {
+                subvol: subvol.clone(),
+                user,
+                recursive: true,
+                reason: format!("mounted at {}", transfer.mount),
+            });
+        }
+    }
+    if cfg.btrbk.snapshot_dir_options.nodatacow {
+        plan.nodatacow.push(cfg.btrbk.snapshot_dir.clone());
+    }
+    plan
+}
+
+fn print_attribute_plan(plan: &AttributePlan) {
+    prompt::section("Ownership and attribute plan");
+    let width = plan
+        .chown
+        .iter()
+        .map(|entry| entry.subvol.len())
+        .chain(plan.nodatacow.iter().map(String::len))
+        .max()
+        .unwrap_or(0);
+    for entry in &plan.chown {
+        let flag = if entry.recursive { " -R" } else { "" };
+        println!(
+            "  {:<width$} → chown{} {}: ({})",
+            entry.subvol,
+            flag,
+            entry.user,
+            entry.reason,
+            width = width
+        );
+    }
+    for subvol in &plan.nodatacow {
+        println!(
+            "  {:<width$} → chattr +C (nodatacow)",
+            subvol,
+            width = width
+        );
+    }
+    if plan.chown.is_empty() && plan.nodatacow.is_empty() {
+        println!("  No ownership or attribute changes");
+    }
+}
+
+/// chown `path` unless it is already owned by the user; returns whether ownership is correct
+fn chown_to_user(
+    runner: &dyn CommandRunner,
+    path: &str,
+    user: &str,
+    ids: Option<(u32, u32)>,
+    recursive: bool,
+    dry_run: bool,
+) -> Result<bool> {
+    // `user:` picks the login group, the gid `user_ids` reads with `id -g`
+    let owner = format!("{}:", user);
+    let args: Vec<&str> = if recursive {
+        vec!["-R", &owner, path]
+    } else {
+        vec![&owner, path]
+    };
+    let Some(ids) = ids else {
+        runner.run_or_dry("chown", &args, dry_run)?;
+        return Ok(true);
+    };
+
+    // Recursive chowns always run so contents copied in earlier are covered
+    if !recursive && owned_by(path, ids) {
+        return Ok(true);
+    }
+    runner.run_or_dry("chown", &args, dry_run)?;
+    Ok(owned_by(path, ids))
+}
+
+fn owned_by(path: &str, (uid, gid): (u32, u32)) -> bool {
+    fs::metadata(path).is_ok_and(|meta| meta.uid() == uid && meta.gid() == gid)
+}
+
+/// chattr +C, verified with lsattr and retried once; returns whether it is set
+fn set_nocow(runner: &dyn CommandRunner, dir: &str, dry_run: bool) -> Result<bool> {
+    if dry_run {
+        runner.run_or_dry("chattr", &["+C", dir], dry_run)?;
+        return Ok(true);
+    }
+    if has_nocow_attr(dir)? {
+        debug!("{} already has nodatacow", dir);
+        return Ok(true);
+    }
+    for attempt in 1..=2 {
+        if let Err(e) = runner.run_or_dry("chattr", &["+C", dir], false) {
+            warn(&format!(
+                "chattr +C {} failed (attempt {}): {}",
+                dir, attempt, e
+            ));
+        }
+        if has_nocow_attr(dir)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn create_subvolume(
+    runner: &dyn CommandRunner,
+    mount_point: &str,
+    name: &str,
+    dry_run: bool,
+) -> Result<()> {
     let path = format!("{}/{}", mount_point, name);
 
     // Check if subvolume already exists
@@ -427,14 +1313,20 @@ fn create_subvolume(mount_point: &str, name: &str, dry_run: bool) -> Result<()>
         return Ok(());
     }
 
-    run_or_dry("btrfs", &["subvolume", "create", &path], dry_run)?;
+    runner.run_or_dry("btrfs", &["subvolume", "create", &path], dry_run)?;
     info(&format!("  {} (created)", name));
     Ok(())
 }
 
 /// Copy source directory content to subvolume if the subvolume is empty
 /// This is essential for @etc and @usr to prevent empty mount overlay
-fn copy_if_empty(mount_point: &str, subvol: &str, source: &str, dry_run: bool) -> Result<()> {
+fn copy_if_empty(
+    mount_point: &str,
+    subvol: &str,
+    source: &str,
+    dry_run: bool,
+    continue_on_error: bool,
+) -> Result<()> {
     let target = format!("{}/{}", mount_point, subvol);
 
     if dry_run {
@@ -469,24 +1361,65 @@ fn copy_if_empty(mount_point: &str, subvol: &str, source: &str, dry_run: bool) -
     info(&format!("Copying {} to {}...", source, subvol));
     warn("This may take a while for large directories like /usr");
 
-    // Use rsync to preserve permissions, ACLs, and xattrs
-    run_or_dry(
+    // Use rsync to preserve permissions, ACLs, and xattrs; run_unchecked
+    // leaves stdout on the terminal, so the progress2 line updates live
+    let output = run_unchecked(
         "rsync",
         &[
             "-aAX",
+            "--human-readable",
             "--info=progress2",
             &format!("{}/", source),
             &format!("{}/", target),
         ],
-        dry_run,
     )?;
+    match output.code {
+        Some(0) => {}
+        Some(code) if continue_on_error || RSYNC_PARTIAL_CODES.contains(&code) => {
+            let skipped = rsync_skipped_files(&output.stderr);
+            warn(&format!(
+                "  rsync exited with code {} copying {}; {} file(s) skipped",
+                code,
+                source,
+                skipped.len()
+            ));
+            for file in &skipped {
+                println!("      {}", file);
+            }
+        }
+        code => bail!(
+            "rsync failed copying {} to {} (exit code {:?}); pass --continue-on-copy-error \
+             to continue anyway\n{}",
+            source,
+            subvol,
+            code,
+            output.stderr.trim()
+        ),
+    }
 
     success(&format!("  {} copied to {}", source, subvol));
     Ok(())
 }
 
+/// rsync exit codes for a partial copy of a live tree: 23 (some files could
+/// not be transferred) and 24 (source files vanished during the copy)
+const RSYNC_PARTIAL_CODES: &[i32] = &[23, 24];
+
+/// Paths rsync reported as vanished or not transferred, from its stderr
+fn rsync_skipped_files(stderr: &str) -> Vec<String> {
+    stderr
+        .lines()
+        .filter(|line| !line.starts_with("rsync error:"))
+        .filter_map(|line| {
+            let (_, rest) = line.split_once('"')?;
+            let (path, _) = rest.split_once('"')?;
+            Some(path.to_string())
+        })
+        .collect()
+}
+
 /// Mount base Btrfs volume to config.mount.base
-fn mount_base(cfg: &Config, device: &str, dry_run: bool) -> Result<()> {
+fn mount_base(runner: &dyn CommandRunner, cfg: &Config, device: &str, dry_run: bool) -> Result<()> {
     let mount_point = &cfg.mount.base;
 
     // Check if already mounted
@@ -495,18 +1428,353 @@ fn mount_base(cfg: &Config, device: &str, dry_run: bool) -> Result<()> {
         return Ok(());
     }
 
+    // Files left in the plain directory disappear from view once mounted over
+    let hidden = shadowed_entry_count(mount_point);
+    if hidden > 0 {
+        warn(&format!(
+            "{} is not empty ({} entries); they will be hidden (not deleted) while the volume is mounted",
+            mount_point, hidden
+        ));
+    }
+
     // Create mount point
     if !dry_run {
         fs::create_dir_all(mount_point)?;
     }
 
     // Mount with configured options
-    run_or_dry(
+    runner.run_or_dry(
         "mount",
-        &["-o", &cfg.mount.options, device, mount_point],
+        &["-o", &cfg.mount.effective_options(), device, mount_point],
         dry_run,
     )?;
 
     success(&format!("Mounted {} to {}", device, mount_point));
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::shell::MockRunner;
+
+    #[test]
+    fn ensure_user_creates_only_missing_users() {
+        let mut cfg = Config::default();
+        cfg.set_user("alice");
+        let runner = MockRunner::default().with_failure("id", "no such user");
+
+        ensure_user(&runner, &cfg, false).unwrap();
+        assert_eq!(
+            runner.calls(),
+            vec!["id alice", "useradd -M -G wheel alice"]
+        );
+
+        let runner = MockRunner::default();
+        ensure_user(&runner, &cfg, false).unwrap();
+        assert_eq!(runner.calls(), vec!["id alice"]);
+    }
+
+    #[test]
+    fn chown_to_user_uses_the_login_group() {
+        let runner = MockRunner::default();
+        assert!(chown_to_user(&runner, "/mnt/btrfs/@home", "alice", None, true, false).unwrap());
+        assert!(chown_to_user(&runner, "/mnt/btrfs/@data", "alice", None, false, false).unwrap());
+        assert_eq!(
+            runner.calls(),
+            vec![
+                "chown -R alice: /mnt/btrfs/@home",
+                "chown alice: /mnt/btrfs/@data"
+            ]
+        );
+    }
+
+    #[test]
+    fn force_format_required_for_foreign_or_mislabeled_disks() {
+        let mismatch = DeviceCheck::LabelMismatch("Data".to_string());
+        let problem = force_format_problem(&mismatch, "ArchBtrfs", false).unwrap();
+        assert!(problem.contains("label is 'Data' but the config expects 'ArchBtrfs'"));
+        assert!(problem.contains("--force-format"));
+
+        let ext4 = DeviceCheck::OtherFilesystem("ext4".to_string());
+        assert!(force_format_problem(&ext4, "ArchBtrfs", false)
+            .unwrap()
+            .contains("erase the existing ext4 filesystem"));
+        assert!(force_format_problem(&DeviceCheck::Unlabeled, "ArchBtrfs", false).is_some());
+
+        assert!(force_format_problem(&mismatch, "ArchBtrfs", true).is_none());
+        assert!(force_format_problem(&DeviceCheck::Blank, "ArchBtrfs", false).is_none());
+        assert!(force_format_problem(&DeviceCheck::Matching, "ArchBtrfs", false).is_none());
+    }
+
+    #[test]
+    fn get_uuid_rejects_empty_blkid_output() {
+        let runner = MockRunner::default().with_output("blkid", "1234-abcd");
+        assert_eq!(get_uuid(&runner, "/dev/sdd", false).unwrap(), "1234-abcd");
+
+        let err = get_uuid(&MockRunner::default(), "/dev/sdd", false).unwrap_err();
+        assert!(err.to_string().contains("Could not get UUID for /dev/sdd"));
+    }
+
+    #[test]
+    fn resume_point_skips_steps_already_done() {
+        let disk = |name: &str, uuid: &str| BlockDevice {
+            name: name.to_string(),
+            label: Some("wsl-btrfs".to_string()),
+            fstype: Some("btrfs".to_string()),
+            size: None,
+            uuid: Some(uuid.to_string()),
+        };
+        assert_eq!(resume_point(&[], Some("u1"), false).unwrap(), (1, None));
+        // Formatted, but the config with its UUID was never saved
+        assert_eq!(
+            resume_point(&[disk("sdd", "u1")], None, false).unwrap(),
+            (4, Some("/dev/sdd".to_string()))
+        );
+        assert_eq!(
+            resume_point(&[disk("sdd", "u2")], Some("u1"), false)
+                .unwrap()
+                .0,
+            4
+        );
+        // A clone with the same label does not hide the saved disk
+        assert_eq!(
+            resume_point(&[disk("sdd", "u2"), disk("sde", "u1")], Some("u1"), false).unwrap(),
+            (7, Some("/dev/sde".to_string()))
+        );
+        assert_eq!(
+            resume_point(&[disk("sdd", "u1")], Some("u1"), true)
+                .unwrap()
+                .0,
+            8
+        );
+    }
+
+    #[test]
+    fn resume_point_rejects_ambiguous_disks() {
+        let disk = |name: &str, uuid: &str| BlockDevice {
+            name: name.to_string(),
+            label: Some("wsl-btrfs".to_string()),
+            fstype: Some("btrfs".to_string()),
+            size: None,
+            uuid: Some(uuid.to_string()),
+        };
+        // No saved UUID to tell two labeled disks apart
+        let err = resume_point(&[disk("sdd", "u1"), disk("sde", "u2")], None, false).unwrap_err();
+        assert!(err.to_string().contains("/dev/sdd, /dev/sde"));
+        // A clone shares the saved UUID too
+        assert!(resume_point(&[disk("sdd", "u1"), disk("sde", "u1")], Some("u1"), false).is_err());
+    }
+
+    #[test]
+    fn resume_config_collects_again_when_config_was_never_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        let partial = dir.path().join("config.toml.partial");
+        let partial = partial.to_string_lossy();
+
+        // Interrupted before step 1 could save anything
+        assert!(resume_config(&Config::default(), &partial)
+            .unwrap()
+            .is_none());
+
+        let mut answers = Config::default();
+        answers.set_user("alice");
+        answers.vhdx.path = "C:\\wsl\\btrfs.vhdx".to_string();
+        answers.save(&partial).unwrap();
+        let resumed = resume_config(&Config::default(), &partial)
+            .unwrap()
+            .unwrap();
+        assert_eq!(resumed.user.name, "alice");
+        assert_eq!(resumed.vhdx.path, answers.vhdx.path);
+
+        // Step 6 saved the config with its UUID
+        fs::remove_file(partial.as_ref()).unwrap();
+        let saved = Config {
+            uuid: Some("u1".to_string()),
+            ..Config::default()
+        };
+        assert_eq!(
+            resume_config(&saved, &partial).unwrap().unwrap().uuid,
+            saved.uuid
+        );
+    }
+
+    #[test]
+    fn wait_for_new_device_polls_until_the_disk_appears() {
+        let disk = |name: &str| BlockDevice {
+            name: name.to_string(),
+            label: None,
+            fstype: None,
+            size: None,
+            uuid: None,
+        };
+        let before = vec!["sda".to_string()];
+
+        let mut polls = 0;
+        let after = wait_for_new_device(&before, Duration::from_secs(10), || {
+            polls += 1;
+            Ok(if polls < 3 {
+                vec![disk("sda")]
+            } else {
+                vec![disk("sda"), disk("sdd")]
+            })
+        })
+        .unwrap();
+        assert_eq!(polls, 3);
+        assert_eq!(after.len(), 2);
+
+        // Gives up after the timeout and hands back what it last saw
+        let after = wait_for_new_device(&before, Duration::ZERO, || Ok(vec![disk("sda")])).unwrap();
+        assert_eq!(after.len(), 1);
+    }
+
+    #[test]
+    fn select_new_device_applies_detect_strategy() {
+        let device = |name: &str, fstype: Option<&str>, size: u64| BlockDevice {
+            name: name.to_string(),
+            label: None,
+            fstype: fstype.map(str::to_string),
+            size: Some(size),
+            uuid: None,
+        };
+        let before = vec!["sda".to_string()];
+        // sde attached concurrently with our bare disk sdd
+        let after = vec![
+            device("sda", Some("ext4"), 1 << 30),
+            device("sdd", None, 256 << 30),
+            device("sde", Some("ext4"), 8 << 30),
+        ];
+        let mut vhdx = Config::default().vhdx;
+
+        vhdx.detect = DeviceDetect::BlankDevice;
+        assert_eq!(select_new_device(&vhdx, &before, &after).unwrap(), "sdd");
+
+        vhdx.detect = DeviceDetect::BySize;
+        assert!(select_new_device(&vhdx, &before, &after).is_err());
+        vhdx.size = Some("256G".to_string());
+        assert_eq!(select_new_device(&vhdx, &before, &after).unwrap(), "sdd");
+        vhdx.size = Some("8G".to_string());
+        assert_eq!(select_new_device(&vhdx, &before, &after).unwrap(), "sde");
+
+        vhdx.detect = DeviceDetect::LsblkDiff;
+        assert_eq!(select_new_device(&vhdx, &before, &after).unwrap(), "sdd");
+    }
+
+    #[test]
+    fn check_device_classifies_fstype_and_label() {
+        let device = |fstype: Option<&str>, label: Option<&str>| BlockDevice {
+            name: "sdd".to_string(),
+            label: label.map(str::to_string),
+            fstype: fstype.map(str::to_string),
+            size: None,
+            uuid: None,
+        };
+
+        assert_eq!(check_device(None, "WSL"), DeviceCheck::Blank);
+        assert_eq!(
+            check_device(Some(&device(None, None)), "WSL"),
+            DeviceCheck::Blank
+        );
+        assert_eq!(
+            check_device(Some(&device(Some("btrfs"), Some("WSL"))), "WSL"),
+            DeviceCheck::Matching
+        );
+        assert_eq!(
+            check_device(Some(&device(Some("btrfs"), Some("Other"))), "WSL"),
+            DeviceCheck::LabelMismatch("Other".to_string())
+        );
+        assert_eq!(
+            check_device(Some(&device(Some("btrfs"), None)), "WSL"),
+            DeviceCheck::Unlabeled
+        );
+        assert_eq!(
+            check_device(Some(&device(Some("ext4"), Some("WSL"))), "WSL"),
+            DeviceCheck::OtherFilesystem("ext4".to_string())
+        );
+    }
+
+    #[test]
+    fn rsync_skipped_files_reads_quoted_paths() {
+        let stderr = "\
+file has vanished: \"/usr/lib/python3.12/__pycache__/x.pyc\"
+rsync: [sender] send_files failed to open \"/usr/share/secret\": Permission denied (13)
+rsync error: some files/attrs were not transferred (see previous errors) (code 23) at main.c(1338)";
+
+        assert_eq!(
+            rsync_skipped_files(stderr),
+            vec!["/usr/lib/python3.12/__pycache__/x.pyc", "/usr/share/secret"]
+        );
+    }
+
+    #[test]
+    fn attribute_plan_lists_chown_and_nodatacow_targets() {
+        let mut cfg = Config::default();
+        cfg.subvolumes.exclude.paths = vec![".cache".to_string()];
+        cfg.subvolumes.transfer.clear();
+        cfg.subvolumes.transfer.insert(
+            "@containers_user".to_string(),
+            crate::config::TransferSubvol {
+                mount: "/home/$USER/.local/share/containers".to_string(),
+                nodatacow: true,
+                options: None,
+                options_append: None,
+            },
+        );
+        cfg.subvolumes.transfer.insert(
+            "@var_log".to_string(),
+            crate::config::TransferSubvol {
+                mount: "/var/log".to_string(),
+                nodatacow: false,
+                options: None,
+                options_append: None,
+            },
+        );
+        cfg.set_user("alice");
+
+        let plan = attribute_plan(&cfg);
+        let chown: Vec<(&str, &str, bool)> = plan
+            .chown
+            .iter()
+            .map(|entry| (entry.subvol.as_str(), entry.user.as_str(), entry.recursive))
+            .collect();
+        assert_eq!(
+            chown,
+            vec![
+                ("@home/.cache", "alice", false),
+                ("@home", "alice", false),
+                ("@containers_user", "alice", true),
+            ]
+        );
+        assert_eq!(plan.nodatacow, vec!["@containers_user"]);
+    }
+
+    #[test]
+    fn classify_subvolumes_reports_matched_missing_and_unexpected() {
+        let mut cfg = Config::default();
+        cfg.set_user("alice");
+        let present: Vec<String> = [
+            "@usr",
+            "@home",
+            "@home/.cache",
+            "@etc",
+            "@legacy",
+            ".snapshots",
+            ".snapshots/usr.20240115T0300",
+        ]
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+
+        let adoption = classify_subvolumes(&cfg, &present);
+
+        assert!(adoption
+            .matched
+            .contains(&("@home/.cache".to_string(), "exclude")));
+        assert!(adoption
+            .matched
+            .contains(&(".snapshots".to_string(), "snapshots")));
+        assert!(adoption.missing.contains(&"@opt".to_string()));
+        assert!(adoption.missing.contains(&"@containers".to_string()));
+        assert_eq!(adoption.unexpected, vec!["@legacy".to_string()]);
+    }
+}
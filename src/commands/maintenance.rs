@@ -0,0 +1,85 @@
+//! Btrfs scrub, balance and trim on the wslarc volume
+//!
+//! All run in the foreground so the scheduled units (wslarc-scrub.timer,
+//! wslarc-balance.timer, wslarc-trim.timer) finish only when the work is done.
+
+use anyhow::{bail, Result};
+use console::style;
+
+use crate::config::Config;
+use crate::utils::cli::{ensure_dependencies, is_mountpoint, Dependency};
+use crate::utils::prompt::{info, success};
+use crate::utils::shell::run_with_output;
+
+/// Only rewrite chunks at most this full; keeps a scheduled balance cheap
+const BALANCE_USAGE: &str = "50";
+
+pub fn scrub(config: &Config) -> Result<()> {
+    println!("{}", style("Btrfs Scrub").bold().cyan());
+    println!();
+
+    ensure_ready(config)?;
+    info(&format!(
+        "Scrubbing {} (verifies all checksums)...",
+        config.mount.base
+    ));
+    run_with_output("btrfs", &["scrub", "start", "-B", &config.mount.base])?;
+
+    println!();
+    println!("{}", style("Scrub complete!").bold().green());
+    Ok(())
+}
+
+pub fn balance(config: &Config) -> Result<()> {
+    println!("{}", style("Btrfs Balance").bold().cyan());
+    println!();
+
+    ensure_ready(config)?;
+    let data_filter = format!("-dusage={}", BALANCE_USAGE);
+    let metadata_filter = format!("-musage={}", BALANCE_USAGE);
+    info(&format!(
+        "Compacting chunks up to {}% full on {}...",
+        BALANCE_USAGE, config.mount.base
+    ));
+    run_with_output(
+        "btrfs",
+        &[
+            "balance",
+            "start",
+            &data_filter,
+            &metadata_filter,
+            &config.mount.base,
+        ],
+    )?;
+
+    success("Balance finished");
+    println!();
+    println!("{}", style("Balance complete!").bold().green());
+    Ok(())
+}
+
+/// Discard unused blocks so Windows can shrink the sparse VHDX behind the volume
+pub fn trim(config: &Config) -> Result<()> {
+    println!("{}", style("Btrfs Trim").bold().cyan());
+    println!();
+
+    ensure_ready(config)?;
+    ensure_dependencies(&[Dependency::new("util-linux", &["fstrim"])])?;
+    info(&format!("Trimming free space on {}...", config.mount.base));
+    run_with_output("fstrim", &["-v", &config.mount.base])?;
+
+    println!();
+    println!("{}", style("Trim complete!").bold().green());
+    Ok(())
+}
+
+fn ensure_ready(config: &Config) -> Result<()> {
+    ensure_dependencies(&[Dependency::new("btrfs-progs", &["btrfs"])])?;
+    if !is_mountpoint(&config.mount.base) {
+        bail!(
+            "{} is not mounted. Run 'wslarc mount' or attach the disk first.",
+            config.mount.base
+        );
+    }
+    Ok(())
+}
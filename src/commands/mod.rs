@@ -1,8 +1,20 @@
 pub mod attach;
+pub mod benchmark;
+pub mod browse;
+pub mod command_list;
+pub mod config;
+pub mod diagnostics;
+pub mod doctor;
+pub mod graph;
 pub mod hook_sync_systemd;
 pub mod init;
+pub mod maintenance;
 pub mod mount;
+pub mod recover_config;
+pub mod relabel;
 pub mod restore;
 pub mod snapshot;
 pub mod status;
+pub mod timer;
 pub mod unmount;
+pub mod verify;
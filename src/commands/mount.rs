@@ -1,63 +1,249 @@
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use console::style;
 use ini::Ini;
+use log::debug;
+use std::env;
 use std::fs;
 use std::path::Path;
+use std::process;
 
+use crate::commands::unmount::prune_orphaned_units;
 use crate::config::Config;
-use crate::generators::{btrbk, ext4_sync, systemd};
-use crate::utils::cli::{ensure_dependencies, Dependency};
-use crate::utils::prompt::{confirm_or_yes, info, step, success, warn};
-use crate::utils::shell::run_or_dry;
+use crate::generators::{btrbk, ext4_sync, maintenance, systemd};
+use crate::utils::cli::{
+    ensure_dependencies, find_unreachable_ssh_targets, is_mountpoint, is_read_only,
+    list_subvolumes, parse_ssh_target, shadowed_entry_count, show_subvolume, Dependency,
+};
+use crate::utils::prompt::{confirm_destructive, confirm_or_yes, info, step, success, warn};
+use crate::utils::scope::{Scope, SYSTEM_UNIT_DIR};
+use crate::utils::shell::CommandRunner;
+use crate::utils::wsl::{with_boot_command, WSL_CONF, WSL_CONF_BACKUP};
 
-const SYSTEMD_DIR: &str = "/etc/systemd/system";
-const BTRBK_CONF: &str = "/etc/btrbk/btrbk.conf";
 const WSLARC_BIN: &str = "/usr/local/bin/wslarc";
-const WSL_CONF: &str = "/etc/wsl.conf";
 const PACMAN_HOOK_PATH: &str = "/etc/pacman.d/hooks/sync-systemd-ext4.hook";
 
-fn has_usr_subvol(config: &Config) -> bool {
+pub fn has_usr_subvol(config: &Config) -> bool {
     config.subvolumes.backup.contains_key("@usr")
 }
 
-pub fn run(config: &Config, yes: bool, dry_run: bool) -> Result<()> {
+fn has_ssh_targets(config: &Config) -> bool {
+    config
+        .btrbk
+        .targets
+        .iter()
+        .any(|target| parse_ssh_target(target).is_some())
+}
+
+pub struct MountOptions {
+    /// Only show what would be written
+    pub dry_run: bool,
+    /// Link units into .wants directories instead of running systemctl enable
+    pub defer_enable: bool,
+    /// User scope only generates units into the user unit directory
+    pub scope: Scope,
+    /// Leave files whose content is unchanged alone and skip needless reloads
+    pub only_new: bool,
+    /// Generate and validate everything in a temporary directory, install nothing
+    pub check_only: bool,
+    /// Only regenerate btrbk.conf and the btrbk units, leaving mount units alone
+    pub btrbk_only: bool,
+    /// Write every generated file under this directory instead of the system paths
+    pub output_dir: Option<String>,
+    /// Regenerate and re-enable only the mount unit of this subvolume
+    pub only: Option<String>,
+    /// Offer to disable and delete generated mount units no longer in the config
+    pub prune: bool,
+}
+
+/// Writes generated files; with `only_new`, identical files are left untouched
+struct FileWriter {
+    dry_run: bool,
+    only_new: bool,
+    /// Number of files written (or that would be written in a dry run)
+    changed: usize,
+    /// Prefix for every path, mirroring the system layout below it
+    output_dir: Option<String>,
+}
+
+impl FileWriter {
+    fn new(options: &MountOptions) -> Self {
+        Self {
+            dry_run: options.dry_run,
+            only_new: options.only_new,
+            changed: 0,
+            output_dir: options.output_dir.clone(),
+        }
+    }
+
+    /// Whether files land where they are used, so validators and other
+    /// side effects on the live system apply
+    fn live(&self) -> bool {
+        !self.dry_run && self.output_dir.is_none()
+    }
+
+    /// Where `path` is actually written
+    fn target(&self, path: &str) -> String {
+        match &self.output_dir {
+            Some(dir) => format!(
+                "{}/{}",
+                dir.trim_end_matches('/'),
+                path.trim_start_matches('/')
+            ),
+            None => path.to_string(),
+        }
+    }
+
+    fn write(&mut self, path: &str, content: &str) -> Result<()> {
+        let path = &self.target(path);
+        if self.only_new && fs::read_to_string(path).is_ok_and(|existing| existing == content) {
+            debug!("{} unchanged, not rewriting", path);
+            return Ok(());
+        }
+        self.changed += 1;
+
+        if self.dry_run {
+            info(&format!("[dry-run] Would write {}", path));
+            return Ok(());
+        }
+
+        // Create parent directory if needed
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, content).with_context(|| format!("Failed to write {}", path))?;
+        Ok(())
+    }
+
+    /// Write systemd unit file into `unit_dir` (ext4 /etc in system scope)
+    fn write_unit(&mut self, unit_dir: &str, filename: &str, content: &str) -> Result<()> {
+        self.write(&format!("{}/{}", unit_dir, filename), content)
+    }
+}
+
+pub fn run(
+    config: &Config,
+    yes: bool,
+    options: &MountOptions,
+    runner: &dyn CommandRunner,
+) -> Result<()> {
+    let dry_run = options.dry_run;
+
     println!("{}", style("WSL Btrfs Mount Setup").bold().cyan());
 
     if config.uuid.is_none() {
         bail!("UUID not set. Run 'wslarc init' first.");
     }
 
-    ensure_dependencies(&[Dependency::new("btrbk", &["btrbk"])])?;
+    if let Some(subvol) = &options.only {
+        if options.scope == Scope::User {
+            bail!("--only supports the system scope only");
+        }
+        return run_only(runner, config, subvol, options);
+    }
+
+    // Generating into a directory runs neither btrbk nor ssh
+    if options.output_dir.is_none() {
+        btrbk::ensure_binary(config)?;
+        if has_ssh_targets(config) {
+            ensure_dependencies(&[Dependency::new("openssh", &["ssh"])])?;
+        }
+    }
+
+    if options.btrbk_only {
+        if options.scope == Scope::User {
+            bail!("--btrbk-only supports the system scope only");
+        }
+        return run_btrbk_only(runner, config, yes, options);
+    }
 
     let needs_ext4_sync = has_usr_subvol(config);
 
-    show_summary(config, needs_ext4_sync);
+    if let Some(dir) = &options.output_dir {
+        return generate_to_dir(runner, config, dir, needs_ext4_sync, options);
+    }
+
+    show_summary(config, needs_ext4_sync, options.scope);
+
+    for warning in config.warnings() {
+        warn(&warning);
+    }
+    if let Some(warning) = check_snapshot_dir_layout(config) {
+        warn(&warning);
+    }
+    let hidden = shadowed_entry_count(&config.mount.base);
+    if hidden > 0 {
+        warn(&format!(
+            "{} is not empty ({} entries); the base mount unit will hide them",
+            config.mount.base, hidden
+        ));
+    }
 
-    if !confirm_or_yes("Generate and install systemd units?", true, yes)? {
+    if !options.check_only && !confirm_destructive("Generate and install systemd units?", yes)? {
         println!("Aborted.");
         return Ok(());
     }
 
+    if options.scope == Scope::User {
+        if options.check_only {
+            bail!("--check-only supports the system scope only");
+        }
+        if options.prune {
+            bail!("--prune supports the system scope only");
+        }
+        return generate_user_units(runner, config, &mut FileWriter::new(options));
+    }
+
+    let Some(local_only) = validate_local_only(config, yes)? else {
+        println!("Aborted.");
+        return Ok(());
+    };
+
+    if options.check_only {
+        return check_only(runner, config, local_only);
+    }
+
     let total_steps = if needs_ext4_sync { 6 } else { 5 };
 
     step(1, total_steps, "Install wslarc binary");
-    install_binary(config, dry_run)?;
+    install_binary(runner, config, dry_run)?;
 
     step(2, total_steps, "Setup wsl.conf boot command");
     update_wsl_conf(dry_run)?;
 
-    step(3, total_steps, "Generate systemd mount units");
-    generate_systemd_units(config, dry_run)?;
-
-    step(4, total_steps, "Generate btrbk configuration");
-    generate_btrbk_config(config, dry_run)?;
+    let mut writer = FileWriter::new(options);
 
-    step(5, total_steps, "Enable systemd services");
-    enable_services(config, dry_run)?;
+    step(3, total_steps, "Generate systemd mount units");
+    generate_systemd_units(runner, config, SYSTEM_UNIT_DIR, &mut writer)?;
+    let pruned = if options.prune {
+        prune_orphaned_units(runner, config, SYSTEM_UNIT_DIR, yes, dry_run)?
+    } else {
+        0
+    };
+
+    step(4, total_steps, "Generate btrbk configuration and timers");
+    generate_btrbk_config(
+        runner,
+        config,
+        btrbk::config_path(config),
+        local_only,
+        &mut writer,
+    )?;
+    generate_btrbk_units(config, SYSTEM_UNIT_DIR, &mut writer)?;
+    generate_maintenance_units(config, SYSTEM_UNIT_DIR, &mut writer)?;
+
+    if options.defer_enable {
+        step(5, total_steps, "Link systemd services (deferred enable)");
+        link_services(config, dry_run)?;
+    } else {
+        step(5, total_steps, "Enable systemd services");
+        let reload = !options.only_new || writer.changed > 0 || pruned > 0;
+        enable_services(runner, config, reload, dry_run)?;
+    }
 
     if needs_ext4_sync {
         step(6, total_steps, "Setup ext4 systemd sync");
-        setup_ext4_sync(config, dry_run)?;
+        setup_ext4_sync(config, &mut writer)?;
     }
 
     println!();
@@ -68,33 +254,381 @@ pub fn run(config: &Config, yes: bool, dry_run: bool) -> Result<()> {
     Ok(())
 }
 
-fn show_summary(config: &Config, needs_ext4_sync: bool) {
+/// Whether btrbk.conf can only be validated locally because a target is
+/// unreachable; None when the user declines to continue
+fn validate_local_only(config: &Config, yes: bool) -> Result<Option<bool>> {
+    // An unreachable backup server would make `btrbk dryrun` fail or hang
+    let unreachable = find_unreachable_ssh_targets(&config.btrbk.targets);
+    if unreachable.is_empty() {
+        return Ok(Some(false));
+    }
+    for target in &unreachable {
+        warn(&format!("btrbk target unreachable: {}", target));
+    }
+    if !confirm_or_yes("Continue and validate local snapshots only?", true, yes)? {
+        return Ok(None);
+    }
+    Ok(Some(true))
+}
+
+/// Rewrite btrbk.conf, btrbk.service and btrbk.timer after a retention or
+/// schedule change, without touching the mount units
+fn run_btrbk_only(
+    runner: &dyn CommandRunner,
+    config: &Config,
+    yes: bool,
+    options: &MountOptions,
+) -> Result<()> {
+    let Some(local_only) = validate_local_only(config, yes)? else {
+        println!("Aborted.");
+        return Ok(());
+    };
+    let mut writer = FileWriter::new(options);
+
+    step(1, 2, "Generate btrbk configuration and timer");
+    generate_btrbk_config(
+        runner,
+        config,
+        btrbk::config_path(config),
+        local_only,
+        &mut writer,
+    )?;
+    generate_btrbk_units(config, SYSTEM_UNIT_DIR, &mut writer)?;
+
+    if options.defer_enable {
+        step(2, 2, "Link btrbk timer (deferred enable)");
+        link_unit("btrbk.timer", "timers.target", options.dry_run)?;
+    } else {
+        step(2, 2, "Enable btrbk timer");
+        if !options.only_new || writer.changed > 0 {
+            runner.run_or_dry("systemctl", &["daemon-reload"], options.dry_run)?;
+            success("systemd daemon reloaded");
+        } else {
+            info("No unit files changed; skipping daemon-reload");
+        }
+        runner.run_or_dry(
+            "systemctl",
+            &["enable", "--now", "btrbk.timer"],
+            options.dry_run,
+        )?;
+        success("btrbk.timer enabled");
+    }
+
     println!();
-    println!("{}", style("Files to generate:").bold());
+    println!("{}", style("btrbk setup complete!").green().bold());
+    Ok(())
+}
 
-    println!("  {}", WSLARC_BIN);
-    println!("  {} (update [boot] command)", WSL_CONF);
+/// Mount unit filename and content for one backup or transfer subvolume
+fn single_mount_unit(config: &Config, subvol: &str) -> Result<(String, String)> {
+    let subvol = format!("@{}", subvol.trim_start_matches('@'));
 
-    let base_unit = systemd::mount_unit_filename(&config.mount.base);
-    println!("  {}/{}", SYSTEMD_DIR, base_unit);
+    if let Some(backup) = config.subvolumes.backup.get(&subvol) {
+        let content = systemd::generate_subvol_mount(
+            config,
+            &subvol,
+            backup.mount(),
+            backup.options(),
+            backup.options_append(),
+        );
+        return Ok((systemd::mount_unit_filename(backup.mount()), content));
+    }
+    if let Some(transfer) = config.subvolumes.transfer.get(&subvol) {
+        let content = systemd::generate_subvol_mount(
+            config,
+            &subvol,
+            &transfer.mount,
+            transfer.options.as_deref(),
+            transfer.options_append.as_deref(),
+        );
+        return Ok((systemd::mount_unit_filename(&transfer.mount), content));
+    }
 
-    for backup in config.subvolumes.backup.values() {
-        let unit = systemd::mount_unit_filename(backup.mount());
-        println!("  {}/{}", SYSTEMD_DIR, unit);
+    bail!(
+        "{} is not in subvolumes.backup or subvolumes.transfer",
+        subvol
+    );
+}
+
+/// Rewrite, validate and re-enable the mount unit of one subvolume after
+/// changing its options, leaving every other unit and btrbk alone
+fn run_only(
+    runner: &dyn CommandRunner,
+    config: &Config,
+    subvol: &str,
+    options: &MountOptions,
+) -> Result<()> {
+    let (unit, content) = single_mount_unit(config, subvol)?;
+    let mut writer = FileWriter::new(options);
+
+    step(1, 2, &format!("Generate {}", unit));
+    writer.write_unit(SYSTEM_UNIT_DIR, &unit, &content)?;
+    if writer.live() && writer.changed > 0 {
+        info("Validating systemd unit...");
+        let path = format!("{}/{}", SYSTEM_UNIT_DIR, unit);
+        runner.run("systemd-analyze", &["verify", &path])?;
+    }
+    success(&format!("{} created and validated", unit));
+
+    if options.defer_enable {
+        step(2, 2, &format!("Link {} (deferred enable)", unit));
+        link_unit(&unit, "multi-user.target", options.dry_run)?;
+    } else {
+        step(2, 2, &format!("Enable {}", unit));
+        if !options.only_new || writer.changed > 0 {
+            runner.run_or_dry("systemctl", &["daemon-reload"], options.dry_run)?;
+            success("systemd daemon reloaded");
+        } else {
+            info("No unit files changed; skipping daemon-reload");
+        }
+        runner.run_or_dry("systemctl", &["enable", &unit], options.dry_run)?;
+        success(&format!("{} enabled", unit));
     }
 
-    for transfer in config.subvolumes.transfer.values() {
-        let unit = systemd::mount_unit_filename(&transfer.mount);
-        println!("  {}/{}", SYSTEMD_DIR, unit);
+    println!();
+    println!("{}", style("Mount unit updated!").green().bold());
+    println!();
+    println!(
+        "Apply the new options with: {}",
+        style(format!("systemctl restart {}", unit)).cyan()
+    );
+    Ok(())
+}
+
+/// Generate every file into a temporary directory and run the same validators
+/// a real run would, leaving /etc and systemd untouched
+fn check_only(runner: &dyn CommandRunner, config: &Config, local_only: bool) -> Result<()> {
+    let check_dir = env::temp_dir().join(format!("wslarc-check-{}", process::id()));
+    let unit_dir = check_dir.join("units").to_string_lossy().to_string();
+    let conf_path = check_dir.join("btrbk.conf").to_string_lossy().to_string();
+    let mut writer = FileWriter {
+        dry_run: false,
+        only_new: false,
+        changed: 0,
+        output_dir: None,
+    };
+
+    let result = (|| {
+        step(1, 2, "Validate systemd units");
+        generate_systemd_units(runner, config, &unit_dir, &mut writer)?;
+        let mut units = generate_btrbk_units(config, &unit_dir, &mut writer)?;
+        units.extend(generate_maintenance_units(config, &unit_dir, &mut writer)?);
+        info("Validating btrbk and maintenance units...");
+        let mut args = vec!["verify"];
+        args.extend(units.iter().map(String::as_str));
+        runner.run("systemd-analyze", &args)?;
+        success("btrbk and maintenance units validated");
+
+        step(2, 2, "Validate btrbk configuration");
+        generate_btrbk_config(runner, config, &conf_path, local_only, &mut writer)
+    })();
+    if let Err(e) = fs::remove_dir_all(&check_dir) {
+        debug!("Could not remove {}: {}", check_dir.display(), e);
+    }
+
+    println!();
+    match result {
+        Ok(()) => {
+            println!("{}", style("Check passed!").green().bold());
+            println!("A real 'wslarc mount' would install these units.");
+            Ok(())
+        }
+        Err(e) => Err(e.context("Check failed; 'wslarc mount' would not succeed")),
     }
+}
 
-    println!("  {}", BTRBK_CONF);
-    println!("  {}/btrbk.service", SYSTEMD_DIR);
-    println!("  {}/btrbk.timer", SYSTEMD_DIR);
+/// Write the units, btrbk.conf, wsl.conf and pacman hook below `dir` with
+/// the same relative layout as on the system; nothing is installed, enabled
+/// or validated, so this also works on a machine without the Btrfs disk
+fn generate_to_dir(
+    runner: &dyn CommandRunner,
+    config: &Config,
+    dir: &str,
+    needs_ext4_sync: bool,
+    options: &MountOptions,
+) -> Result<()> {
+    let mut writer = FileWriter::new(options);
+    let total_steps = if needs_ext4_sync { 4 } else { 3 };
+
+    step(1, total_steps, "Generate systemd mount units");
+    generate_systemd_units(runner, config, SYSTEM_UNIT_DIR, &mut writer)?;
+
+    step(2, total_steps, "Generate btrbk configuration and timers");
+    generate_btrbk_config(
+        runner,
+        config,
+        btrbk::config_path(config),
+        false,
+        &mut writer,
+    )?;
+    generate_btrbk_units(config, SYSTEM_UNIT_DIR, &mut writer)?;
+    generate_maintenance_units(config, SYSTEM_UNIT_DIR, &mut writer)?;
+
+    step(3, total_steps, "Generate wsl.conf boot command");
+    let current = fs::read_to_string(WSL_CONF).unwrap_or_default();
+    writer.write(WSL_CONF, &with_boot_command(&current, WSLARC_ATTACH_CMD))?;
+    success("wsl.conf created");
 
     if needs_ext4_sync {
+        step(4, total_steps, "Generate ext4 systemd sync");
+        setup_ext4_sync(config, &mut writer)?;
+    }
+
+    println!();
+    println!("{}", style("Unit generation complete!").green().bold());
+    println!();
+    info(&format!("{} files written below {}", writer.changed, dir));
+    info("Nothing was installed or enabled");
+
+    Ok(())
+}
+
+/// Recovery path: mount the base volume and every subvolume directly
+///
+/// Skips unit generation entirely, so a broken unit (bad UUID, typo in the
+/// options) cannot keep the system from coming up. Options are built the same
+/// way as in the generated units.
+pub fn run_emergency(config: &Config, dry_run: bool, runner: &dyn CommandRunner) -> Result<()> {
+    println!(
+        "{}",
+        style("Emergency Mount (bypassing systemd)").bold().cyan()
+    );
+    println!();
+
+    let Some(uuid) = config.uuid.as_deref() else {
+        bail!("UUID not set. Run 'wslarc init' first.");
+    };
+    let source = format!("UUID={}", uuid);
+
+    let plan = emergency_mount_plan(config);
+    let total = plan.len() as u32;
+    let mut failed = Vec::new();
+    for (num, (target, options)) in (1..).zip(&plan) {
+        step(num, total, &format!("Mount {}", target));
+        if is_mountpoint(target) {
+            info(&format!("{} is already mounted, skipping", target));
+            continue;
+        }
+        if dry_run {
+            info(&format!(
+                "[dry-run] Would run: mount -t btrfs -o {} {} {}",
+                options, source, target
+            ));
+            continue;
+        }
+        fs::create_dir_all(target)?;
+        // Keep going so one bad subvolume doesn't block the rest of recovery
+        match runner.run("mount", &["-t", "btrfs", "-o", options, &source, target]) {
+            Ok(_) => success(&format!("Mounted {}", target)),
+            Err(e) => {
+                warn(&format!("Failed to mount {}: {}", target, e));
+                failed.push(target.clone());
+            }
+        }
+    }
+
+    println!();
+    if !failed.is_empty() {
+        bail!("Emergency mount failed for: {}", failed.join(", "));
+    }
+    println!("{}", style("Emergency mount complete!").bold().green());
+    println!("Fix the units with 'wslarc mount' once the system is usable again.");
+
+    Ok(())
+}
+
+/// (target, options) pairs: base first, then subvolumes with parents before nested mounts
+fn emergency_mount_plan(config: &Config) -> Vec<(String, String)> {
+    let mut subvols: Vec<(String, String)> = config
+        .sorted_backup()
+        .into_iter()
+        .map(|(subvol, backup)| {
+            (
+                backup.mount().to_string(),
+                systemd::subvol_mount_options(
+                    config,
+                    subvol,
+                    backup.options(),
+                    backup.options_append(),
+                ),
+            )
+        })
+        .chain(
+            config
+                .sorted_transfer()
+                .into_iter()
+                .map(|(subvol, transfer)| {
+                    (
+                        transfer.mount.clone(),
+                        systemd::subvol_mount_options(
+                            config,
+                            subvol,
+                            transfer.options.as_deref(),
+                            transfer.options_append.as_deref(),
+                        ),
+                    )
+                }),
+        )
+        .collect();
+    subvols.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut plan = vec![(
+        config.mount.base.clone(),
+        systemd::base_mount_options(config),
+    )];
+    plan.extend(subvols);
+    plan
+}
+
+/// init creates the snapshot directory as its own subvolume; a plain directory
+/// there usually means the layout was built by hand
+fn check_snapshot_dir_layout(config: &Config) -> Option<String> {
+    if !is_mountpoint(&config.mount.base) {
+        return None;
+    }
+    let path = format!("{}/{}", config.mount.base, config.btrbk.snapshot_dir);
+    if !Path::new(&path).is_dir() || show_subvolume(&path).is_ok() {
+        return None;
+    }
+    Some(format!(
+        "{} is a plain directory, not a subvolume; recreate it with 'btrfs subvolume create' \
+         so snapshots are not stored inside another subvolume",
+        path
+    ))
+}
+
+fn show_summary(config: &Config, needs_ext4_sync: bool, scope: Scope) {
+    let unit_dir = scope.unit_dir();
+    let system = scope == Scope::System;
+
+    println!();
+    println!("{}", style("Files to generate:").bold());
+
+    if system {
+        println!("  {}", WSLARC_BIN);
+        println!("  {} (update [boot] command)", WSL_CONF);
+    }
+
+    for unit in systemd::mount_unit_names(config) {
+        println!("  {}/{}", unit_dir, unit);
+    }
+
+    if system {
+        println!("  {}", btrbk::config_path(config));
+    }
+    println!("  {}/btrbk.service", unit_dir);
+    println!("  {}/btrbk.timer", unit_dir);
+    // Scheduled scrub, balance and trim from [maintenance]
+    if system {
+        for (task, _) in maintenance::scheduled_tasks(config) {
+            println!("  {}/{}", unit_dir, task.service_unit());
+            println!("  {}/{}", unit_dir, task.timer_unit());
+        }
+    }
+
+    if system && needs_ext4_sync {
         let ext4_unit = ext4_sync::ext4_mount_unit_filename(config);
-        println!("  {}/{}", SYSTEMD_DIR, ext4_unit);
+        println!("  {}/{}", SYSTEM_UNIT_DIR, ext4_unit);
         println!("  {}", PACMAN_HOOK_PATH);
     }
 
@@ -102,7 +636,7 @@ fn show_summary(config: &Config, needs_ext4_sync: bool) {
 }
 
 /// Install wslarc binary to /usr/local/bin (ext4 and @usr subvolume)
-fn install_binary(config: &Config, dry_run: bool) -> Result<()> {
+fn install_binary(runner: &dyn CommandRunner, config: &Config, dry_run: bool) -> Result<()> {
     let current_exe = std::env::current_exe()?;
     let current_path = current_exe.to_string_lossy();
 
@@ -121,24 +655,52 @@ fn install_binary(config: &Config, dry_run: bool) -> Result<()> {
         return Ok(());
     }
 
-    // Create directory if needed
-    fs::create_dir_all("/usr/local/bin")?;
+    // A read-only root (recovery mode, or remounted after an error) fails
+    // the copy with a bare EROFS; report it and still update @usr
+    let root_read_only = is_read_only(WSLARC_BIN);
+    if root_read_only {
+        warn(&format!(
+            "{} is on a read-only filesystem; skipping the ext4 copy",
+            WSLARC_BIN
+        ));
+    } else {
+        // Create directory if needed
+        fs::create_dir_all("/usr/local/bin")?;
 
-    // Remove old binary first (can't overwrite running executable)
-    let _ = fs::remove_file(WSLARC_BIN);
+        // Remove old binary first (can't overwrite running executable)
+        let _ = fs::remove_file(WSLARC_BIN);
 
-    // Copy binary to ext4
-    fs::copy(&current_exe, WSLARC_BIN)?;
-    run_or_dry("chmod", &["+x", WSLARC_BIN], false)?;
+        // Copy binary to ext4
+        fs::copy(&current_exe, WSLARC_BIN)
+            .with_context(|| format!("Failed to copy wslarc to {}", WSLARC_BIN))?;
+        runner.run_or_dry("chmod", &["+x", WSLARC_BIN], false)?;
+    }
 
     // Also copy to @usr subvolume if mounted
-    let btrfs_bin = format!("{}/@usr/local/bin/wslarc", config.mount.base);
-    let btrfs_bin_dir = format!("{}/@usr/local/bin", config.mount.base);
-    if Path::new(&format!("{}/@usr", config.mount.base)).exists() {
-        fs::create_dir_all(&btrfs_bin_dir)?;
-        let _ = fs::remove_file(&btrfs_bin);
-        fs::copy(&current_exe, &btrfs_bin)?;
-        run_or_dry("chmod", &["+x", &btrfs_bin], false)?;
+    let btrfs_usr = format!("{}/@usr", config.mount.base);
+    let btrfs_bin = format!("{}/local/bin/wslarc", btrfs_usr);
+    let btrfs_bin_dir = format!("{}/local/bin", btrfs_usr);
+    if Path::new(&btrfs_usr).exists() {
+        if is_read_only(&btrfs_usr) {
+            warn(&format!(
+                "{} is on a read-only filesystem; skipping the @usr copy",
+                btrfs_usr
+            ));
+        } else {
+            fs::create_dir_all(&btrfs_bin_dir)?;
+            let _ = fs::remove_file(&btrfs_bin);
+            fs::copy(&current_exe, &btrfs_bin)
+                .with_context(|| format!("Failed to copy wslarc to {}", btrfs_bin))?;
+            runner.run_or_dry("chmod", &["+x", &btrfs_bin], false)?;
+        }
+    }
+
+    if root_read_only {
+        bail!(
+            "Cannot install {}: the root filesystem is read-only. \
+             Remount it read-write (mount -o remount,rw /) and re-run 'wslarc mount'.",
+            WSLARC_BIN
+        );
     }
 
     success(&format!("wslarc installed to {}", WSLARC_BIN));
@@ -156,171 +718,428 @@ fn update_wsl_conf(dry_run: bool) -> Result<()> {
         return Ok(());
     }
 
-    let mut conf = Ini::load_from_file(WSL_CONF).unwrap_or_else(|_| Ini::new());
-
-    if let Some(boot) = conf.section(Some("boot")) {
-        if let Some(cmd) = boot.get("command") {
-            if cmd == WSLARC_ATTACH_CMD {
-                success("wsl.conf already configured");
-                return Ok(());
-            }
-            warn(&format!("Overwriting existing [boot] command: {}", cmd));
+    let existing = match fs::read_to_string(WSL_CONF) {
+        Ok(content) => Some(content),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", WSL_CONF)),
+    };
+    let content = existing.as_deref().unwrap_or_default();
+    let previous = Ini::load_from_str(content).ok().and_then(|conf| {
+        conf.section(Some("boot"))
+            .and_then(|boot| boot.get("command"))
+            .map(str::to_string)
+    });
+    match previous.as_deref() {
+        Some(WSLARC_ATTACH_CMD) => {
+            success("wsl.conf already configured");
+            return Ok(());
         }
+        Some(cmd) => warn(&format!("Overwriting existing [boot] command: {}", cmd)),
+        None => {}
     }
 
-    conf.with_section(Some("boot"))
-        .set("command", WSLARC_ATTACH_CMD);
-
-    conf.write_to_file(WSL_CONF)?;
+    // Only the command line changes; comments and other settings are kept
+    let updated = with_boot_command(content, WSLARC_ATTACH_CMD);
+    if existing.is_some() {
+        fs::copy(WSL_CONF, WSL_CONF_BACKUP)
+            .with_context(|| format!("Failed to back up {}", WSL_CONF))?;
+        info(&format!(
+            "Previous {} saved as {}",
+            WSL_CONF, WSL_CONF_BACKUP
+        ));
+    }
+    fs::write(WSL_CONF, updated).with_context(|| format!("Failed to write {}", WSL_CONF))?;
     success("wsl.conf updated with boot command");
     Ok(())
 }
 
-fn generate_systemd_units(config: &Config, dry_run: bool) -> Result<()> {
+/// Rootless mode: write the units for inspection without installing anything
+fn generate_user_units(
+    runner: &dyn CommandRunner,
+    config: &Config,
+    writer: &mut FileWriter,
+) -> Result<()> {
+    let unit_dir = Scope::User.unit_dir();
+
+    step(1, 2, "Generate systemd mount units");
+    generate_systemd_units(runner, config, &unit_dir, writer)?;
+
+    step(2, 2, "Generate btrbk units");
+    generate_btrbk_units(config, &unit_dir, writer)?;
+
+    println!();
+    println!("{}", style("Unit generation complete!").green().bold());
+    println!();
+    info(&format!("Units written to {} for inspection", unit_dir));
+    info("Install them with: sudo wslarc mount");
+
+    Ok(())
+}
+
+fn generate_systemd_units(
+    runner: &dyn CommandRunner,
+    config: &Config,
+    unit_dir: &str,
+    writer: &mut FileWriter,
+) -> Result<()> {
     let mut units_to_verify = Vec::new();
+    let changed_before = writer.changed;
 
     // Base mount
     let base_content = systemd::generate_base_mount(config);
     let base_unit = systemd::mount_unit_filename(&config.mount.base);
-    write_systemd_unit(&base_unit, &base_content, dry_run)?;
-    units_to_verify.push(format!("{}/{}", SYSTEMD_DIR, base_unit));
+    writer.write_unit(unit_dir, &base_unit, &base_content)?;
+    units_to_verify.push(format!("{}/{}", unit_dir, base_unit));
     success(&format!("{} created", base_unit));
 
     // Backup subvolumes (A-class)
     info("Creating A-class (backup) mount units...");
-    for (subvol, backup) in &config.subvolumes.backup {
-        let content =
-            systemd::generate_subvol_mount(config, subvol, backup.mount(), backup.options());
+    for (subvol, backup) in config.sorted_backup() {
+        let content = systemd::generate_subvol_mount(
+            config,
+            subvol,
+            backup.mount(),
+            backup.options(),
+            backup.options_append(),
+        );
         let unit = systemd::mount_unit_filename(backup.mount());
-        write_systemd_unit(&unit, &content, dry_run)?;
-        units_to_verify.push(format!("{}/{}", SYSTEMD_DIR, unit));
+        writer.write_unit(unit_dir, &unit, &content)?;
+        units_to_verify.push(format!("{}/{}", unit_dir, unit));
     }
 
     // Transfer subvolumes (C-class)
     info("Creating C-class (transfer) mount units...");
-    for (subvol, transfer) in &config.subvolumes.transfer {
+    for (subvol, transfer) in config.sorted_transfer() {
         let content = systemd::generate_subvol_mount(
             config,
             subvol,
             &transfer.mount,
             transfer.options.as_deref(),
+            transfer.options_append.as_deref(),
         );
         let unit = systemd::mount_unit_filename(&transfer.mount);
-        write_systemd_unit(&unit, &content, dry_run)?;
-        units_to_verify.push(format!("{}/{}", SYSTEMD_DIR, unit));
+        writer.write_unit(unit_dir, &unit, &content)?;
+        units_to_verify.push(format!("{}/{}", unit_dir, unit));
     }
 
-    // Verify all units with systemd-analyze
-    if !dry_run {
+    // Verify all units with systemd-analyze (skipped when none changed)
+    if writer.live() && writer.changed > changed_before {
         info("Validating systemd units...");
         let mut args = vec!["verify"];
         let unit_refs: Vec<&str> = units_to_verify.iter().map(|s| s.as_str()).collect();
         args.extend(unit_refs);
-        run_or_dry("systemd-analyze", &args, false)?;
+        runner.run_or_dry("systemd-analyze", &args, false)?;
     }
 
     success("All mount units created and validated");
     Ok(())
 }
 
-fn generate_btrbk_config(config: &Config, dry_run: bool) -> Result<()> {
-    // Create /etc/btrbk directory
-    if !dry_run {
-        fs::create_dir_all("/etc/btrbk")?;
+fn generate_btrbk_config(
+    runner: &dyn CommandRunner,
+    config: &Config,
+    conf_path: &str,
+    local_only: bool,
+    writer: &mut FileWriter,
+) -> Result<()> {
+    // Generate btrbk.conf (write creates its directory as needed)
+    // include/exclude patterns are expanded against the subvolumes on disk
+    let present = if is_mountpoint(&config.mount.base) {
+        list_subvolumes(&config.mount.base).unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+    for warning in btrbk::coupling_warnings(config, &btrbk::snapshot_subvolumes(config, &present)) {
+        warn(&warning);
     }
-
-    // Generate btrbk.conf
-    let conf_content = btrbk::generate_config(config);
-    write_file(BTRBK_CONF, &conf_content, dry_run)?;
+    let conf_content = btrbk::generate_config(config, &present);
+    writer.write(conf_path, &conf_content)?;
 
     // Validate btrbk config syntax
-    if !dry_run {
+    if writer.live() {
         info("Validating btrbk.conf syntax...");
-        run_or_dry("btrbk", &["-c", BTRBK_CONF, "dryrun"], false)?;
+        let binary = btrbk::binary(config);
+        if local_only {
+            runner.run_or_dry(&binary, &["-c", conf_path, "-n", "snapshot"], false)?;
+        } else {
+            runner.run_or_dry(&binary, &["-c", conf_path, "dryrun"], false)?;
+        }
     }
     success("btrbk.conf created and validated");
 
+    Ok(())
+}
+
+/// Write btrbk.service and btrbk.timer; returns their paths for verification
+fn generate_btrbk_units(
+    config: &Config,
+    unit_dir: &str,
+    writer: &mut FileWriter,
+) -> Result<Vec<String>> {
     // Generate btrbk.service
-    let service_content = btrbk::generate_service(config);
-    write_systemd_unit("btrbk.service", &service_content, dry_run)?;
+    let service_content = btrbk::generate_service(config, &btrbk::binary(config));
+    writer.write_unit(unit_dir, "btrbk.service", &service_content)?;
     success("btrbk.service created");
 
     // Generate btrbk.timer
     let timer_content = btrbk::generate_timer(&config.btrbk.timer_schedule);
-    write_systemd_unit("btrbk.timer", &timer_content, dry_run)?;
+    writer.write_unit(unit_dir, "btrbk.timer", &timer_content)?;
     success("btrbk.timer created");
 
-    Ok(())
+    Ok(["btrbk.service", "btrbk.timer"]
+        .iter()
+        .map(|unit| format!("{}/{}", unit_dir, unit))
+        .collect())
 }
 
-fn enable_services(config: &Config, dry_run: bool) -> Result<()> {
-    // Reload systemd
-    run_or_dry("systemctl", &["daemon-reload"], dry_run)?;
-    success("systemd daemon reloaded");
-
-    // Enable base mount
-    let base_unit = systemd::mount_unit_filename(&config.mount.base);
-    run_or_dry("systemctl", &["enable", &base_unit], dry_run)?;
-
-    // Enable backup mounts
-    for backup in config.subvolumes.backup.values() {
-        let unit = systemd::mount_unit_filename(backup.mount());
-        run_or_dry("systemctl", &["enable", &unit], dry_run)?;
+/// Write a service and timer per scheduled task; returns their paths for
+/// verification
+fn generate_maintenance_units(
+    config: &Config,
+    unit_dir: &str,
+    writer: &mut FileWriter,
+) -> Result<Vec<String>> {
+    let mut units = Vec::new();
+    for (task, schedule) in maintenance::scheduled_tasks(config) {
+        let service = maintenance::generate_service(config, task, WSLARC_BIN);
+        writer.write_unit(unit_dir, &task.service_unit(), &service)?;
+        let timer = maintenance::generate_timer(task, schedule);
+        writer.write_unit(unit_dir, &task.timer_unit(), &timer)?;
+        success(&format!("{} created ({})", task.timer_unit(), schedule));
+        units.push(format!("{}/{}", unit_dir, task.service_unit()));
+        units.push(format!("{}/{}", unit_dir, task.timer_unit()));
     }
+    Ok(units)
+}
 
-    // Enable transfer mounts
-    for transfer in config.subvolumes.transfer.values() {
-        let unit = systemd::mount_unit_filename(&transfer.mount);
-        run_or_dry("systemctl", &["enable", &unit], dry_run)?;
+fn enable_services(
+    runner: &dyn CommandRunner,
+    config: &Config,
+    reload: bool,
+    dry_run: bool,
+) -> Result<()> {
+    // Reload systemd
+    if reload {
+        runner.run_or_dry("systemctl", &["daemon-reload"], dry_run)?;
+        success("systemd daemon reloaded");
+    } else {
+        info("No unit files changed; skipping daemon-reload");
     }
 
-    // Enable btrbk timer
-    run_or_dry("systemctl", &["enable", "btrbk.timer"], dry_run)?;
+    // Enable base, backup and transfer mounts plus the btrbk and maintenance timers
+    for (unit, _) in systemd::wanted_units(config) {
+        runner.run_or_dry("systemctl", &["enable", &unit], dry_run)?;
+    }
 
     success("All services enabled");
     Ok(())
 }
 
-fn write_file(path: &str, content: &str, dry_run: bool) -> Result<()> {
+/// Create the `.wants` symlinks `systemctl enable` would, for rootfs builds without systemd running
+fn link_services(config: &Config, dry_run: bool) -> Result<()> {
+    for (unit, wanted_by) in systemd::wanted_units(config) {
+        link_unit(&unit, wanted_by, dry_run)?;
+    }
+
+    success("Units linked; systemd will pick them up on next boot");
+    Ok(())
+}
+
+fn link_unit(unit: &str, wanted_by: &str, dry_run: bool) -> Result<()> {
+    let wants_dir = format!("{}/{}.wants", SYSTEM_UNIT_DIR, wanted_by);
+    let link = format!("{}/{}", wants_dir, unit);
+    let target = format!("{}/{}", SYSTEM_UNIT_DIR, unit);
+
     if dry_run {
-        info(&format!("[dry-run] Would write {}", path));
+        info(&format!("[dry-run] Would link {} -> {}", link, target));
         return Ok(());
     }
 
-    // Create parent directory if needed
-    if let Some(parent) = Path::new(path).parent() {
-        fs::create_dir_all(parent)?;
+    fs::create_dir_all(&wants_dir)?;
+    if fs::symlink_metadata(&link).is_ok() {
+        fs::remove_file(&link)?;
     }
-
-    fs::write(path, content)?;
+    std::os::unix::fs::symlink(&target, &link)?;
     Ok(())
 }
 
-/// Write systemd unit file to ext4 /etc
-fn write_systemd_unit(filename: &str, content: &str, dry_run: bool) -> Result<()> {
-    let path = format!("{}/{}", SYSTEMD_DIR, filename);
-    write_file(&path, content, dry_run)
-}
-
-fn setup_ext4_sync(config: &Config, dry_run: bool) -> Result<()> {
+fn setup_ext4_sync(config: &Config, writer: &mut FileWriter) -> Result<()> {
     let ext4_uuid = ext4_sync::get_ext4_root_uuid()
         .ok_or_else(|| anyhow::anyhow!("Could not get ext4 root UUID"))?;
     info(&format!("ext4 root UUID: {}", ext4_uuid));
 
     let mount_point = &config.ext4_sync.mount_point;
-    if !dry_run {
+    if writer.live() {
         fs::create_dir_all(mount_point)?;
     }
 
     let mount_unit = ext4_sync::generate_ext4_mount(config, &ext4_uuid);
     let mount_unit_name = ext4_sync::ext4_mount_unit_filename(config);
-    write_systemd_unit(&mount_unit_name, &mount_unit, dry_run)?;
+    writer.write_unit(SYSTEM_UNIT_DIR, &mount_unit_name, &mount_unit)?;
     success(&format!("{} created", mount_unit_name));
 
     let hook_targets = ext4_sync::collect_hook_targets()?;
     let hook = ext4_sync::generate_pacman_hook(&hook_targets);
-    write_file(PACMAN_HOOK_PATH, &hook, dry_run)?;
+    writer.write(PACMAN_HOOK_PATH, &hook)?;
     success("pacman hook created");
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::shell::MockRunner;
+
+    #[test]
+    fn check_only_verifies_btrbk_and_maintenance_units() {
+        let mut config = Config::default();
+        config.set_user("alice");
+        config.uuid = Some("1234".to_string());
+        config.maintenance.scrub_schedule = Some("monthly".to_string());
+        let runner = MockRunner::default();
+
+        check_only(&runner, &config, true).unwrap();
+
+        let verify = runner
+            .calls()
+            .into_iter()
+            .find(|call| call.contains("/btrbk.service"))
+            .expect("btrbk units verified");
+        assert!(verify.starts_with("systemd-analyze verify "), "{}", verify);
+        for unit in ["btrbk.timer", "wslarc-scrub.service", "wslarc-scrub.timer"] {
+            assert!(verify.contains(&format!("/{}", unit)), "{}", verify);
+        }
+    }
+
+    #[test]
+    fn enable_services_reloads_only_when_asked() {
+        let cfg = Config::default();
+        let enables: Vec<String> = systemd::wanted_units(&cfg)
+            .into_iter()
+            .map(|(unit, _)| format!("systemctl enable {}", unit))
+            .collect();
+
+        let runner = MockRunner::default();
+        enable_services(&runner, &cfg, false, false).unwrap();
+        assert_eq!(runner.calls(), enables);
+
+        let runner = MockRunner::default();
+        enable_services(&runner, &cfg, true, false).unwrap();
+        assert_eq!(runner.calls()[0], "systemctl daemon-reload");
+        assert_eq!(runner.calls()[1..], enables);
+    }
+
+    #[test]
+    fn emergency_mount_plan_orders_parents_first() {
+        let mut cfg = Config::default();
+        cfg.set_user("alice");
+        cfg.subvolumes.transfer.insert(
+            "@containers_user".to_string(),
+            crate::config::TransferSubvol {
+                mount: "/home/alice/.local/share/containers".to_string(),
+                nodatacow: true,
+                options: Some("noatime".to_string()),
+                options_append: None,
+            },
+        );
+
+        let plan = emergency_mount_plan(&cfg);
+        assert_eq!(
+            plan[0],
+            (cfg.mount.base.clone(), cfg.mount.effective_options())
+        );
+
+        let position = |target: &str| plan.iter().position(|(t, _)| t == target).unwrap();
+        assert!(position("/home/alice") < position("/home/alice/.local/share/containers"));
+        assert!(plan.contains(&(
+            "/home/alice/.local/share/containers".to_string(),
+            "subvol=@containers_user,noatime".to_string()
+        )));
+    }
+
+    #[test]
+    fn single_mount_unit_requires_a_configured_subvolume() {
+        let mut cfg = Config::default();
+        cfg.set_user("alice");
+
+        let (unit, content) = single_mount_unit(&cfg, "home").unwrap();
+        assert_eq!(unit, "home-alice.mount");
+        assert!(content.contains("subvol=@home"));
+        assert_eq!(single_mount_unit(&cfg, "@home").unwrap().0, unit);
+
+        let err = single_mount_unit(&cfg, "@missing").unwrap_err();
+        assert!(err.to_string().contains("@missing is not in"));
+    }
+
+    fn writer(only_new: bool) -> FileWriter {
+        FileWriter {
+            dry_run: false,
+            only_new,
+            changed: 0,
+            output_dir: None,
+        }
+    }
+
+    #[test]
+    fn file_writer_skips_identical_content_with_only_new() {
+        let dir = tempfile::tempdir().unwrap();
+        let unit_dir = dir.path().to_str().unwrap();
+
+        let mut first = writer(true);
+        first
+            .write_unit(unit_dir, "usr.mount", "[Mount]\n")
+            .unwrap();
+        assert_eq!(first.changed, 1);
+
+        let mut second = writer(true);
+        second
+            .write_unit(unit_dir, "usr.mount", "[Mount]\n")
+            .unwrap();
+        assert_eq!(second.changed, 0);
+
+        second
+            .write_unit(unit_dir, "usr.mount", "[Mount]\nWhat=x\n")
+            .unwrap();
+        assert_eq!(second.changed, 1);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("usr.mount")).unwrap(),
+            "[Mount]\nWhat=x\n"
+        );
+    }
+
+    #[test]
+    fn file_writer_always_writes_without_only_new() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("btrbk.conf");
+        fs::write(&path, "same").unwrap();
+
+        let mut writer = writer(false);
+        writer.write(path.to_str().unwrap(), "same").unwrap();
+        assert_eq!(writer.changed, 1);
+    }
+
+    #[test]
+    fn file_writer_mirrors_paths_below_output_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = format!("{}/", dir.path().display());
+
+        let mut writer = FileWriter {
+            output_dir: Some(root),
+            ..writer(false)
+        };
+        assert!(!writer.live());
+        writer
+            .write_unit(SYSTEM_UNIT_DIR, "usr.mount", "[Mount]\n")
+            .unwrap();
+        writer.write(PACMAN_HOOK_PATH, "[Trigger]\n").unwrap();
+
+        let unit = dir.path().join("etc/systemd/system/usr.mount");
+        assert_eq!(fs::read_to_string(unit).unwrap(), "[Mount]\n");
+        assert!(dir
+            .path()
+            .join("etc/pacman.d/hooks/sync-systemd-ext4.hook")
+            .is_file());
+        assert_eq!(writer.changed, 2);
+    }
+}
@@ -0,0 +1,139 @@
+//! Recover a lost config from the copy init saved into the @etc subvolume
+
+use anyhow::{bail, Context, Result};
+use console::style;
+use std::fs;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::utils::cli::{find_btrfs_device_by_label, is_mountpoint};
+use crate::utils::prompt::{self, confirm_or_yes, info, success, warn};
+use crate::utils::shell::CommandRunner;
+
+/// Where init saves a copy of the config, relative to the top-level subvolume
+const SAVED_CONFIG: &str = "@etc/wslarc/config.toml";
+
+/// Copy `{mount.base}/@etc/wslarc/config.toml` back to `config_path`
+///
+/// Uses the base volume when it is mounted; otherwise mounts the Btrfs device
+/// (`device`, or the one labeled `vhdx.label`) read-only at `mount.base` for
+/// the duration and unmounts it again.
+pub fn run(
+    config: &Config,
+    config_path: &str,
+    device: Option<&str>,
+    yes: bool,
+    runner: &dyn CommandRunner,
+) -> Result<()> {
+    println!("{}", style("Recover Config from @etc").bold().cyan());
+    println!();
+
+    let base = &config.mount.base;
+    let mounted_here = if is_mountpoint(base) {
+        info(&format!("Using mounted {}", base));
+        false
+    } else {
+        let device = match device {
+            Some(device) => device.to_string(),
+            None => find_btrfs_device_by_label(&config.vhdx.label)?.with_context(|| {
+                format!(
+                    "No Btrfs device with label '{}' found; run 'wslarc attach' or pass --device",
+                    config.vhdx.label
+                )
+            })?,
+        };
+        fs::create_dir_all(base)?;
+        runner.run("mount", &["-o", "ro,subvolid=5", &device, base])?;
+        info(&format!("Mounted {} read-only at {}", device, base));
+        true
+    };
+
+    let result = recover(base, config_path, yes);
+
+    if mounted_here {
+        if let Err(e) = runner.run("umount", &[base]) {
+            warn(&format!("Could not unmount {}: {:#}", base, e));
+        }
+    }
+    result
+}
+
+fn recover(base: &str, config_path: &str, yes: bool) -> Result<()> {
+    let saved_path = Path::new(base).join(SAVED_CONFIG);
+    let saved_path = saved_path.to_string_lossy();
+    if !Path::new(saved_path.as_ref()).is_file() {
+        bail!(
+            "{} not found; was the volume initialized with 'wslarc init'?",
+            saved_path
+        );
+    }
+    let saved = Config::load(&saved_path)
+        .with_context(|| format!("The saved copy at {} is not usable", saved_path))?;
+
+    prompt::section("Recovery Plan");
+    prompt::kv("From", &saved_path);
+    prompt::kv("To", config_path);
+    prompt::kv("UUID", saved.uuid.as_deref().unwrap_or("-"));
+    prompt::kv("User", &saved.user.name);
+    println!();
+
+    let overwrite = Path::new(config_path).exists();
+    if overwrite {
+        warn(&format!("{} exists and will be replaced", config_path));
+        if let Ok(existing) = Config::load(config_path) {
+            for change in existing.diff(&saved)? {
+                prompt::change(&change.key, change.old.as_deref(), change.new.as_deref());
+            }
+        }
+    }
+
+    if !confirm_or_yes("Write the recovered config?", !overwrite, yes)? {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    saved.save(config_path)?;
+    success(&format!("Recovered config saved to {}", config_path));
+    println!();
+    println!(
+        "Next step: {} to regenerate the mount units",
+        style("wslarc mount").cyan()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recover_copies_the_saved_config() {
+        let base = tempfile::tempdir().unwrap();
+        let mut saved = Config::default();
+        saved.set_user("alice");
+        saved.uuid = Some("2b1c5e9a-0d41-4c0e-9a53-0c8f3f0f5a11".to_string());
+        saved
+            .save(&base.path().join(SAVED_CONFIG).to_string_lossy())
+            .unwrap();
+
+        let target = base.path().join("etc/wslarc/config.toml");
+        let target = target.to_string_lossy();
+        recover(&base.path().to_string_lossy(), &target, true).unwrap();
+
+        let recovered = Config::load(&target).unwrap();
+        assert_eq!(recovered.uuid, saved.uuid);
+        assert_eq!(recovered.user.name, "alice");
+    }
+
+    #[test]
+    fn recover_fails_without_a_saved_copy() {
+        let base = tempfile::tempdir().unwrap();
+        let err = recover(
+            &base.path().to_string_lossy(),
+            "/nonexistent/config.toml",
+            true,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("not found"));
+    }
+}
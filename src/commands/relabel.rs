@@ -0,0 +1,114 @@
+use anyhow::{bail, Result};
+use console::style;
+
+use crate::config::Config;
+use crate::utils::cli::{
+    ensure_dependencies, find_btrfs_device_by_label, is_mountpoint, Dependency,
+};
+use crate::utils::prompt::{self, confirm_destructive, info, success};
+use crate::utils::shell::run_or_dry;
+
+/// btrfs stores the label in a 256-byte field including the trailing NUL
+const MAX_LABEL_BYTES: usize = 255;
+
+pub fn run(
+    config: &Config,
+    config_path: &str,
+    new_label: &str,
+    yes: bool,
+    dry_run: bool,
+) -> Result<()> {
+    println!("{}", style("Rename Btrfs Label").bold().cyan());
+
+    validate_label(new_label)?;
+
+    let old_label = &config.vhdx.label;
+    if new_label == old_label {
+        success(&format!("Label is already '{}'", new_label));
+        return Ok(());
+    }
+
+    ensure_dependencies(&[Dependency::new("btrfs-progs", &["btrfs"])])?;
+
+    // btrfs relabels a mounted filesystem through its mount point, otherwise the device
+    let target = if is_mountpoint(&config.mount.base) {
+        config.mount.base.clone()
+    } else if let Some(device) = find_btrfs_device_by_label(old_label)? {
+        device
+    } else {
+        bail!(
+            "No Btrfs device with label '{}' found. Run 'wslarc attach' first.",
+            old_label
+        );
+    };
+
+    prompt::section("Relabel Plan");
+    prompt::kv("Filesystem", &target);
+    prompt::kv("Current label", old_label);
+    prompt::kv("New label", new_label);
+    prompt::kv("Config", config_path);
+    println!();
+
+    if !confirm_destructive("Rename the filesystem label?", yes)? {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    run_or_dry(
+        "btrfs",
+        &["filesystem", "label", &target, new_label],
+        dry_run,
+    )?;
+
+    let mut cfg = config.clone();
+    cfg.vhdx.label = new_label.to_string();
+    if dry_run {
+        info(&format!("[dry-run] Would save to {}", config_path));
+    } else {
+        cfg.save(config_path)?;
+        success(&format!("Label changed to '{}'", new_label));
+    }
+
+    println!();
+    info(&format!(
+        "wslarc attach now looks for a Btrfs device labeled '{}'",
+        new_label
+    ));
+
+    Ok(())
+}
+
+fn validate_label(label: &str) -> Result<()> {
+    if label.is_empty() {
+        bail!("Label must not be empty");
+    }
+    if label.len() > MAX_LABEL_BYTES {
+        bail!(
+            "Label is {} bytes long; btrfs allows at most {}",
+            label.len(),
+            MAX_LABEL_BYTES
+        );
+    }
+    if label.chars().any(char::is_control) {
+        bail!("Label must not contain control characters or newlines");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_label_accepts_regular_names() {
+        assert!(validate_label("ArchBtrfs").is_ok());
+        assert!(validate_label("work disk-2").is_ok());
+    }
+
+    #[test]
+    fn validate_label_rejects_empty_long_and_control_chars() {
+        assert!(validate_label("").is_err());
+        assert!(validate_label(&"a".repeat(256)).is_err());
+        assert!(validate_label("bad\nlabel").is_err());
+    }
+}
@@ -1,13 +1,45 @@
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use console::style;
+use std::fs;
+use std::io;
 use std::path::Path;
 
+use crate::commands::hook_sync_systemd;
+use crate::commands::mount::has_usr_subvol;
 use crate::config::Config;
-use crate::utils::cli::{is_mountpoint, list_directory_names};
-use crate::utils::prompt::{confirm_or_yes, info, section, select, step, success, warn};
-use crate::utils::shell::run as shell_run;
+use crate::generators::systemd;
+use crate::utils::btrfs::{delete_subvolume_recursive, WritableBase};
+use crate::utils::cli::{
+    btrfs_du_total_bytes, btrfs_free_bytes, btrfs_used_bytes, format_bytes, is_mountpoint,
+    list_directory_names, parse_snapshot_name, processes_using, read_unit_property, show_subvolume,
+    user_ids,
+};
+use crate::utils::mount_options::append_options;
+use crate::utils::prompt::{confirm, confirm_or_yes, info, section, select, step, success, warn};
+use crate::utils::shell::CommandRunner;
+
+pub struct RestoreOptions {
+    /// Snapshot name to restore from (interactive selection when unset)
+    pub snapshot: Option<String>,
+    /// Rewrite restored data with the current compression option
+    pub recompress: bool,
+    /// Read every file in the snapshot before restoring
+    pub deep_check: bool,
+    /// Check the remounted subvolume is usable, offering a rollback if not
+    pub verify_after: bool,
+    /// Compare the snapshot size with the free space before restoring
+    pub check_space: bool,
+}
+
+pub fn run(
+    config: &Config,
+    yes: bool,
+    options: &RestoreOptions,
+    runner: &dyn CommandRunner,
+) -> Result<()> {
+    let snapshot = &options.snapshot;
+    let recompress = options.recompress;
 
-pub fn run(config: &Config, snapshot: Option<String>, yes: bool) -> Result<()> {
     println!("{}", style("Restore from Snapshot").bold().cyan());
     println!();
 
@@ -20,7 +52,7 @@ pub fn run(config: &Config, snapshot: Option<String>, yes: bool) -> Result<()> {
     }
 
     // Select snapshot
-    let selected = if let Some(ref name) = snapshot {
+    let selected = if let Some(name) = snapshot {
         if !snapshot_list.contains(name) {
             bail!("Snapshot '{}' not found", name);
         }
@@ -41,17 +73,20 @@ pub fn run(config: &Config, snapshot: Option<String>, yes: bool) -> Result<()> {
     info(&format!("Selected: {}", selected));
 
     // Parse snapshot name to get subvolume
-    // Format: subvol.YYYYMMDDTHHMMSS or subvol.YYYYMMDD (btrbk formats)
-    let parts: Vec<&str> = selected.rsplitn(2, '.').collect();
-    if parts.len() < 2 {
+    // Format: subvol.YYYYMMDDTHHMMSS or subvol.YYYYMMDD (btrbk formats),
+    // optionally followed by btrbk's _N collision counter
+    let Some((subvol_base, _)) = parse_snapshot_name(&selected) else {
         bail!("Invalid snapshot name format: {}", selected);
-    }
-    // rsplitn reverses order, so parts[1] is the subvol name
-    let subvol_base = parts[1];
+    };
     let subvol_name = format!("@{}", subvol_base);
 
     info(&format!("Target subvolume: {}", subvol_name));
 
+    // Never rename the live subvolume away before knowing the snapshot is usable
+    let source_snapshot = format!("{}/{}", snapshot_dir, selected);
+    verify_snapshot(&source_snapshot, options.deep_check)
+        .with_context(|| format!("Snapshot {} failed the integrity check", selected))?;
+
     // Verify the subvolume exists in config
     let is_backup_subvol = config.subvolumes.backup.contains_key(&subvol_name);
     let is_etc_subvol = subvol_name == "@etc";
@@ -80,6 +115,13 @@ pub fn run(config: &Config, snapshot: Option<String>, yes: bool) -> Result<()> {
     if let Some(ref mp) = mount_point {
         println!("  Mount point: {}", mp);
     }
+    let mount_options = restore_mount_options(config, &subvol_name);
+    let compression = recompress
+        .then(|| compression_algorithm(&mount_options))
+        .flatten();
+    if let Some(algorithm) = compression {
+        println!("  Recompress: {}", algorithm);
+    }
     println!();
 
     // Warn about destructive operation
@@ -90,13 +132,59 @@ pub fn run(config: &Config, snapshot: Option<String>, yes: bool) -> Result<()> {
     }
     println!();
 
+    if options.check_space {
+        check_space(config, &source_snapshot, yes)?;
+    }
+
     if !confirm_or_yes("Proceed with restore?", false, yes)? {
         println!("Aborted.");
         return Ok(());
     }
 
+    // systemd boots from the ext4 /etc, so restoring @etc alone changes
+    // nothing; copying it over is asked separately and never implied by --yes.
+    // Without @usr there is no ext4 sync setup to copy into
+    let ext4_root = &config.ext4_sync.mount_point;
+    let ext4_etc = format!("{}/etc", ext4_root);
+    let sync_etc = is_etc_subvol
+        && has_usr_subvol(config)
+        && confirm(
+            &format!(
+                "Also copy the restored /etc into the ext4 root ({})? \
+                 Files not in the snapshot are deleted there",
+                ext4_etc
+            ),
+            false,
+        )?;
+
+    if recompress && compression.is_none() {
+        warn("--recompress ignored: mount options do not enable compression");
+    }
+
+    // Fail before anything is swapped if the ext4 root cannot be reached
+    if sync_etc {
+        fs::create_dir_all(ext4_root).with_context(|| format!("Failed to create {}", ext4_root))?;
+        hook_sync_systemd::ensure_mounted(ext4_root, false)?;
+        if !Path::new(&ext4_etc).is_dir() {
+            bail!("{} not found; is {} the ext4 root?", ext4_etc, ext4_root);
+        }
+    }
+
+    // Services stopped to free the mount point; restarted when restore returns
+    let mut stopped = StoppedServices {
+        runner,
+        services: Vec::new(),
+    };
+    let _writable = WritableBase::acquire(config, runner)?;
+
     // Execute restore
-    let total_steps = if mount_point.is_some() { 5 } else { 3 };
+    let mut total_steps = if mount_point.is_some() { 5 } else { 3 };
+    if compression.is_some() {
+        total_steps += 1;
+    }
+    if sync_etc {
+        total_steps += 1;
+    }
     let mut current_step = 0;
 
     // Step 1: Unmount if needed
@@ -106,8 +194,10 @@ pub fn run(config: &Config, snapshot: Option<String>, yes: bool) -> Result<()> {
 
         // Check if mounted
         if is_mountpoint(mp) {
+            release_mount(runner, config, mp, yes, &mut stopped)?;
+
             // Try to unmount
-            match shell_run("umount", &[mp]) {
+            match runner.run("umount", &[mp]) {
                 Ok(_) => success("Unmounted successfully"),
                 Err(e) => {
                     warn(&format!("Failed to unmount: {}", e));
@@ -115,7 +205,7 @@ pub fn run(config: &Config, snapshot: Option<String>, yes: bool) -> Result<()> {
                     if !confirm_or_yes("Retry unmount?", true, yes)? {
                         bail!("Cannot proceed without unmounting {}", mp);
                     }
-                    shell_run("umount", &["-l", mp])?; // Lazy unmount as fallback
+                    runner.run("umount", &["-l", mp])?; // Lazy unmount as fallback
                     success("Lazy unmount completed");
                 }
             }
@@ -124,66 +214,55 @@ pub fn run(config: &Config, snapshot: Option<String>, yes: bool) -> Result<()> {
         }
     }
 
-    // Step 2: Rename current subvolume to .old
-    current_step += 1;
-    step(
-        current_step,
+    // Steps 2-4: swap the snapshot in for the current subvolume
+    swap_in_snapshot(
+        runner,
+        config,
+        &subvol_name,
+        &source_snapshot,
+        mount_point.as_deref(),
+        &mut current_step,
         total_steps,
-        &format!("Backup current {}", subvol_name),
-    );
-
+    )?;
     let current_subvol = format!("{}/{}", config.mount.base, subvol_name);
     let backup_subvol = format!("{}/{}.restore-backup", config.mount.base, subvol_name);
 
-    // Remove old backup if exists
-    if Path::new(&backup_subvol).exists() {
-        info("Removing old restore backup...");
-        shell_run("btrfs", &["subvolume", "delete", &backup_subvol])?;
+    if let Some(ref mp) = mount_point {
+        if options.verify_after {
+            if let Err(e) = verify_restored_mount(mp, &subvol_name) {
+                warn(&format!("Restored mount failed verification: {:#}", e));
+                if !confirm_or_yes(
+                    &format!("Roll back to {}.restore-backup?", subvol_name),
+                    true,
+                    yes,
+                )? {
+                    bail!(
+                        "Restored {} failed verification; the previous data is kept as {}",
+                        subvol_name,
+                        backup_subvol
+                    );
+                }
+                roll_back(runner, config, &subvol_name, Some(mp))?;
+                bail!(
+                    "Restored {} failed verification; rolled back to the previous subvolume",
+                    subvol_name
+                );
+            }
+            success(&format!("{} is mounted and readable", mp));
+        }
     }
 
-    // Rename current to backup
-    if Path::new(&current_subvol).exists() {
-        shell_run("mv", &[&current_subvol, &backup_subvol])?;
-        success(&format!("Backed up to {}.restore-backup", subvol_name));
-    } else {
-        info("Current subvolume not found, skipping backup");
+    // Optional: rewrite restored data with the current compression settings
+    if let Some(algorithm) = compression {
+        current_step += 1;
+        step(current_step, total_steps, "Recompress restored data");
+        recompress_subvolume(runner, config, &current_subvol, algorithm)?;
     }
 
-    // Step 3: Create snapshot from selected snapshot
-    current_step += 1;
-    step(
-        current_step,
-        total_steps,
-        &format!("Restore {} from snapshot", subvol_name),
-    );
-
-    let source_snapshot = format!("{}/{}", snapshot_dir, selected);
-    shell_run(
-        "btrfs",
-        &["subvolume", "snapshot", &source_snapshot, &current_subvol],
-    )?;
-    success("Snapshot restored");
-
-    // Step 4: Remount if needed
-    if let Some(ref mp) = mount_point {
+    if sync_etc {
         current_step += 1;
-        step(current_step, total_steps, &format!("Remount {}", mp));
-
-        // Get mount options from config
-        let uuid = config.uuid.as_deref().unwrap_or("");
-        let base_opts = config
-            .subvolumes
-            .backup
-            .get(&subvol_name)
-            .and_then(|b| b.options())
-            .unwrap_or(&config.mount.options);
-        let opts = format!("subvol={},{}", subvol_name, base_opts);
-
-        shell_run(
-            "mount",
-            &["-t", "btrfs", "-o", &opts, &format!("UUID={}", uuid), mp],
-        )?;
-        success("Remounted successfully");
+        step(current_step, total_steps, "Copy /etc into the ext4 root");
+        copy_etc_to_ext4(runner, &current_subvol, &ext4_etc)?;
     }
 
     // Step 5: Cleanup (optional)
@@ -199,7 +278,10 @@ pub fn run(config: &Config, snapshot: Option<String>, yes: bool) -> Result<()> {
         "  To delete it (free space): btrfs subvolume delete {}",
         backup_subvol
     );
-    println!("  To rollback: reverse the restore process");
+    println!("  To roll back:");
+    for command in rollback_commands(config, &subvol_name, mount_point.as_deref()) {
+        println!("    {}", command);
+    }
 
     // Done
     println!();
@@ -212,3 +294,598 @@ pub fn run(config: &Config, snapshot: Option<String>, yes: bool) -> Result<()> {
 
     Ok(())
 }
+
+/// Mirror the restored @etc into the ext4 root's /etc, keeping ownership,
+/// ACLs and xattrs
+fn copy_etc_to_ext4(runner: &dyn CommandRunner, etc_subvol: &str, ext4_etc: &str) -> Result<()> {
+    runner
+        .run(
+            "rsync",
+            &[
+                "-aAX",
+                "--delete",
+                &format!("{}/", etc_subvol),
+                &format!("{}/", ext4_etc),
+            ],
+        )
+        .with_context(|| format!("Failed to copy {} into {}", etc_subvol, ext4_etc))?;
+    success(&format!("{} now matches the snapshot", ext4_etc));
+    Ok(())
+}
+
+/// Move the current subvolume to `.restore-backup`, snapshot the source in
+/// its place and remount it
+///
+/// A failure after the move would leave no live subvolume, so the backup is
+/// moved back before the error is returned.
+fn swap_in_snapshot(
+    runner: &dyn CommandRunner,
+    config: &Config,
+    subvol_name: &str,
+    source_snapshot: &str,
+    mount_point: Option<&str>,
+    current_step: &mut u32,
+    total_steps: u32,
+) -> Result<()> {
+    *current_step += 1;
+    step(
+        *current_step,
+        total_steps,
+        &format!("Backup current {}", subvol_name),
+    );
+
+    let current_subvol = format!("{}/{}", config.mount.base, subvol_name);
+    let backup_subvol = format!("{}/{}.restore-backup", config.mount.base, subvol_name);
+
+    // Remove old backup if exists
+    if Path::new(&backup_subvol).exists() {
+        info("Removing old restore backup...");
+        delete_subvolume_recursive(&backup_subvol)?;
+    }
+
+    // Rename current to backup
+    let backed_up = Path::new(&current_subvol).exists();
+    if backed_up {
+        runner.run("mv", &[&current_subvol, &backup_subvol])?;
+        success(&format!("Backed up to {}.restore-backup", subvol_name));
+    } else {
+        info("Current subvolume not found, skipping backup");
+    }
+
+    let restored = (|| {
+        *current_step += 1;
+        step(
+            *current_step,
+            total_steps,
+            &format!("Restore {} from snapshot", subvol_name),
+        );
+        runner.run(
+            "btrfs",
+            &["subvolume", "snapshot", source_snapshot, &current_subvol],
+        )?;
+        success("Snapshot restored");
+
+        if let Some(mp) = mount_point {
+            *current_step += 1;
+            step(*current_step, total_steps, &format!("Remount {}", mp));
+            mount_subvolume(runner, config, subvol_name, mp)?;
+            success("Remounted successfully");
+        }
+        Ok(())
+    })();
+
+    let Err(e) = restored else {
+        return Ok(());
+    };
+    if !backed_up {
+        return Err(e);
+    }
+    warn(&format!("Restore failed: {:#}", e));
+    match roll_back(runner, config, subvol_name, mount_point) {
+        Ok(()) => Err(e.context(format!(
+            "Restore of {} failed; rolled back to the previous subvolume",
+            subvol_name
+        ))),
+        Err(rollback) => Err(e.context(format!(
+            "Restore of {} failed and so did the rollback ({:#}); the previous data is in {}",
+            subvol_name, rollback, backup_subvol
+        ))),
+    }
+}
+
+/// The restored subvolume shares extents with the snapshot, and the current
+/// one stays as .restore-backup, so every block rewritten afterwards (or by
+/// --recompress) needs new space: up to the snapshot's full size
+fn check_space(config: &Config, source_snapshot: &str, yes: bool) -> Result<()> {
+    info("Estimating space needed...");
+    let needed = btrfs_du_total_bytes(source_snapshot)?;
+    let free = btrfs_free_bytes(&config.mount.base)?;
+    info(&format!(
+        "Snapshot size: {}, free (estimated): {}",
+        format_bytes(needed),
+        format_bytes(free)
+    ));
+    if free >= needed {
+        success("Enough free space to keep both copies");
+        return Ok(());
+    }
+
+    warn(&format!(
+        "Only {} free; rewriting the restored data could need up to {}",
+        format_bytes(free),
+        format_bytes(needed)
+    ));
+    // --yes would confirm the risk silently, so it aborts instead
+    if yes || !confirm("Restore anyway?", false)? {
+        bail!("Not enough free space for a safe restore; free space or delete old snapshots first");
+    }
+    Ok(())
+}
+
+/// Services stopped so a mount point can be unmounted; dropping restarts them,
+/// so they come back whether the restore succeeds or bails
+struct StoppedServices<'a> {
+    runner: &'a dyn CommandRunner,
+    services: Vec<String>,
+}
+
+impl Drop for StoppedServices<'_> {
+    fn drop(&mut self) {
+        for service in &self.services {
+            match self.runner.run("systemctl", &["start", service]) {
+                Ok(_) => success(&format!("{} restarted", service)),
+                Err(e) => warn(&format!("Failed to restart {}: {}", service, e)),
+            }
+        }
+    }
+}
+
+/// Show what keeps `mount_point` busy and offer to stop the user sessions on it
+fn release_mount(
+    runner: &dyn CommandRunner,
+    config: &Config,
+    mount_point: &str,
+    yes: bool,
+    stopped: &mut StoppedServices<'_>,
+) -> Result<()> {
+    if let Ok(cwd) = std::env::current_dir() {
+        if cwd.starts_with(mount_point) {
+            warn(&format!(
+                "The current directory {} is inside {}; cd / first",
+                cwd.display(),
+                mount_point
+            ));
+        }
+    }
+
+    let holders = processes_using(mount_point);
+    if holders.is_empty() {
+        return Ok(());
+    }
+    warn(&format!(
+        "{} process(es) are using {}:",
+        holders.len(),
+        mount_point
+    ));
+    for (pid, comm) in holders.iter().take(10) {
+        println!("    {} {}", pid, comm);
+    }
+    if holders.len() > 10 {
+        println!("    ... and {} more", holders.len() - 10);
+    }
+
+    let services: Vec<String> = users_with_home_in(config, mount_point)
+        .into_iter()
+        .filter_map(|user| user_ids(&user).ok())
+        .map(|(uid, _)| format!("user@{}.service", uid))
+        .filter(|service| read_unit_property(service, "ActiveState").as_deref() == Some("active"))
+        .collect();
+    if services.is_empty() {
+        return Ok(());
+    }
+    if !confirm_or_yes(
+        &format!(
+            "Stop {} before unmounting? They are restarted after the restore.",
+            services.join(", ")
+        ),
+        true,
+        yes,
+    )? {
+        return Ok(());
+    }
+
+    for service in services {
+        runner.run("systemctl", &["stop", &service])?;
+        success(&format!("{} stopped", service));
+        stopped.services.push(service);
+    }
+    Ok(())
+}
+
+/// Users whose home is, contains or lies inside `mount_point`
+fn users_with_home_in(config: &Config, mount_point: &str) -> Vec<String> {
+    let mount_point = Path::new(mount_point);
+    config
+        .users()
+        .into_iter()
+        .filter(|user| {
+            let home = format!("/home/{}", user);
+            let home = Path::new(&home);
+            home.starts_with(mount_point) || mount_point.starts_with(home)
+        })
+        .collect()
+}
+
+/// Mount one subvolume at `mount_point` with the options its unit would use
+fn mount_subvolume(
+    runner: &dyn CommandRunner,
+    config: &Config,
+    subvol_name: &str,
+    mount_point: &str,
+) -> Result<()> {
+    let uuid = config.uuid.as_deref().unwrap_or("");
+    let base_opts = restore_mount_options(config, subvol_name);
+    let opts = format!("subvol={},{}", subvol_name, base_opts);
+
+    runner.run(
+        "mount",
+        &[
+            "-t",
+            "btrfs",
+            "-o",
+            &opts,
+            &format!("UUID={}", uuid),
+            mount_point,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Confirm the remounted subvolume is a mountpoint with readable contents
+fn verify_restored_mount(mount_point: &str, subvol_name: &str) -> Result<()> {
+    if !is_mountpoint(mount_point) {
+        bail!("{} is not mounted", mount_point);
+    }
+    check_restored_contents(Path::new(mount_point), subvol_name)
+}
+
+/// A file every healthy copy of a system subvolume contains
+fn sentinel_path(subvol_name: &str) -> Option<&'static str> {
+    match subvol_name {
+        "@usr" => Some("bin/env"),
+        "@var_lib_pacman" => Some("local"),
+        _ => None,
+    }
+}
+
+fn check_restored_contents(dir: &Path, subvol_name: &str) -> Result<()> {
+    fs::read_dir(dir).with_context(|| format!("Cannot list {}", dir.display()))?;
+    if let Some(sentinel) = sentinel_path(subvol_name) {
+        let path = dir.join(sentinel);
+        if !path.exists() {
+            bail!("{} is missing", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Commands that undo a finished restore by hand, the same steps `roll_back` runs
+fn rollback_commands(config: &Config, subvol_name: &str, mount_point: Option<&str>) -> Vec<String> {
+    let current_subvol = format!("{}/{}", config.mount.base, subvol_name);
+    let backup_subvol = format!("{}/{}.restore-backup", config.mount.base, subvol_name);
+    let mut commands = Vec::new();
+    if let Some(mp) = mount_point {
+        commands.push(format!("umount {}", mp));
+    }
+    // -R (btrfs-progs 6.12+) also deletes nested subvolumes such as excluded caches
+    commands.push(format!("btrfs subvolume delete -R {}", current_subvol));
+    commands.push(format!("mv {} {}", backup_subvol, current_subvol));
+    if let Some(mp) = mount_point {
+        commands.push(format!(
+            "systemctl start {}",
+            systemd::mount_unit_filename(mp)
+        ));
+    }
+    commands
+}
+
+/// Put `<subvol>.restore-backup` back in place of the restored subvolume
+fn roll_back(
+    runner: &dyn CommandRunner,
+    config: &Config,
+    subvol_name: &str,
+    mount_point: Option<&str>,
+) -> Result<()> {
+    let current_subvol = format!("{}/{}", config.mount.base, subvol_name);
+    let backup_subvol = format!("{}/{}.restore-backup", config.mount.base, subvol_name);
+    if !Path::new(&backup_subvol).exists() {
+        bail!("Cannot roll back: {} does not exist", backup_subvol);
+    }
+
+    if let Some(mp) = mount_point {
+        if is_mountpoint(mp) {
+            runner.run("umount", &[mp])?;
+        }
+    }
+    if Path::new(&current_subvol).exists() {
+        delete_subvolume_recursive(&current_subvol)?;
+    }
+    runner.run("mv", &[&backup_subvol, &current_subvol])?;
+    if let Some(mp) = mount_point {
+        mount_subvolume(runner, config, subvol_name, mp)?;
+    }
+    success(&format!(
+        "Rolled back {} to the previous subvolume",
+        subvol_name
+    ));
+    Ok(())
+}
+
+/// Confirm the snapshot is a read-only subvolume and optionally that all file data is readable
+fn verify_snapshot(path: &str, deep_check: bool) -> Result<()> {
+    let subvolume = show_subvolume(path)?;
+    if !subvolume.readonly {
+        bail!("{} is not a read-only snapshot", path);
+    }
+    success("Snapshot is a valid read-only subvolume");
+
+    if deep_check {
+        info("Reading all snapshot data (checksums are verified on read)...");
+        let files = read_all_files(Path::new(path))?;
+        success(&format!("Read {} files without errors", files));
+    }
+    Ok(())
+}
+
+/// Read every regular file below `path`; btrfs reports checksum failures as I/O errors
+fn read_all_files(path: &Path) -> Result<u64> {
+    let mut files = 0;
+    for entry in fs::read_dir(path).with_context(|| format!("Cannot read {}", path.display()))? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let entry_path = entry.path();
+
+        if file_type.is_dir() {
+            files += read_all_files(&entry_path)?;
+        } else if file_type.is_file() {
+            let mut file = fs::File::open(&entry_path)
+                .with_context(|| format!("Cannot open {}", entry_path.display()))?;
+            io::copy(&mut file, &mut io::sink())
+                .with_context(|| format!("Read error in {}", entry_path.display()))?;
+            files += 1;
+        }
+    }
+    Ok(files)
+}
+
+/// Mount options that apply to a restored subvolume
+fn restore_mount_options(config: &Config, subvol_name: &str) -> String {
+    let backup = config.subvolumes.backup.get(subvol_name);
+    let base = backup
+        .and_then(|b| b.options())
+        .map(str::to_string)
+        .unwrap_or_else(|| config.mount.effective_options());
+    match backup.and_then(|b| b.options_append()) {
+        Some(append) => append_options(&base, append),
+        None => base,
+    }
+}
+
+/// Extract the algorithm from `compress=`/`compress-force=` for `btrfs filesystem defragment -c`
+fn compression_algorithm(options: &str) -> Option<&str> {
+    options.split(',').find_map(|option| {
+        let value = option
+            .strip_prefix("compress-force=")
+            .or_else(|| option.strip_prefix("compress="))?;
+        let algorithm = value.split(':').next().unwrap_or(value);
+        (!algorithm.is_empty() && algorithm != "no" && algorithm != "none").then_some(algorithm)
+    })
+}
+
+/// Btrfs does not recompress existing extents on mount, so rewrite them explicitly
+fn recompress_subvolume(
+    runner: &dyn CommandRunner,
+    config: &Config,
+    path: &str,
+    algorithm: &str,
+) -> Result<()> {
+    warn("Recompressing unshares extents with snapshots and may increase space usage");
+
+    let before = btrfs_used_bytes(&config.mount.base).ok();
+    runner.run(
+        "btrfs",
+        &[
+            "filesystem",
+            "defragment",
+            "-r",
+            &format!("-c{}", algorithm),
+            path,
+        ],
+    )?;
+    let after = btrfs_used_bytes(&config.mount.base).ok();
+
+    match (before, after) {
+        (Some(before), Some(after)) if after <= before => {
+            success(&format!(
+                "Space reclaimed: {}",
+                format_bytes(before - after)
+            ));
+        }
+        (Some(before), Some(after)) => {
+            warn(&format!(
+                "Space usage grew by {} (extents no longer shared with snapshots)",
+                format_bytes(after - before)
+            ));
+        }
+        _ => success("Recompression complete"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::shell::MockRunner;
+
+    #[test]
+    fn rollback_commands_undo_the_swap() {
+        let config = Config::default();
+        assert_eq!(
+            rollback_commands(&config, "@home", Some("/home")),
+            vec![
+                "umount /home",
+                "btrfs subvolume delete -R /mnt/btrfs/@home",
+                "mv /mnt/btrfs/@home.restore-backup /mnt/btrfs/@home",
+                "systemctl start home.mount",
+            ]
+        );
+        // @etc is never mounted
+        assert_eq!(rollback_commands(&config, "@etc", None).len(), 2);
+    }
+
+    #[test]
+    fn compression_algorithm_reads_compress_and_compress_force() {
+        assert_eq!(
+            compression_algorithm("compress=zstd:3,noatime,nofail"),
+            Some("zstd")
+        );
+        assert_eq!(
+            compression_algorithm("noatime,compress-force=lzo"),
+            Some("lzo")
+        );
+        assert_eq!(compression_algorithm("noatime,nofail"), None);
+        assert_eq!(compression_algorithm("compress=no"), None);
+    }
+
+    #[test]
+    fn read_all_files_counts_nested_regular_files() {
+        let tempdir = tempfile::tempdir().unwrap();
+        fs::create_dir(tempdir.path().join("nested")).unwrap();
+        fs::write(tempdir.path().join("a"), "a").unwrap();
+        fs::write(tempdir.path().join("nested/b"), "b").unwrap();
+
+        assert_eq!(read_all_files(tempdir.path()).unwrap(), 2);
+    }
+
+    #[test]
+    fn check_restored_contents_requires_sentinel_for_system_subvolumes() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(check_restored_contents(dir.path(), "@opt").is_ok());
+
+        let err = check_restored_contents(dir.path(), "@usr").unwrap_err();
+        assert!(err.to_string().contains("bin/env is missing"));
+
+        fs::create_dir_all(dir.path().join("bin")).unwrap();
+        fs::write(dir.path().join("bin/env"), "").unwrap();
+        assert!(check_restored_contents(dir.path(), "@usr").is_ok());
+
+        assert!(check_restored_contents(&dir.path().join("gone"), "@opt").is_err());
+    }
+
+    /// Moves directories for `mv` like the real command and fails everything else
+    struct FailingSnapshotRunner;
+
+    impl CommandRunner for FailingSnapshotRunner {
+        fn run(&self, cmd: &str, args: &[&str]) -> Result<String> {
+            match (cmd, args) {
+                ("mv", [from, to]) => {
+                    fs::rename(from, to)?;
+                    Ok(String::new())
+                }
+                _ => bail!("Command failed: {} {}", cmd, args.join(" ")),
+            }
+        }
+    }
+
+    #[test]
+    fn swap_in_snapshot_snapshots_and_remounts() {
+        let base = tempfile::tempdir().unwrap();
+        let mut cfg = Config::default();
+        cfg.mount.base = base.path().to_string_lossy().to_string();
+        cfg.uuid = Some("1234".to_string());
+        let current = format!("{}/@data", cfg.mount.base);
+        fs::create_dir(&current).unwrap();
+
+        let runner = MockRunner::default();
+        let mut current_step = 0;
+        swap_in_snapshot(
+            &runner,
+            &cfg,
+            "@data",
+            "/snapshots/data.1",
+            Some("/srv/data"),
+            &mut current_step,
+            4,
+        )
+        .unwrap();
+
+        assert_eq!(current_step, 3);
+        assert_eq!(
+            runner.calls(),
+            vec![
+                format!("mv {} {}.restore-backup", current, current),
+                format!("btrfs subvolume snapshot /snapshots/data.1 {}", current),
+                format!(
+                    "mount -t btrfs -o subvol=@data,{} UUID=1234 /srv/data",
+                    cfg.mount.effective_options()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn swap_in_snapshot_rolls_back_when_snapshot_fails() {
+        let base = tempfile::tempdir().unwrap();
+        let mut cfg = Config::default();
+        cfg.mount.base = base.path().to_string_lossy().to_string();
+        let current = base.path().join("@data");
+        fs::create_dir(&current).unwrap();
+        fs::write(current.join("keep"), "live").unwrap();
+
+        let mut current_step = 0;
+        let err = swap_in_snapshot(
+            &FailingSnapshotRunner,
+            &cfg,
+            "@data",
+            "/snapshots/data.20240115T0300",
+            None,
+            &mut current_step,
+            3,
+        )
+        .unwrap_err();
+
+        assert!(format!("{:#}", err).contains("rolled back to the previous subvolume"));
+        assert_eq!(fs::read_to_string(current.join("keep")).unwrap(), "live");
+        assert!(!base.path().join("@data.restore-backup").exists());
+    }
+
+    #[test]
+    fn copy_etc_to_ext4_mirrors_the_restored_subvolume() {
+        let runner = MockRunner::default();
+        copy_etc_to_ext4(&runner, "/mnt/btrfs/@etc", "/mnt/ext4-root/etc").unwrap();
+        assert_eq!(
+            runner.calls(),
+            vec!["rsync -aAX --delete /mnt/btrfs/@etc/ /mnt/ext4-root/etc/"]
+        );
+
+        let runner = MockRunner::default().with_failure("rsync", "rsync error");
+        let err = copy_etc_to_ext4(&runner, "/mnt/btrfs/@etc", "/mnt/ext4-root/etc").unwrap_err();
+        assert!(err.to_string().contains("Failed to copy /mnt/btrfs/@etc"));
+    }
+
+    #[test]
+    fn users_with_home_in_matches_home_and_parents() {
+        let mut cfg = Config::default();
+        cfg.user.extra = vec!["bob".to_string()];
+        cfg.set_user("alice");
+
+        assert_eq!(users_with_home_in(&cfg, "/home/alice"), vec!["alice"]);
+        assert_eq!(users_with_home_in(&cfg, "/home"), vec!["alice", "bob"]);
+        assert_eq!(
+            users_with_home_in(&cfg, "/home/bob/.local/share/containers"),
+            vec!["bob"]
+        );
+        assert!(users_with_home_in(&cfg, "/usr").is_empty());
+        assert!(users_with_home_in(&cfg, "/home/alicia").is_empty());
+    }
+}
@@ -1,31 +1,129 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use chrono::NaiveDateTime;
 use console::style;
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::fs;
+use std::hash::Hasher;
+use std::io::Read;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
 
+use crate::commands::status::OutputFormat;
 use crate::config::Config;
-use crate::utils::cli::{ensure_dependencies, list_directory_names, Dependency};
-use crate::utils::prompt::{info, success};
-use crate::utils::shell::{run as shell_run, run_with_output};
+use crate::generators::btrbk;
+use crate::utils::btrfs::WritableBase;
+use crate::utils::cli::{
+    ensure_dependencies, find_unreachable_ssh_targets, list_directory_names, list_subvolumes,
+    parse_snapshot_name, parse_snapshot_subvolume, parse_ssh_target, show_subvolume,
+    unique_snapshot_name, Dependency,
+};
+use crate::utils::prompt::{confirm_or_yes, info, success, warn};
+use crate::utils::shell::{run as shell_run, run_or_dry, run_with_output, RealRunner};
 
-pub fn run(config: &Config) -> Result<()> {
-    println!("{}", style("Creating Btrfs Snapshot").bold().cyan());
-    println!();
+/// Create snapshots; `quiet` prints only the names of new snapshots, one per line
+///
+/// With `concurrency` above 1, all snapshots are still taken by one btrbk
+/// run, then the backups are sent by up to `concurrency` btrbk processes,
+/// one subvolume each. `dry_run` prints btrbk's plan and changes nothing.
+pub fn run(
+    config: &Config,
+    yes: bool,
+    quiet: bool,
+    concurrency: usize,
+    dry_run: bool,
+) -> Result<()> {
+    if !quiet {
+        println!("{}", style("Creating Btrfs Snapshot").bold().cyan());
+        println!();
+    }
+
+    btrbk::ensure_binary(config)?;
+    let mut dependencies = vec![Dependency::new("rsync", &["rsync"])];
+    let has_ssh_targets = config
+        .btrbk
+        .targets
+        .iter()
+        .any(|target| parse_ssh_target(target).is_some());
+    if has_ssh_targets {
+        dependencies.push(Dependency::new("openssh", &["ssh"]));
+    }
+    ensure_dependencies(&dependencies)?;
 
-    ensure_dependencies(&[
-        Dependency::new("btrbk", &["btrbk"]),
-        Dependency::new("rsync", &["rsync"]),
-    ])?;
+    // Check remote targets up front instead of letting btrbk hang on them
+    let unreachable = find_unreachable_ssh_targets(&config.btrbk.targets);
+    let local_only = !unreachable.is_empty();
+    if local_only {
+        for target in &unreachable {
+            warn(&format!("btrbk target unreachable: {}", target));
+        }
+        if !confirm_or_yes("Create local snapshots only (no backups)?", true, yes)? {
+            println!("Aborted.");
+            return Ok(());
+        }
+    }
 
     // Sync /etc to @etc before snapshot
-    info("Syncing /etc to @etc...");
     let etc_target = format!("{}/{}", config.mount.base, "@etc");
-    run_with_output(
-        "rsync",
-        &["-aAX", "--delete", "/etc/", &format!("{}/", etc_target)],
-    )?;
+    let rsync_args = ["-aAX", "--delete", "/etc/", &format!("{}/", etc_target)];
+    let binary = btrbk::binary(config);
+    let conf_path = btrbk::config_path(config);
+
+    if dry_run {
+        run_or_dry("rsync", &rsync_args, true)?;
+        info("btrbk plan (nothing is created or sent):");
+        // dryrun covers the sends too, so --concurrency changes nothing here
+        let action: &[&str] = if local_only {
+            &["--dry-run", "snapshot"]
+        } else {
+            &["dryrun"]
+        };
+        let mut args = vec!["-c", conf_path, "-v"];
+        args.extend(action);
+        run_with_output(&binary, &args)?;
+        return Ok(());
+    }
+
+    let snapshot_dir = format!("{}/{}", config.mount.base, config.btrbk.snapshot_dir);
+    let before = list_directory_names(&snapshot_dir).unwrap_or_default();
+    let _writable = WritableBase::acquire(config, &RealRunner)?;
+    let parallel = concurrency > 1 && !local_only && !config.btrbk.targets.is_empty();
+    let btrbk_action = if local_only || parallel {
+        "snapshot"
+    } else {
+        "run"
+    };
+
+    if quiet {
+        shell_run("rsync", &rsync_args)?;
+        shell_run(&binary, &["-c", conf_path, btrbk_action])?;
+        if parallel {
+            send_backups(config, concurrency, true)?;
+        }
+
+        let after = list_directory_names(&snapshot_dir)?;
+        for name in new_entries(&before, &after) {
+            println!("{}", name);
+        }
+        return Ok(());
+    }
+
+    info("Syncing /etc to @etc...");
+    run_with_output("rsync", &rsync_args)?;
     success("/etc synced to @etc");
 
     info("Running btrbk...");
-    run_with_output("btrbk", &["-v", "run"])?;
+    run_with_output(&binary, &["-c", conf_path, "-v", btrbk_action])?;
+    if parallel {
+        info(&format!(
+            "Sending backups with up to {} btrbk processes...",
+            concurrency
+        ));
+        send_backups(config, concurrency, quiet)?;
+    }
 
     success("Snapshot created");
     println!();
@@ -34,14 +132,130 @@ pub fn run(config: &Config) -> Result<()> {
     Ok(())
 }
 
-pub fn list(config: &Config) -> Result<()> {
+/// Send the backups of the snapshots just taken, one `btrbk resume` per subvolume
+///
+/// The lockfile from btrbk.conf is held for the whole fan-out, so a scheduled
+/// btrbk run cannot transfer or prune the same targets meanwhile. Inside it,
+/// each process gets its own lockfile; the global one would let only a single
+/// process run at a time.
+fn send_backups(config: &Config, concurrency: usize, quiet: bool) -> Result<()> {
+    let _lock = lock_btrbk()?;
+    let present = list_subvolumes(&config.mount.base).unwrap_or_default();
+    let subvolumes = btrbk::snapshot_subvolumes(config, &present);
+    let binary = btrbk::binary(config);
+    let conf_path = btrbk::config_path(config);
+    let next = AtomicUsize::new(0);
+    let failed = Mutex::new(Vec::new());
+
+    thread::scope(|scope| {
+        for _ in 0..concurrency.min(subvolumes.len()) {
+            scope.spawn(|| {
+                while let Some(subvol) = subvolumes.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let filter = format!("{}/{}", config.mount.base, subvol);
+                    let lockfile = format!("--lockfile=/var/lock/btrbk-{}.lock", subvol);
+                    let args = ["-c", conf_path, &lockfile, "resume", &filter];
+                    match shell_run(&binary, &args) {
+                        Ok(_) if !quiet => success(&format!("{} sent", subvol)),
+                        Ok(_) => {}
+                        Err(e) => {
+                            warn(&format!("{}: {}", subvol, e));
+                            failed.lock().unwrap().push(subvol.clone());
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    let failed = failed.into_inner().unwrap();
+    if !failed.is_empty() {
+        bail!(
+            "Backups failed for: {}. Snapshots were created; 'btrbk resume' retries the transfer.",
+            failed.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Take btrbk's global lock (btrbk flocks it too), failing when another btrbk
+/// run such as btrbk.service holds it; released when the file is dropped
+fn lock_btrbk() -> Result<fs::File> {
+    let lock = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(btrbk::LOCKFILE)
+        .with_context(|| format!("Failed to open {}", btrbk::LOCKFILE))?;
+    match lock.try_lock() {
+        Ok(()) => Ok(lock),
+        Err(fs::TryLockError::WouldBlock) => bail!(
+            "Another btrbk run (e.g. btrbk.service) holds {}; snapshots were created, \
+             retry the backups with 'btrbk resume' once it has finished",
+            btrbk::LOCKFILE
+        ),
+        Err(fs::TryLockError::Error(e)) => {
+            Err(e).with_context(|| format!("Failed to lock {}", btrbk::LOCKFILE))
+        }
+    }
+}
+
+/// Entries present in `after` but not in `before`, keeping `after`'s order
+fn new_entries(before: &[String], after: &[String]) -> Vec<String> {
+    after
+        .iter()
+        .filter(|name| !before.contains(name))
+        .cloned()
+        .collect()
+}
+
+/// One entry of `snapshot list --format json`
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct SnapshotInfo {
+    pub subvol: String,
+    pub timestamp: String,
+    pub name: String,
+}
+
+/// Snapshots in the snapshot directory, oldest first; entries that are not
+/// btrbk snapshot names are skipped
+fn snapshot_infos(entries: &[String]) -> Vec<SnapshotInfo> {
+    let mut infos: Vec<SnapshotInfo> = entries
+        .iter()
+        .filter_map(|name| {
+            parse_snapshot_name(name).map(|(subvol, stamp)| SnapshotInfo {
+                subvol: subvol.to_string(),
+                timestamp: stamp.format("%Y-%m-%dT%H:%M:%S").to_string(),
+                name: name.clone(),
+            })
+        })
+        .collect();
+    infos.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then(a.name.cmp(&b.name)));
+    infos
+}
+
+/// List snapshots; `--format json` prints them as a JSON array of `SnapshotInfo`
+pub fn list(config: &Config, format: OutputFormat) -> Result<()> {
+    if format == OutputFormat::Json {
+        let snapshot_dir = format!("{}/{}", config.mount.base, config.btrbk.snapshot_dir);
+        let entries = list_directory_names(&snapshot_dir)
+            .with_context(|| format!("Failed to list {}", snapshot_dir))?;
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&snapshot_infos(&entries))?
+        );
+        return Ok(());
+    }
+
     println!("{}", style("Btrfs Snapshots").bold().cyan());
     println!();
 
-    ensure_dependencies(&[Dependency::new("btrbk", &["btrbk"])])?;
+    btrbk::ensure_binary(config)?;
 
     // Try btrbk list first
-    let btrbk_list = shell_run("btrbk", &["list", "snapshots"]);
+    let btrbk_list = shell_run(
+        &btrbk::binary(config),
+        &["-c", btrbk::config_path(config), "list", "snapshots"],
+    );
 
     match btrbk_list {
         Ok(output) if !output.is_empty() => {
@@ -67,3 +281,771 @@ pub fn list(config: &Config) -> Result<()> {
 
     Ok(())
 }
+
+/// Archive format for `snapshot export`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    /// `btrfs send` stream; can be received back with `snapshot import`
+    BtrfsStream,
+    /// Plain tar of the snapshot contents
+    Tar,
+}
+
+/// Write a snapshot to a single file for offsite archival
+pub fn export(
+    config: &Config,
+    name: &str,
+    out: &str,
+    format: ExportFormat,
+    compress: bool,
+) -> Result<()> {
+    println!("{}", style("Export Snapshot").bold().cyan());
+    println!();
+
+    let mut dependencies = vec![match format {
+        ExportFormat::BtrfsStream => Dependency::new("btrfs-progs", &["btrfs"]),
+        ExportFormat::Tar => Dependency::new("tar", &["tar"]),
+    }];
+    if compress {
+        dependencies.push(Dependency::new("zstd", &["zstd"]));
+    }
+    ensure_dependencies(&dependencies)?;
+
+    let snapshot = snapshot_path(config, name)?;
+    if Path::new(out).exists() {
+        bail!("{} already exists", out);
+    }
+
+    info(&format!("Exporting {} to {}...", name, out));
+    match format {
+        ExportFormat::BtrfsStream => {
+            // btrfs send only works on read-only snapshots, which btrbk creates
+            run_with_output("btrfs", &["send", "-f", out, &snapshot])?;
+        }
+        ExportFormat::Tar => {
+            run_with_output(
+                "tar",
+                &["--xattrs", "--acls", "-C", &snapshot, "-cf", out, "."],
+            )?;
+        }
+    }
+
+    let written = if compress {
+        let compressed = format!("{}.zst", out);
+        run_with_output("zstd", &["-q", "--rm", "-o", &compressed, out])?;
+        compressed
+    } else {
+        out.to_string()
+    };
+
+    success(&format!("Snapshot exported to {}", written));
+    Ok(())
+}
+
+/// Receive a `btrfs send` stream (optionally .zst) into the snapshot directory
+pub fn import(config: &Config, file: &str) -> Result<()> {
+    println!("{}", style("Import Snapshot").bold().cyan());
+    println!();
+
+    let compressed = file.ends_with(".zst");
+    let mut dependencies = vec![Dependency::new("btrfs-progs", &["btrfs"])];
+    if compressed {
+        dependencies.push(Dependency::new("zstd", &["zstd"]));
+    }
+    ensure_dependencies(&dependencies)?;
+
+    if !Path::new(file).is_file() {
+        bail!("{} not found", file);
+    }
+    let snapshot_dir = format!("{}/{}", config.mount.base, config.btrbk.snapshot_dir);
+    let before = list_directory_names(&snapshot_dir)?;
+    // Receive into a staging directory, so a snapshot with a taken name can be
+    // renamed with a counter instead of failing the receive
+    let staging = format!("{}/.wslarc-import", snapshot_dir);
+    ensure_empty_staging(&staging)?;
+    let _writable = WritableBase::acquire(config, &RealRunner)?;
+
+    // btrfs receive reads a plain stream; decompress next to the snapshots first
+    let stream = if compressed {
+        let stream = format!("{}/.wslarc-import.stream", snapshot_dir);
+        info("Decompressing stream...");
+        run_with_output("zstd", &["-d", "-q", "-f", "-o", &stream, file])?;
+        stream
+    } else {
+        file.to_string()
+    };
+
+    fs::create_dir_all(&staging)?;
+    info(&format!("Receiving into {}...", snapshot_dir));
+    let result = run_with_output("btrfs", &["receive", "-f", &stream, &staging]);
+    if compressed {
+        if let Err(e) = fs::remove_file(&stream) {
+            warn(&format!("Could not remove {}: {}", stream, e));
+        }
+    }
+    if let Err(e) = result {
+        discard_staging(&staging);
+        return Err(e);
+    }
+
+    let mut existing = before;
+    for name in list_directory_names(&staging)? {
+        let target = unique_snapshot_name(&existing, &name);
+        fs::rename(
+            format!("{}/{}", staging, name),
+            format!("{}/{}", snapshot_dir, target),
+        )?;
+        if target == name {
+            success(&format!("Imported {}", name));
+        } else {
+            warn(&format!("{} already exists; imported as {}", name, target));
+        }
+        existing.push(target);
+    }
+    fs::remove_dir(&staging)?;
+    Ok(())
+}
+
+/// Refuse to import over what an interrupted import left in `staging`, which
+/// would otherwise be moved into the snapshot directory with the new snapshot
+fn ensure_empty_staging(staging: &str) -> Result<()> {
+    let Ok(entries) = list_directory_names(staging) else {
+        return Ok(());
+    };
+    if !entries.is_empty() {
+        bail!(
+            "{} is not empty ({}); an earlier import was interrupted. Delete what is left there with 'btrfs subvolume delete' and retry.",
+            staging,
+            entries.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Delete the partly received subvolumes in `staging` and the directory itself
+fn discard_staging(staging: &str) {
+    for name in list_directory_names(staging).unwrap_or_default() {
+        let path = format!("{}/{}", staging, name);
+        if let Err(e) = shell_run("btrfs", &["subvolume", "delete", &path]) {
+            warn(&format!("Could not delete {}: {}", path, e));
+        }
+    }
+    if let Err(e) = fs::remove_dir(staging) {
+        warn(&format!("Could not remove {}: {}", staging, e));
+    }
+}
+
+/// Delete one snapshot ahead of btrbk's retention policy
+pub fn delete(config: &Config, name: &str, yes: bool) -> Result<()> {
+    println!("{}", style("Delete Snapshot").bold().cyan());
+    println!();
+
+    ensure_dependencies(&[Dependency::new("btrfs-progs", &["btrfs"])])?;
+    let path = listed_snapshot_path(config, name)?;
+
+    info(&format!("Snapshot: {}", path));
+    if !confirm_or_yes(&format!("Delete snapshot {}?", name), false, yes)? {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    let _writable = WritableBase::acquire(config, &RealRunner)?;
+    run_with_output("btrfs", &["subvolume", "delete", &path])?;
+    success(&format!("Deleted {}", name));
+    Ok(())
+}
+
+/// Path of `name` in the snapshot directory, accepting only btrbk snapshot
+/// names that are listed there, so restore backups and other subvolumes are
+/// never touched
+fn listed_snapshot_path(config: &Config, name: &str) -> Result<String> {
+    let path = snapshot_path(config, name)?;
+    let snapshot_dir = format!("{}/{}", config.mount.base, config.btrbk.snapshot_dir);
+    let listed = list_directory_names(&snapshot_dir)?;
+    if parse_snapshot_subvolume(name).is_none() || !listed.iter().any(|entry| entry == name) {
+        bail!(
+            "{} is not a btrbk snapshot in {} (see 'wslarc snapshot list')",
+            name,
+            snapshot_dir
+        );
+    }
+    Ok(path)
+}
+
+/// State of one path in the live tree or a snapshot, cheap to compare
+#[derive(Debug, PartialEq, Eq)]
+enum PathState {
+    Missing,
+    File {
+        len: u64,
+        hash: u64,
+    },
+    Symlink(String),
+    Dir(Vec<String>),
+    /// FIFOs, sockets and devices, compared by type alone since opening a
+    /// FIFO blocks until a writer shows up
+    Special(fs::FileType),
+}
+
+/// Walk a subvolume's snapshots newest first and report where `path` changed
+pub fn find(config: &Config, path: &str) -> Result<()> {
+    println!(
+        "{}",
+        style("Find Snapshot Where Path Changed").bold().cyan()
+    );
+    println!();
+
+    let Some((subvol, relative)) = locate_subvolume(config, path) else {
+        bail!("{} is not inside a snapshotted subvolume", path);
+    };
+    let snapshot_dir = format!("{}/{}", config.mount.base, config.btrbk.snapshot_dir);
+    let snapshots = snapshots_newest_first(
+        &list_directory_names(&snapshot_dir)?,
+        subvol.trim_start_matches('@'),
+    );
+    if snapshots.is_empty() {
+        bail!("No snapshots of {} found in {}", subvol, snapshot_dir);
+    }
+    info(&format!(
+        "{} is {} in {} ({} snapshots)",
+        path,
+        if relative.is_empty() { "/" } else { &relative },
+        subvol,
+        snapshots.len()
+    ));
+    println!();
+
+    let live = path_state(Path::new(path))?;
+    let mut newer = ("live".to_string(), live);
+    let mut changed_after: Option<(String, String)> = None;
+    for name in &snapshots {
+        let state = path_state(&Path::new(&snapshot_dir).join(name).join(&relative))?;
+        let note = if state == newer.1 {
+            "unchanged"
+        } else if state == PathState::Missing {
+            "missing"
+        } else {
+            "differs from newer"
+        };
+        println!("  {}  {}", name, note);
+
+        if changed_after.is_none() && state != newer.1 {
+            changed_after = Some((name.clone(), newer.0.clone()));
+        }
+        newer = (name.clone(), state);
+    }
+
+    println!();
+    match changed_after {
+        Some((older, newer)) => success(&format!(
+            "{} last changed between {} and {}",
+            path, older, newer
+        )),
+        None => success(&format!(
+            "{} is identical in the live system and all snapshots",
+            path
+        )),
+    }
+    Ok(())
+}
+
+/// Map a live path to (snapshotted subvolume, path inside it), longest mount first
+fn locate_subvolume(config: &Config, path: &str) -> Option<(String, String)> {
+    let path = path.trim_end_matches('/');
+    // @etc is synced from /etc before each snapshot rather than mounted
+    let mounts = config
+        .sorted_backup()
+        .into_iter()
+        .map(|(subvol, backup)| (subvol.clone(), backup.mount().to_string()))
+        .chain([("@etc".to_string(), "/etc".to_string())]);
+
+    mounts
+        .filter_map(|(subvol, mount)| {
+            let mount = mount.trim_end_matches('/');
+            let rest = path.strip_prefix(mount)?;
+            if !rest.is_empty() && !rest.starts_with('/') {
+                return None;
+            }
+            Some((
+                mount.len(),
+                subvol,
+                rest.trim_start_matches('/').to_string(),
+            ))
+        })
+        .max_by_key(|(len, _, _)| *len)
+        .map(|(_, subvol, rest)| (subvol, rest))
+}
+
+/// btrbk snapshots named `<snapshot_name>.<timestamp>`, newest first
+pub fn snapshots_newest_first(entries: &[String], snapshot_name: &str) -> Vec<String> {
+    let mut snapshots: Vec<(NaiveDateTime, &String)> = entries
+        .iter()
+        .filter_map(|name| match parse_snapshot_name(name) {
+            Some((subvol, stamp)) if subvol == snapshot_name => Some((stamp, name)),
+            _ => None,
+        })
+        .collect();
+    snapshots.sort_by(|a, b| b.cmp(a));
+    snapshots
+        .into_iter()
+        .map(|(_, name)| name.clone())
+        .collect()
+}
+
+fn path_state(path: &Path) -> Result<PathState> {
+    let Ok(meta) = fs::symlink_metadata(path) else {
+        return Ok(PathState::Missing);
+    };
+    if meta.file_type().is_symlink() {
+        let target = fs::read_link(path)?;
+        return Ok(PathState::Symlink(target.to_string_lossy().to_string()));
+    }
+    if meta.is_dir() {
+        return Ok(PathState::Dir(list_directory_names(
+            &path.to_string_lossy(),
+        )?));
+    }
+    if !meta.is_file() {
+        return Ok(PathState::Special(meta.file_type()));
+    }
+
+    let mut hasher = DefaultHasher::new();
+    let mut file = fs::File::open(path)?;
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.write(&buf[..n]);
+    }
+    Ok(PathState::File {
+        len: meta.len(),
+        hash: hasher.finish(),
+    })
+}
+
+/// How a path differs in the newer of two snapshots
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    Added,
+    Changed,
+    Deleted,
+}
+
+/// List the paths added, changed and deleted between two snapshots of the
+/// same subvolume, older to newer whatever the argument order
+pub fn diff(config: &Config, a: &str, b: &str) -> Result<()> {
+    println!("{}", style("Snapshot Diff").bold().cyan());
+    println!();
+
+    ensure_dependencies(&[Dependency::new("btrfs-progs", &["btrfs"])])?;
+    let (older, newer) = diff_pair(config, a, b)?;
+    let older_path = listed_snapshot_path(config, &older)?;
+    let newer_path = listed_snapshot_path(config, &newer)?;
+    info(&format!("{} -> {}", older, newer));
+    println!();
+
+    // find-new names files whose data changed without reading them; without
+    // it, files present in both snapshots are compared by content
+    let changed_files = match find_new_files(&older_path, &newer_path) {
+        Ok(files) => Some(files),
+        Err(e) => {
+            warn(&format!(
+                "btrfs subvolume find-new failed ({:#}); comparing file contents",
+                e
+            ));
+            None
+        }
+    };
+
+    let (older_root, newer_root) = (Path::new(&older_path), Path::new(&newer_path));
+    let mut changes = Vec::new();
+    compare_trees(
+        older_root,
+        newer_root,
+        "",
+        changed_files.is_none(),
+        &mut changes,
+    )?;
+    let mut recorded: HashSet<String> = changes.iter().map(|(_, path)| path.clone()).collect();
+    for file in changed_files.unwrap_or_default() {
+        let in_both = fs::symlink_metadata(older_root.join(&file)).is_ok()
+            && fs::symlink_metadata(newer_root.join(&file)).is_ok();
+        if in_both && recorded.insert(file.clone()) {
+            changes.push((Change::Changed, file));
+        }
+    }
+    changes.sort_by(|a, b| a.1.cmp(&b.1));
+
+    if changes.is_empty() {
+        success("No differences");
+        return Ok(());
+    }
+    for (change, path) in &changes {
+        let marker = match change {
+            Change::Added => style("+").green(),
+            Change::Changed => style("~").yellow(),
+            Change::Deleted => style("-").red(),
+        };
+        println!("  {} {}", marker, path);
+    }
+    let count = |kind: Change| changes.iter().filter(|(change, _)| *change == kind).count();
+    println!();
+    info(&format!(
+        "{} added, {} changed, {} deleted",
+        count(Change::Added),
+        count(Change::Changed),
+        count(Change::Deleted)
+    ));
+    Ok(())
+}
+
+/// Validate two snapshot names and return them as (older, newer); both must
+/// be listed btrbk snapshots of the same subvolume
+fn diff_pair(config: &Config, a: &str, b: &str) -> Result<(String, String)> {
+    listed_snapshot_path(config, a)?;
+    listed_snapshot_path(config, b)?;
+    if a == b {
+        bail!("Both arguments name the same snapshot: {}", a);
+    }
+
+    let (Some((subvol_a, stamp_a)), Some((subvol_b, stamp_b))) =
+        (parse_snapshot_name(a), parse_snapshot_name(b))
+    else {
+        bail!("{} or {} is not a btrbk snapshot name", a, b);
+    };
+    if subvol_a != subvol_b {
+        bail!(
+            "{} and {} are snapshots of different subvolumes ({} and {})",
+            a,
+            b,
+            subvol_a,
+            subvol_b
+        );
+    }
+
+    if (stamp_a, a) <= (stamp_b, b) {
+        Ok((a.to_string(), b.to_string()))
+    } else {
+        Ok((b.to_string(), a.to_string()))
+    }
+}
+
+/// Files whose data changed in `newer` since `older` was taken
+fn find_new_files(older: &str, newer: &str) -> Result<Vec<String>> {
+    let generation = show_subvolume(older)?
+        .gen_at_creation
+        .with_context(|| format!("No creation generation for {}", older))?;
+    let output = shell_run(
+        "btrfs",
+        &["subvolume", "find-new", newer, &generation.to_string()],
+    )?;
+    Ok(parse_find_new(&output))
+}
+
+/// Paths from `btrfs subvolume find-new` output, one per changed file
+fn parse_find_new(output: &str) -> Vec<String> {
+    let mut paths: Vec<String> = output
+        .lines()
+        .filter(|line| line.starts_with("inode "))
+        .filter_map(|line| {
+            let (_, rest) = line.split_once(" flags ")?;
+            let (_, path) = rest.split_once(' ')?;
+            Some(path.trim_start_matches('/').to_string())
+        })
+        .collect();
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+/// Walk both trees below `relative`, recording added and deleted entries
+/// (not their contents) and entries whose type or symlink target changed;
+/// with `compare_files`, regular files are also compared by content (other
+/// file types only by type)
+fn compare_trees(
+    older: &Path,
+    newer: &Path,
+    relative: &str,
+    compare_files: bool,
+    changes: &mut Vec<(Change, String)>,
+) -> Result<()> {
+    let old_names = list_directory_names(&older.join(relative).to_string_lossy())?;
+    let new_names = list_directory_names(&newer.join(relative).to_string_lossy())?;
+    let child = |name: &str| {
+        if relative.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", relative, name)
+        }
+    };
+
+    for name in old_names.iter().filter(|name| !new_names.contains(name)) {
+        changes.push((Change::Deleted, child(name)));
+    }
+    for name in &new_names {
+        let path = child(name);
+        if !old_names.contains(name) {
+            changes.push((Change::Added, path));
+            continue;
+        }
+
+        let old_meta = fs::symlink_metadata(older.join(&path))?;
+        let new_meta = fs::symlink_metadata(newer.join(&path))?;
+        if old_meta.is_dir() && new_meta.is_dir() {
+            compare_trees(older, newer, &path, compare_files, changes)?;
+        } else if old_meta.file_type() != new_meta.file_type()
+            || ((old_meta.file_type().is_symlink() || (compare_files && old_meta.is_file()))
+                && path_state(&older.join(&path))? != path_state(&newer.join(&path))?)
+        {
+            changes.push((Change::Changed, path));
+        }
+    }
+    Ok(())
+}
+
+fn snapshot_path(config: &Config, name: &str) -> Result<String> {
+    if name.is_empty() || name.contains('/') || name.starts_with('.') {
+        bail!("Invalid snapshot name: '{}'", name);
+    }
+    let path = format!(
+        "{}/{}/{}",
+        config.mount.base, config.btrbk.snapshot_dir, name
+    );
+    if !Path::new(&path).is_dir() {
+        bail!("Snapshot {} not found (see 'wslarc snapshot list')", name);
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn import_refuses_leftovers_in_staging() {
+        let dir = tempfile::tempdir().unwrap();
+        let staging = dir.path().join(".wslarc-import");
+        let staging = staging.to_string_lossy();
+        // Missing or empty is fine
+        ensure_empty_staging(&staging).unwrap();
+        fs::create_dir(staging.as_ref()).unwrap();
+        ensure_empty_staging(&staging).unwrap();
+
+        fs::create_dir(dir.path().join(".wslarc-import/home.20240115T0300")).unwrap();
+        let err = ensure_empty_staging(&staging).unwrap_err();
+        assert!(err.to_string().contains("(home.20240115T0300)"));
+    }
+
+    #[test]
+    fn locate_subvolume_prefers_longest_mount() {
+        let mut cfg = Config::default();
+        cfg.set_user("alice");
+
+        assert_eq!(
+            locate_subvolume(&cfg, "/home/alice/.bashrc"),
+            Some(("@home".to_string(), ".bashrc".to_string()))
+        );
+        assert_eq!(
+            locate_subvolume(&cfg, "/etc/pacman.conf"),
+            Some(("@etc".to_string(), "pacman.conf".to_string()))
+        );
+        assert_eq!(
+            locate_subvolume(&cfg, "/usr"),
+            Some(("@usr".to_string(), String::new()))
+        );
+        assert_eq!(locate_subvolume(&cfg, "/usrlocal/bin"), None);
+    }
+
+    #[test]
+    fn snapshot_infos_parses_and_sorts_btrbk_names() {
+        let entries: Vec<String> = ["usr.20240115T0300", "home.20240114T0300_1", "notes.txt"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+
+        assert_eq!(
+            snapshot_infos(&entries),
+            vec![
+                SnapshotInfo {
+                    subvol: "home".to_string(),
+                    timestamp: "2024-01-14T03:00:00".to_string(),
+                    name: "home.20240114T0300_1".to_string(),
+                },
+                SnapshotInfo {
+                    subvol: "usr".to_string(),
+                    timestamp: "2024-01-15T03:00:00".to_string(),
+                    name: "usr.20240115T0300".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn snapshots_newest_first_filters_by_subvolume() {
+        let entries: Vec<String> = [
+            "home.20240114T0300",
+            "usr.20240115T0300",
+            "home.20240115T0300_1",
+            "home.20240115T0300",
+            "home_cache.20240116T0300",
+        ]
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+
+        assert_eq!(
+            snapshots_newest_first(&entries, "home"),
+            vec![
+                "home.20240115T0300_1",
+                "home.20240115T0300",
+                "home.20240114T0300"
+            ]
+        );
+    }
+
+    #[test]
+    fn path_state_compares_content_not_timestamps() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a");
+        let b = dir.path().join("b");
+        fs::write(&a, "same").unwrap();
+        fs::write(&b, "same").unwrap();
+        assert_eq!(path_state(&a).unwrap(), path_state(&b).unwrap());
+
+        fs::write(&b, "different").unwrap();
+        assert_ne!(path_state(&a).unwrap(), path_state(&b).unwrap());
+
+        let socket = dir.path().join("socket");
+        std::os::unix::net::UnixListener::bind(&socket).unwrap();
+        assert!(matches!(
+            path_state(&socket).unwrap(),
+            PathState::Special(_)
+        ));
+        assert_eq!(
+            path_state(&dir.path().join("missing")).unwrap(),
+            PathState::Missing
+        );
+    }
+
+    #[test]
+    fn snapshot_path_rejects_names_outside_snapshot_dir() {
+        let cfg = Config::default();
+        for name in ["", "../@usr", ".hidden", "a/b"] {
+            let err = snapshot_path(&cfg, name).unwrap_err().to_string();
+            assert!(err.contains("Invalid snapshot name"), "{}", name);
+        }
+    }
+
+    #[test]
+    fn listed_snapshot_path_accepts_only_listed_snapshots() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = Config::default();
+        cfg.mount.base = dir.path().to_string_lossy().to_string();
+        let snapshots = dir.path().join(&cfg.btrbk.snapshot_dir);
+        for name in ["usr.20240115T0300", "usr.restore-backup"] {
+            fs::create_dir_all(snapshots.join(name)).unwrap();
+        }
+
+        assert!(listed_snapshot_path(&cfg, "usr.20240115T0300")
+            .unwrap()
+            .ends_with("/.snapshots/usr.20240115T0300"));
+        for name in [
+            "usr.restore-backup",
+            "usr.20240116T0300",
+            "../usr.20240115T0300",
+        ] {
+            assert!(listed_snapshot_path(&cfg, name).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn diff_pair_orders_snapshots_of_one_subvolume() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = Config::default();
+        cfg.mount.base = dir.path().to_string_lossy().to_string();
+        let snapshots = dir.path().join(&cfg.btrbk.snapshot_dir);
+        for name in [
+            "home.20240114T0300",
+            "home.20240115T0300",
+            "usr.20240115T0300",
+        ] {
+            fs::create_dir_all(snapshots.join(name)).unwrap();
+        }
+
+        assert_eq!(
+            diff_pair(&cfg, "home.20240115T0300", "home.20240114T0300").unwrap(),
+            (
+                "home.20240114T0300".to_string(),
+                "home.20240115T0300".to_string()
+            )
+        );
+        let err = diff_pair(&cfg, "home.20240115T0300", "usr.20240115T0300").unwrap_err();
+        assert!(err.to_string().contains("different subvolumes"));
+        assert!(diff_pair(&cfg, "home.20240115T0300", "home.20240115T0300").is_err());
+        assert!(diff_pair(&cfg, "home.20240115T0300", "home.20240116T0300").is_err());
+    }
+
+    #[test]
+    fn parse_find_new_lists_each_changed_file_once() {
+        let output = "\
+inode 258 file offset 0 len 4096 disk start 0 offset 0 gen 1850 flags INLINE .bashrc
+inode 260 file offset 0 len 131072 disk start 30408704 offset 0 gen 1851 flags COMPRESS docs/notes with space.txt
+inode 260 file offset 131072 len 8192 disk start 30539776 offset 0 gen 1851 flags NONE docs/notes with space.txt
+transid marker was 1852
+";
+        assert_eq!(
+            parse_find_new(output),
+            vec![".bashrc", "docs/notes with space.txt"]
+        );
+    }
+
+    #[test]
+    fn compare_trees_reports_added_changed_and_deleted() {
+        let older = tempfile::tempdir().unwrap();
+        let newer = tempfile::tempdir().unwrap();
+        for root in [older.path(), newer.path()] {
+            fs::create_dir_all(root.join("docs")).unwrap();
+            fs::write(root.join("docs/same.txt"), "same").unwrap();
+            // Special files are compared by type, never opened
+            std::os::unix::net::UnixListener::bind(root.join("app.sock")).unwrap();
+        }
+        fs::write(older.path().join("edited.txt"), "old").unwrap();
+        fs::write(newer.path().join("edited.txt"), "new").unwrap();
+        fs::create_dir_all(older.path().join("gone/deep")).unwrap();
+        fs::write(newer.path().join("docs/new.txt"), "").unwrap();
+
+        let mut changes = Vec::new();
+        compare_trees(older.path(), newer.path(), "", true, &mut changes).unwrap();
+        changes.sort_by(|a, b| a.1.cmp(&b.1));
+        assert_eq!(
+            changes,
+            vec![
+                (Change::Added, "docs/new.txt".to_string()),
+                (Change::Changed, "edited.txt".to_string()),
+                (Change::Deleted, "gone".to_string()),
+            ]
+        );
+
+        // With find-new supplying changed files, contents are not compared
+        let mut changes = Vec::new();
+        compare_trees(older.path(), newer.path(), "", false, &mut changes).unwrap();
+        assert!(!changes.contains(&(Change::Changed, "edited.txt".to_string())));
+    }
+
+    #[test]
+    fn new_entries_reports_only_created_snapshots() {
+        let before = vec!["@usr.20240101T0300".to_string()];
+        let after = vec![
+            "@etc.20240102T0300".to_string(),
+            "@usr.20240101T0300".to_string(),
+            "@usr.20240102T0300".to_string(),
+        ];
+
+        assert_eq!(
+            new_entries(&before, &after),
+            vec!["@etc.20240102T0300", "@usr.20240102T0300"]
+        );
+    }
+}
@@ -1,13 +1,40 @@
-use anyhow::Result;
-use console::style;
+use anyhow::{Context, Result};
+use chrono::{Local, NaiveDateTime};
+use console::{style, Term};
+use serde::Serialize;
+use std::thread;
+use std::time::Duration;
 
 use crate::config::Config;
-use crate::generators::systemd;
-use crate::utils::cli::{find_mount, list_btrfs_mounts, list_directory_names, systemctl_property};
+use crate::generators::{btrbk, maintenance, systemd};
+use crate::utils::cli::{
+    find_mount, format_bytes, list_btrfs_mounts, list_directory_names, list_subvolumes,
+    parse_snapshot_name, parse_snapshot_timestamp, read_unit_property, shadowed_entry_count,
+    subvolume_usage, MountInfo,
+};
 use crate::utils::prompt::{kv, section};
-use crate::utils::shell::run as shell_run;
+use crate::utils::wsl::boot_command_problem;
+
+/// How `status` renders its report (global --format)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Sections for humans
+    #[default]
+    Text,
+    /// One JSON document (`StatusReport`) for scripts
+    Json,
+}
+
+pub fn run(config: &Config, format: OutputFormat) -> Result<()> {
+    if format == OutputFormat::Json {
+        let report = gather_report(config);
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&report).context("Failed to serialize status")?
+        );
+        return Ok(());
+    }
 
-pub fn run(config: &Config) -> Result<()> {
     println!("{}", style("WSL Btrfs Status").bold().cyan());
 
     // Configuration
@@ -16,6 +43,16 @@ pub fn run(config: &Config) -> Result<()> {
     kv("VHDX", &config.vhdx.path);
     kv("Mount base", &config.mount.base);
     kv("User", &config.get_user());
+    match boot_command_problem() {
+        None => kv("Boot command", "up to date"),
+        Some(problem) => {
+            kv("Boot command", &problem);
+            println!(
+                "  {} Run 'sudo wslarc mount' to reinstall the boot binary",
+                style("!").yellow()
+            );
+        }
+    }
 
     // Btrfs mounts
     section("Btrfs Mounts");
@@ -32,15 +69,33 @@ pub fn run(config: &Config) -> Result<()> {
     section("Subvolumes");
     if !is_mounted(&config.mount.base) {
         println!("  {} not mounted", config.mount.base);
+        let hidden = shadowed_entry_count(&config.mount.base);
+        if hidden > 0 {
+            println!(
+                "  {} {} contains {} entries that the mount will hide",
+                style("!").yellow(),
+                config.mount.base,
+                hidden
+            );
+        }
     } else {
-        let subvols = shell_run("btrfs", &["subvolume", "list", &config.mount.base]);
-        match subvols {
-            Ok(output) if !output.is_empty() => {
-                for line in output.lines() {
-                    if let Some(path) = line.split_whitespace().last() {
-                        println!("  {}", path);
-                    }
+        match list_subvolumes(&config.mount.base) {
+            Ok(paths) if !paths.is_empty() => {
+                for path in &paths {
+                    println!("  {}", path);
                 }
+                // @etc has no mount unit, so this is the only place it shows up
+                let snapshot_dir = format!("{}/{}", config.mount.base, config.btrbk.snapshot_dir);
+                let snapshots = list_directory_names(&snapshot_dir).unwrap_or_default();
+                println!();
+                println!(
+                    "  {}",
+                    etc_status_line(
+                        paths.iter().any(|path| path == "@etc"),
+                        btrbk::snapshot_subvolumes(config, &paths).contains(&"@etc".to_string()),
+                        latest_snapshot_of(&snapshots, "@etc"),
+                    )
+                );
             }
             Ok(_) => println!("  No subvolumes found"),
             Err(err) => {
@@ -51,6 +106,26 @@ pub fn run(config: &Config) -> Result<()> {
         }
     }
 
+    if config.mount.quotas && is_mounted(&config.mount.base) {
+        section("Subvolume Usage");
+        match subvolume_usage(&config.mount.base) {
+            Ok(usage) => {
+                for entry in usage.iter().filter(|entry| !entry.path.contains('/')) {
+                    println!(
+                        "  {:<20} {:>10} referenced  {:>10} exclusive",
+                        entry.path,
+                        format_bytes(entry.referenced),
+                        format_bytes(entry.exclusive)
+                    );
+                }
+            }
+            Err(_) => println!(
+                "  Quota data unavailable; run: btrfs quota enable {}",
+                config.mount.base
+            ),
+        }
+    }
+
     // Snapshots
     section("Snapshots");
     let snapshot_dir = format!("{}/{}", config.mount.base, config.btrbk.snapshot_dir);
@@ -72,7 +147,7 @@ pub fn run(config: &Config) -> Result<()> {
 
     // Systemd services
     section("Systemd Services");
-    check_service("btrbk.timer");
+    print_service(&check_service("btrbk.timer"));
 
     // Next timer
     if let Some(next) = read_unit_property("btrbk.timer", "NextElapseUSecRealtime") {
@@ -83,18 +158,148 @@ pub fn run(config: &Config) -> Result<()> {
         }
     }
 
-    // Mount units status
+    let tasks = maintenance::scheduled_tasks(config);
+    if !tasks.is_empty() {
+        section("Maintenance Timers");
+        for (task, _) in &tasks {
+            let timer = task.timer_unit();
+            print_service(&check_service(&timer));
+            if let Some(next) = read_unit_property(&timer, "NextElapseUSecRealtime") {
+                println!("    Next run: {}", next);
+            }
+        }
+    }
+
+    print_mount_units(config);
+
+    Ok(())
+}
+
+/// Redraw a compact status every `interval` until interrupted
+///
+/// Each frame is built from the same `StatusReport` as `--format json`. A unit
+/// systemctl could not be asked about keeps its state from the previous frame.
+pub fn watch(config: &Config, interval: Duration) -> Result<()> {
+    let term = Term::stdout();
+    let mut previous: Option<StatusReport> = None;
+    loop {
+        let mut report = gather_report(config);
+        let stale = previous
+            .as_ref()
+            .map_or(0, |previous| carry_over_unknown(&mut report, previous));
+
+        term.clear_screen()?;
+        println!(
+            "{} {}",
+            style("WSL Btrfs Status").bold().cyan(),
+            style(format!(
+                "(every {}s, {}; Ctrl-C to stop)",
+                interval.as_secs(),
+                Local::now().format("%H:%M:%S")
+            ))
+            .dim()
+        );
+        print_watch_frame(&report);
+        if stale > 0 {
+            println!();
+            println!(
+                "  {} systemctl did not answer for {} unit(s); showing their last known state",
+                style("!").yellow(),
+                stale
+            );
+        }
+
+        previous = Some(report);
+        thread::sleep(interval);
+    }
+}
+
+fn print_watch_frame(report: &StatusReport) {
+    section("Health");
+    kv("State", report.health.describe());
+    let base = if report.base_mounted {
+        "mounted"
+    } else {
+        "not mounted"
+    };
+    kv(&report.mount_base, base);
+    if let Some(problem) = &report.boot_command_problem {
+        kv("Boot command", problem);
+    }
+
+    section("Btrfs Mounts");
+    if report.mounts.is_empty() {
+        println!("  No Btrfs mounts found");
+    }
+    for mount in &report.mounts {
+        println!("  {}", format_mount(mount));
+    }
+
+    section("Snapshots");
+    match &report.snapshots.latest {
+        Some(latest) => println!("  {} snapshots, latest {}", report.snapshots.count, latest),
+        None => println!("  {} snapshots", report.snapshots.count),
+    }
+    if let Some(error) = &report.subvolumes_error {
+        println!("  Subvolume list unavailable: {}", error);
+    }
+
+    section("Mount Units");
+    for unit in &report.mount_units {
+        print_service(unit);
+    }
+
+    section("Timers");
+    for timer in &report.timers {
+        print_service(timer);
+    }
+}
+
+/// Replace units systemctl returned nothing for with their previous state;
+/// returns how many were replaced
+fn carry_over_unknown(report: &mut StatusReport, previous: &StatusReport) -> usize {
+    let mut replaced = 0;
+    let unknown = report
+        .mount_units
+        .iter_mut()
+        .chain(report.timers.iter_mut())
+        .filter(|service| {
+            service.unit_file_state == "unknown" && service.active_state == "unknown"
+        });
+    for service in unknown {
+        let known = previous
+            .mount_units
+            .iter()
+            .chain(&previous.timers)
+            .find(|old| old.name == service.name && old.active_state != "unknown");
+        if let Some(known) = known {
+            *service = known.clone();
+            replaced += 1;
+        }
+    }
+    replaced
+}
+
+/// Print only the enabled/active table of the managed mount units
+pub fn run_units(config: &Config) -> Result<()> {
+    println!("{}", style("WSL Btrfs Status").bold().cyan());
+    print_mount_units(config);
+    Ok(())
+}
+
+/// Mount units status: base, backup and transfer, plus a hint for failed ones
+fn print_mount_units(config: &Config) {
     section("Mount Units");
 
-    let mount_units = mount_unit_names(config);
+    let mount_units = systemd::mount_unit_names(config);
     for unit in &mount_units {
-        check_service(unit);
+        print_service(&check_service(unit));
     }
 
     // Failed mounts hint
     let failed_units: Vec<String> = mount_units
         .into_iter()
-        .filter(|unit| is_failed_mount_status(&read_unit_status(unit)))
+        .filter(|unit| is_failed_unit_status(&read_unit_status(unit)))
         .collect();
     if !failed_units.is_empty() {
         println!();
@@ -105,8 +310,200 @@ pub fn run(config: &Config) -> Result<()> {
         println!("    systemctl status <unit-name>.mount");
         println!("    journalctl -u <unit-name>.mount");
     }
+}
 
-    Ok(())
+/// Print a single-line health summary and return whether everything is healthy
+pub fn run_short(config: &Config) -> Result<bool> {
+    let summary = gather_short_summary(config);
+    println!(
+        "{}",
+        format_short_summary(&summary, Local::now().naive_local())
+    );
+    Ok(summary.is_healthy())
+}
+
+/// Overall health for `status --check-exit`; the first problem found decides
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Health {
+    Healthy,
+    NotMounted,
+    FailedUnits,
+    InactiveMounts,
+    TimerInactive,
+}
+
+impl Health {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Health::Healthy => 0,
+            Health::NotMounted => 2,
+            Health::FailedUnits => 3,
+            Health::InactiveMounts => 4,
+            Health::TimerInactive => 5,
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Health::Healthy => "healthy",
+            Health::NotMounted => "Btrfs base volume is not mounted",
+            Health::FailedUnits => "some wslarc mount units or services failed",
+            Health::InactiveMounts => "some wslarc mount units are not active",
+            Health::TimerInactive => "btrbk.timer is not active",
+        }
+    }
+}
+
+/// Health probe: no output unless `verbose`, the result is the exit code
+pub fn run_check_exit(config: &Config, verbose: bool) -> Result<i32> {
+    if verbose {
+        run(config, OutputFormat::Text)?;
+    }
+    let units: Vec<UnitStatus> = systemd::mount_unit_names(config)
+        .iter()
+        .map(|unit| read_unit_status(unit))
+        .collect();
+    let services: Vec<UnitStatus> = service_unit_names(config)
+        .iter()
+        .map(|unit| read_unit_status(unit))
+        .collect();
+    let timer_state =
+        read_unit_property("btrbk.timer", "ActiveState").unwrap_or_else(|| "unknown".to_string());
+
+    let health = assess_health(
+        is_mounted(&config.mount.base),
+        &units,
+        &services,
+        &timer_state,
+    );
+    if health != Health::Healthy {
+        eprintln!("wslarc: {}", health.describe());
+    }
+    Ok(health.exit_code())
+}
+
+/// Services wslarc schedules: btrbk and the maintenance tasks. Inactive is
+/// their normal state between runs, so only a failed last run counts
+fn service_unit_names(config: &Config) -> Vec<String> {
+    std::iter::once("btrbk.service".to_string())
+        .chain(
+            maintenance::scheduled_tasks(config)
+                .into_iter()
+                .map(|(task, _)| task.service_unit()),
+        )
+        .collect()
+}
+
+fn assess_health(
+    mounted: bool,
+    units: &[UnitStatus],
+    services: &[UnitStatus],
+    timer_state: &str,
+) -> Health {
+    if !mounted {
+        Health::NotMounted
+    } else if units.iter().chain(services).any(is_failed_unit_status) {
+        Health::FailedUnits
+    } else if units.iter().any(|unit| unit.active_state != "active") {
+        Health::InactiveMounts
+    } else if timer_state != "active" {
+        Health::TimerInactive
+    } else {
+        Health::Healthy
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ShortSummary {
+    mounted: bool,
+    failed_units: usize,
+    snapshot_count: usize,
+    latest_snapshot: Option<NaiveDateTime>,
+    timer_state: String,
+}
+
+impl ShortSummary {
+    fn is_healthy(&self) -> bool {
+        self.mounted && self.failed_units == 0 && self.timer_state == "active"
+    }
+}
+
+fn gather_short_summary(config: &Config) -> ShortSummary {
+    let failed_units = systemd::mount_unit_names(config)
+        .iter()
+        .chain(&service_unit_names(config))
+        .filter(|unit| is_failed_unit_status(&read_unit_status(unit)))
+        .count();
+
+    let snapshot_dir = format!("{}/{}", config.mount.base, config.btrbk.snapshot_dir);
+    let snapshots = list_directory_names(&snapshot_dir).unwrap_or_default();
+    let latest_snapshot = snapshots
+        .iter()
+        .filter_map(|name| parse_snapshot_timestamp(name))
+        .max();
+
+    ShortSummary {
+        mounted: is_mounted(&config.mount.base),
+        failed_units,
+        snapshot_count: snapshots.len(),
+        latest_snapshot,
+        timer_state: read_unit_property("btrbk.timer", "ActiveState")
+            .unwrap_or_else(|| "unknown".to_string()),
+    }
+}
+
+/// Newest snapshot of `subvol`, whose btrbk snapshot_name drops the leading @
+fn latest_snapshot_of(snapshots: &[String], subvol: &str) -> Option<NaiveDateTime> {
+    let snapshot_name = subvol.trim_start_matches('@');
+    snapshots
+        .iter()
+        .filter_map(|name| parse_snapshot_name(name))
+        .filter(|(subvol, _)| *subvol == snapshot_name)
+        .map(|(_, stamp)| stamp)
+        .max()
+}
+
+/// Status of the snapshot-only @etc subvolume (synced from /etc, never mounted)
+fn etc_status_line(exists: bool, snapshotted: bool, latest: Option<NaiveDateTime>) -> String {
+    let state = match (exists, snapshotted, latest) {
+        (false, _, _) => "missing; 'wslarc snapshot run' cannot sync /etc".to_string(),
+        (true, false, _) => "present, excluded from btrbk snapshots".to_string(),
+        (true, true, None) => "present, no snapshots yet".to_string(),
+        (true, true, Some(latest)) => {
+            format!("present, last snapshot {}", latest.format("%Y-%m-%d %H:%M"))
+        }
+    };
+    format!("@etc (snapshot-only): {}", state)
+}
+
+fn format_short_summary(summary: &ShortSummary, now: NaiveDateTime) -> String {
+    let btrfs = match (summary.mounted, summary.failed_units) {
+        (false, _) => "not mounted".to_string(),
+        (true, 0) => "mounted ok".to_string(),
+        (true, failed) => format!("mounted, {} failed", failed),
+    };
+    let last = match summary.latest_snapshot {
+        Some(timestamp) => format_age(now - timestamp),
+        None => "never".to_string(),
+    };
+
+    format!(
+        "btrfs: {} | snapshots: {} | last: {} | timer: {}",
+        btrfs, summary.snapshot_count, last, summary.timer_state
+    )
+}
+
+fn format_age(age: chrono::Duration) -> String {
+    if age.num_minutes() < 1 {
+        "just now".to_string()
+    } else if age.num_hours() < 1 {
+        format!("{}m ago", age.num_minutes())
+    } else if age.num_days() < 1 {
+        format!("{}h ago", age.num_hours())
+    } else {
+        format!("{}d ago", age.num_days())
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -116,16 +513,38 @@ struct UnitStatus {
     result: String,
 }
 
-fn check_service(name: &str) {
-    let status = read_unit_status(name);
+/// Enabled/active state of one systemd unit, shared by both renderers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ServiceState {
+    pub name: String,
+    pub enabled: bool,
+    pub active: bool,
+    pub unit_file_state: String,
+    pub active_state: String,
+}
+
+fn check_service(name: &str) -> ServiceState {
+    service_state(name, &read_unit_status(name))
+}
 
-    let enabled_icon = if status.unit_file_state.trim() == "enabled" {
+fn service_state(name: &str, status: &UnitStatus) -> ServiceState {
+    ServiceState {
+        name: name.to_string(),
+        enabled: status.unit_file_state.trim() == "enabled",
+        active: status.active_state.trim() == "active",
+        unit_file_state: status.unit_file_state.trim().to_string(),
+        active_state: status.active_state.trim().to_string(),
+    }
+}
+
+fn print_service(service: &ServiceState) {
+    let enabled_icon = if service.enabled {
         style("✓").green()
     } else {
         style("✗").red()
     };
 
-    let active_icon = if status.active_state.trim() == "active" {
+    let active_icon = if service.active {
         style("●").green()
     } else {
         style("○").dim()
@@ -133,11 +552,110 @@ fn check_service(name: &str) {
 
     println!(
         "  {} {} {} ({})",
-        enabled_icon,
-        active_icon,
-        name,
-        status.unit_file_state.trim()
+        enabled_icon, active_icon, service.name, service.unit_file_state
+    );
+}
+
+/// Everything `status --format json` prints
+#[derive(Debug, Serialize)]
+pub struct StatusReport {
+    pub uuid: Option<String>,
+    pub vhdx: String,
+    pub mount_base: String,
+    pub base_mounted: bool,
+    /// Problem with the wsl.conf [boot] command, None when it is up to date
+    pub boot_command_problem: Option<String>,
+    pub mounts: Vec<MountInfo>,
+    /// Top-level subvolumes on disk; empty when the base is not mounted
+    pub subvolumes: Vec<String>,
+    /// Why `subvolumes` could not be listed, if it could not
+    pub subvolumes_error: Option<String>,
+    pub snapshots: SnapshotReport,
+    pub mount_units: Vec<ServiceState>,
+    /// btrbk.timer followed by the scheduled maintenance timers
+    pub timers: Vec<ServiceState>,
+    /// Same verdict as `status --check-exit`
+    pub health: Health,
+    pub exit_code: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotReport {
+    pub count: usize,
+    /// Name of the newest snapshot by timestamp
+    pub latest: Option<String>,
+    /// Its timestamp as YYYY-MM-DDTHH:MM:SS
+    pub latest_time: Option<String>,
+}
+
+fn gather_report(config: &Config) -> StatusReport {
+    let base_mounted = is_mounted(&config.mount.base);
+    let (subvolumes, subvolumes_error) = if !base_mounted {
+        (Vec::new(), None)
+    } else {
+        match list_subvolumes(&config.mount.base) {
+            Ok(paths) => (paths, None),
+            Err(err) => (Vec::new(), Some(summarize_error(&err))),
+        }
+    };
+
+    let snapshot_dir = format!("{}/{}", config.mount.base, config.btrbk.snapshot_dir);
+    let snapshot_names = list_directory_names(&snapshot_dir).unwrap_or_default();
+    let latest = snapshot_names
+        .iter()
+        .filter_map(|name| parse_snapshot_timestamp(name).map(|stamp| (stamp, name)))
+        .max();
+
+    let unit_names = systemd::mount_unit_names(config);
+    let unit_statuses: Vec<UnitStatus> = unit_names
+        .iter()
+        .map(|unit| read_unit_status(unit))
+        .collect();
+    let service_statuses: Vec<UnitStatus> = service_unit_names(config)
+        .iter()
+        .map(|unit| read_unit_status(unit))
+        .collect();
+    let timer_state =
+        read_unit_property("btrbk.timer", "ActiveState").unwrap_or_else(|| "unknown".to_string());
+    let health = assess_health(
+        base_mounted,
+        &unit_statuses,
+        &service_statuses,
+        &timer_state,
     );
+
+    let timers = std::iter::once("btrbk.timer".to_string())
+        .chain(
+            maintenance::scheduled_tasks(config)
+                .into_iter()
+                .map(|(task, _)| task.timer_unit()),
+        )
+        .map(|timer| check_service(&timer))
+        .collect();
+
+    StatusReport {
+        uuid: config.uuid.clone(),
+        vhdx: config.vhdx.path.clone(),
+        mount_base: config.mount.base.clone(),
+        base_mounted,
+        boot_command_problem: boot_command_problem(),
+        mounts: list_btrfs_mounts().unwrap_or_default(),
+        subvolumes,
+        subvolumes_error,
+        snapshots: SnapshotReport {
+            count: snapshot_names.len(),
+            latest_time: latest.map(|(stamp, _)| stamp.format("%Y-%m-%dT%H:%M:%S").to_string()),
+            latest: latest.map(|(_, name)| name.clone()),
+        },
+        mount_units: unit_names
+            .iter()
+            .zip(&unit_statuses)
+            .map(|(unit, status)| service_state(unit, status))
+            .collect(),
+        timers,
+        health,
+        exit_code: health.exit_code(),
+    }
 }
 
 fn is_mounted(path: &str) -> bool {
@@ -171,20 +689,6 @@ fn configured_subvolume_lines(config: &Config) -> Vec<String> {
     lines
 }
 
-fn mount_unit_names(config: &Config) -> Vec<String> {
-    let mut units = vec![systemd::mount_unit_filename(&config.mount.base)];
-
-    for backup in config.subvolumes.backup.values() {
-        units.push(systemd::mount_unit_filename(backup.mount()));
-    }
-
-    for transfer in config.subvolumes.transfer.values() {
-        units.push(systemd::mount_unit_filename(&transfer.mount));
-    }
-
-    units
-}
-
 fn format_mount(mount: &crate::utils::cli::MountInfo) -> String {
     format!(
         "{} on {} type {} ({})",
@@ -221,18 +725,11 @@ fn read_unit_status(name: &str) -> UnitStatus {
     }
 }
 
-fn is_failed_mount_status(status: &UnitStatus) -> bool {
+fn is_failed_unit_status(status: &UnitStatus) -> bool {
     status.active_state == "failed"
         || (!status.result.is_empty() && status.result != "success" && status.result != "done")
 }
 
-fn read_unit_property(name: &str, property: &str) -> Option<String> {
-    systemctl_property(name, property)
-        .ok()
-        .map(|value| value.trim().to_string())
-        .filter(|value| !value.is_empty() && value != "n/a")
-}
-
 fn summarize_error(err: &anyhow::Error) -> String {
     for cause in err.chain() {
         if let Some(line) = cause
@@ -257,6 +754,70 @@ fn summarize_error(err: &anyhow::Error) -> String {
 mod tests {
     use super::*;
 
+    #[test]
+    fn service_state_serializes_flags_and_raw_states() {
+        let status = UnitStatus {
+            unit_file_state: "enabled\n".to_string(),
+            active_state: "failed".to_string(),
+            result: "exit-code".to_string(),
+        };
+        let service = service_state("usr.mount", &status);
+        assert!(service.enabled);
+        assert!(!service.active);
+
+        let json = serde_json::to_value(&service).unwrap();
+        assert_eq!(json["name"], "usr.mount");
+        assert_eq!(json["unit_file_state"], "enabled");
+        assert_eq!(json["active_state"], "failed");
+        assert_eq!(
+            serde_json::to_value(Health::InactiveMounts).unwrap(),
+            "inactive_mounts"
+        );
+    }
+
+    fn report_with_units(units: &[(&str, &str)]) -> StatusReport {
+        StatusReport {
+            uuid: None,
+            vhdx: String::new(),
+            mount_base: "/mnt/btrfs".to_string(),
+            base_mounted: true,
+            boot_command_problem: None,
+            mounts: Vec::new(),
+            subvolumes: Vec::new(),
+            subvolumes_error: None,
+            snapshots: SnapshotReport {
+                count: 0,
+                latest: None,
+                latest_time: None,
+            },
+            mount_units: units
+                .iter()
+                .map(|(name, state)| {
+                    let status = UnitStatus {
+                        unit_file_state: state.replace("active", "enabled"),
+                        active_state: state.to_string(),
+                        result: String::new(),
+                    };
+                    service_state(name, &status)
+                })
+                .collect(),
+            timers: Vec::new(),
+            health: Health::Healthy,
+            exit_code: 0,
+        }
+    }
+
+    #[test]
+    fn carry_over_unknown_keeps_last_known_unit_state() {
+        let previous = report_with_units(&[("mnt-btrfs.mount", "active"), ("usr.mount", "active")]);
+        let mut report =
+            report_with_units(&[("mnt-btrfs.mount", "unknown"), ("usr.mount", "failed")]);
+
+        assert_eq!(carry_over_unknown(&mut report, &previous), 1);
+        assert!(report.mount_units[0].active);
+        assert_eq!(report.mount_units[1].active_state, "failed");
+    }
+
     #[test]
     fn summarize_error_prefers_specific_failure_line() {
         let err = anyhow::anyhow!(
@@ -314,6 +875,64 @@ mod tests {
         assert!(lines.iter().any(|line| line == "    @etc [snapshot-only]"));
     }
 
+    #[test]
+    fn etc_status_line_reports_latest_etc_snapshot() {
+        let snapshots = vec![
+            "etc.20240114T0300".to_string(),
+            "etc.20240115T0300".to_string(),
+            "usr.20240116T0300".to_string(),
+            // Another subvolume whose name starts with "etc."
+            "etc.old.20240117T0300".to_string(),
+        ];
+        let latest = latest_snapshot_of(&snapshots, "@etc");
+
+        assert_eq!(
+            etc_status_line(true, true, latest),
+            "@etc (snapshot-only): present, last snapshot 2024-01-15 03:00"
+        );
+        assert_eq!(
+            etc_status_line(true, true, latest_snapshot_of(&snapshots, "@opt")),
+            "@etc (snapshot-only): present, no snapshots yet"
+        );
+        assert!(etc_status_line(false, true, None).contains("missing"));
+    }
+
+    #[test]
+    fn format_short_summary_renders_single_line() {
+        let now = NaiveDateTime::parse_from_str("20240115T0600", "%Y%m%dT%H%M").unwrap();
+        let summary = ShortSummary {
+            mounted: true,
+            failed_units: 0,
+            snapshot_count: 42,
+            latest_snapshot: parse_snapshot_timestamp("usr.20240115T0300"),
+            timer_state: "active".to_string(),
+        };
+
+        assert_eq!(
+            format_short_summary(&summary, now),
+            "btrfs: mounted ok | snapshots: 42 | last: 3h ago | timer: active"
+        );
+        assert!(summary.is_healthy());
+    }
+
+    #[test]
+    fn short_summary_is_unhealthy_with_failed_units() {
+        let now = NaiveDateTime::parse_from_str("20240115T0600", "%Y%m%dT%H%M").unwrap();
+        let summary = ShortSummary {
+            mounted: true,
+            failed_units: 2,
+            snapshot_count: 0,
+            latest_snapshot: None,
+            timer_state: "active".to_string(),
+        };
+
+        assert_eq!(
+            format_short_summary(&summary, now),
+            "btrfs: mounted, 2 failed | snapshots: 0 | last: never | timer: active"
+        );
+        assert!(!summary.is_healthy());
+    }
+
     #[test]
     fn failed_mount_status_detects_failed_active_or_result() {
         let active_failed = UnitStatus {
@@ -332,8 +951,53 @@ mod tests {
             result: "success".to_string(),
         };
 
-        assert!(is_failed_mount_status(&active_failed));
-        assert!(is_failed_mount_status(&result_failed));
-        assert!(!is_failed_mount_status(&success));
+        assert!(is_failed_unit_status(&active_failed));
+        assert!(is_failed_unit_status(&result_failed));
+        assert!(!is_failed_unit_status(&success));
+    }
+
+    #[test]
+    fn assess_health_reports_first_problem() {
+        let unit = |active: &str, result: &str| UnitStatus {
+            unit_file_state: "enabled".to_string(),
+            active_state: active.to_string(),
+            result: result.to_string(),
+        };
+        let active = unit("active", "success");
+        let inactive = unit("inactive", "success");
+        let failed = unit("failed", "exit-code");
+        let ran = std::slice::from_ref(&inactive);
+
+        assert_eq!(
+            assess_health(true, std::slice::from_ref(&active), ran, "active"),
+            Health::Healthy
+        );
+        assert_eq!(
+            assess_health(false, std::slice::from_ref(&failed), ran, "inactive"),
+            Health::NotMounted
+        );
+        assert_eq!(
+            assess_health(true, &[inactive.clone(), failed.clone()], ran, "active"),
+            Health::FailedUnits
+        );
+        // The last btrbk or scrub run failed while every mount is fine
+        assert_eq!(
+            assess_health(
+                true,
+                std::slice::from_ref(&active),
+                &[inactive.clone(), failed],
+                "active"
+            ),
+            Health::FailedUnits
+        );
+        assert_eq!(
+            assess_health(true, &[active.clone(), inactive.clone()], ran, "active"),
+            Health::InactiveMounts
+        );
+        assert_eq!(
+            assess_health(true, &[active], ran, "inactive"),
+            Health::TimerInactive
+        );
+        assert_eq!(Health::TimerInactive.exit_code(), 5);
     }
 }
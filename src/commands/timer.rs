@@ -0,0 +1,73 @@
+//! Pause and resume scheduled snapshots
+//!
+//! Pausing only stops btrbk.timer; the unit stays enabled, so scheduled
+//! snapshots come back on the next boot even without `timer resume`.
+
+use anyhow::Result;
+use console::style;
+
+use crate::utils::cli::read_unit_property;
+use crate::utils::prompt::{info, kv, section, success};
+use crate::utils::shell::run as shell_run;
+
+const TIMER_UNIT: &str = "btrbk.timer";
+
+pub fn pause() -> Result<()> {
+    println!("{}", style("Pause Scheduled Snapshots").bold().cyan());
+    println!();
+
+    if read_property("ActiveState").as_deref() != Some("active") {
+        success(&format!("{} is already stopped", TIMER_UNIT));
+    } else {
+        shell_run("systemctl", &["stop", TIMER_UNIT])?;
+        success(&format!("{} stopped", TIMER_UNIT));
+    }
+    info("Snapshots resume with 'wslarc timer resume' or at the next boot");
+
+    Ok(())
+}
+
+pub fn resume() -> Result<()> {
+    println!("{}", style("Resume Scheduled Snapshots").bold().cyan());
+    println!();
+
+    shell_run("systemctl", &["start", TIMER_UNIT])?;
+    success(&format!("{} started", TIMER_UNIT));
+    print_schedule();
+
+    Ok(())
+}
+
+pub fn status() -> Result<()> {
+    println!("{}", style("Snapshot Timer").bold().cyan());
+    print_schedule();
+
+    Ok(())
+}
+
+fn print_schedule() {
+    section(TIMER_UNIT);
+    let active = read_property("ActiveState").unwrap_or_else(|| "unknown".to_string());
+    let state = if active == "active" {
+        "running"
+    } else {
+        "paused"
+    };
+    kv("State", &format!("{} ({})", state, active));
+    kv(
+        "Enabled",
+        &read_property("UnitFileState").unwrap_or_else(|| "unknown".to_string()),
+    );
+    kv(
+        "Next run",
+        &read_property("NextElapseUSecRealtime").unwrap_or_else(|| "not scheduled".to_string()),
+    );
+    kv(
+        "Last run",
+        &read_property("LastTriggerUSec").unwrap_or_else(|| "never".to_string()),
+    );
+}
+
+fn read_property(property: &str) -> Option<String> {
+    read_unit_property(TIMER_UNIT, property)
+}
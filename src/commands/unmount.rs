@@ -1,42 +1,89 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use console::style;
+use std::fs;
+use std::path::Path;
 
+use crate::commands::mount::has_usr_subvol;
 use crate::config::Config;
-use crate::generators::systemd;
-use crate::utils::prompt::{confirm_or_yes, info, step, success};
-use crate::utils::shell::run_or_dry;
+use crate::generators::{ext4_sync, is_generated, maintenance, systemd};
+use crate::utils::cli::list_directory_names;
+use crate::utils::prompt::{confirm_destructive, confirm_or_yes, info, step, success, warn};
+use crate::utils::scope::SYSTEM_UNIT_DIR;
+use crate::utils::shell::{run_or_dry, CommandRunner, RealRunner};
+
+/// Options for `wslarc unmount`
+pub struct UnmountOptions {
+    pub dry_run: bool,
+    /// Only disable the timers; mounts stay as they are
+    pub keep_units: bool,
+    /// Only disable the mount units; scheduled snapshots keep running
+    pub keep_timer: bool,
+}
+
+pub fn run(config: &Config, yes: bool, options: &UnmountOptions) -> Result<()> {
+    let dry_run = options.dry_run;
+    let disable_units = !options.keep_units;
+    let disable_timers = !options.keep_timer;
 
-pub fn run(config: &Config, yes: bool, dry_run: bool) -> Result<()> {
     println!("{}", style("WSL Btrfs Unmount").bold().cyan());
 
     println!();
-    println!(
-        "{}",
-        style("This will disable all wslarc systemd mount units.").yellow()
-    );
-    println!("After restart, the Btrfs subvolumes will not be mounted.");
+    if disable_units {
+        println!(
+            "{}",
+            style("This will disable all wslarc systemd mount units.").yellow()
+        );
+        println!("After restart, the Btrfs subvolumes will not be mounted.");
+    }
+    if disable_timers {
+        println!(
+            "{}",
+            style("This will disable btrbk.timer and the maintenance timers.").yellow()
+        );
+        println!("Scheduled snapshots and maintenance stop until the timers are enabled again.");
+    }
     println!();
 
-    if !confirm_or_yes("Disable all mount units?", false, yes)? {
+    let prompt = match (disable_units, disable_timers) {
+        (true, true) => "Disable all mount units and timers?",
+        (true, false) => "Disable all mount units?",
+        _ => "Disable the timers?",
+    };
+    if !confirm_or_yes(prompt, false, yes)? {
         println!("Aborted.");
         return Ok(());
     }
 
-    let total_steps = 2;
+    let total_steps = disable_units as u32 + disable_timers as u32;
+    let mut num = 0;
 
-    // Step 1: Disable mount units
-    step(1, total_steps, "Disable systemd mount units");
-    disable_mount_units(config, dry_run)?;
+    if disable_units {
+        num += 1;
+        step(num, total_steps, "Disable systemd mount units");
+        // Prune first: `systemctl disable` reloads the manager, which then
+        // forgets the deleted unit files without another daemon-reload
+        prune_orphaned_units(&RealRunner, config, SYSTEM_UNIT_DIR, yes, dry_run)?;
+        disable_mount_units(config, dry_run)?;
+    }
 
-    // Step 2: Disable btrbk timer
-    step(2, total_steps, "Disable btrbk timer");
-    run_or_dry("systemctl", &["disable", "btrbk.timer"], dry_run)?;
-    success("btrbk.timer disabled");
+    if disable_timers {
+        num += 1;
+        step(num, total_steps, "Disable timers");
+        // Without a restart to come, stop the timers right away
+        disable_timer_units(config, !disable_units, dry_run)?;
+    }
 
     // Done
     println!();
     println!("{}", style("Unmount setup complete!").green().bold());
     println!();
+    if !disable_units {
+        println!(
+            "Mounts are unchanged. Re-enable the timers with {}",
+            style("wslarc mount").cyan()
+        );
+        return Ok(());
+    }
     println!("Restart WSL to apply: {}", style("wsl --shutdown").cyan());
     println!();
     println!("Note: The [boot] command in /etc/wsl.conf is still active.");
@@ -49,21 +96,8 @@ pub fn run(config: &Config, yes: bool, dry_run: bool) -> Result<()> {
 }
 
 fn disable_mount_units(config: &Config, dry_run: bool) -> Result<()> {
-    // Disable base mount
-    let base_unit = systemd::mount_unit_filename(&config.mount.base);
-    run_or_dry("systemctl", &["disable", &base_unit], dry_run)?;
-    info(&format!("{} disabled", base_unit));
-
-    // Disable backup mounts
-    for backup in config.subvolumes.backup.values() {
-        let unit = systemd::mount_unit_filename(backup.mount());
-        run_or_dry("systemctl", &["disable", &unit], dry_run)?;
-        info(&format!("{} disabled", unit));
-    }
-
-    // Disable transfer mounts
-    for transfer in config.subvolumes.transfer.values() {
-        let unit = systemd::mount_unit_filename(&transfer.mount);
+    // Base mount first, then backup and transfer mounts
+    for unit in systemd::mount_unit_names(config) {
         run_or_dry("systemctl", &["disable", &unit], dry_run)?;
         info(&format!("{} disabled", unit));
     }
@@ -71,3 +105,115 @@ fn disable_mount_units(config: &Config, dry_run: bool) -> Result<()> {
     success("All mount units disabled");
     Ok(())
 }
+
+/// Disable btrbk.timer and the maintenance timers; `now` also stops them
+fn disable_timer_units(config: &Config, now: bool, dry_run: bool) -> Result<()> {
+    let timers = std::iter::once("btrbk.timer".to_string()).chain(
+        maintenance::scheduled_tasks(config)
+            .into_iter()
+            .map(|(task, _)| task.timer_unit()),
+    );
+    for timer in timers {
+        let mut args = vec!["disable"];
+        if now {
+            args.push("--now");
+        }
+        args.push(&timer);
+        run_or_dry("systemctl", &args, dry_run)?;
+        success(&format!("{} disabled", timer));
+    }
+    Ok(())
+}
+
+/// `.mount` units in `unit_dir` that wslarc generated but that no longer
+/// belong to any configured subvolume, e.g. after removing one from the config
+pub fn orphaned_mount_units(config: &Config, unit_dir: &str) -> Vec<String> {
+    let mut expected = systemd::mount_unit_names(config);
+    if has_usr_subvol(config) {
+        expected.push(ext4_sync::ext4_mount_unit_filename(config));
+    }
+
+    list_directory_names(unit_dir)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|name| name.ends_with(".mount") && !expected.contains(name))
+        .filter(|name| {
+            let path = Path::new(unit_dir).join(name);
+            !path.is_symlink()
+                && fs::read_to_string(&path).is_ok_and(|content| is_generated(&content))
+        })
+        .collect()
+}
+
+/// Offer to disable and delete orphaned mount units; returns how many were
+/// removed so the caller knows whether systemd needs a reload
+pub fn prune_orphaned_units(
+    runner: &dyn CommandRunner,
+    config: &Config,
+    unit_dir: &str,
+    yes: bool,
+    dry_run: bool,
+) -> Result<usize> {
+    let orphans = orphaned_mount_units(config, unit_dir);
+    if orphans.is_empty() {
+        return Ok(0);
+    }
+
+    for unit in &orphans {
+        warn(&format!("{} is no longer in the config", unit));
+    }
+    if !confirm_destructive("Stop, disable and delete these mount units?", yes)? {
+        info("Orphaned mount units left in place");
+        return Ok(0);
+    }
+
+    for unit in &orphans {
+        // Stop it too, so the mount does not outlive its unit file
+        runner.run_or_dry("systemctl", &["disable", "--now", unit], dry_run)?;
+        let path = format!("{}/{}", unit_dir, unit);
+        if dry_run {
+            info(&format!("[dry-run] Would delete {}", path));
+        } else {
+            fs::remove_file(&path).with_context(|| format!("Failed to delete {}", path))?;
+        }
+        success(&format!("{} removed", unit));
+    }
+    Ok(orphans.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generators::generated_header;
+    use crate::utils::shell::MockRunner;
+
+    #[test]
+    fn prune_removes_only_generated_units_missing_from_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let unit_dir = dir.path().to_str().unwrap();
+        let mut cfg = Config::default();
+        cfg.set_user("alice");
+
+        let configured = systemd::mount_unit_filename("/home/alice");
+        let generated = format!("{}\n[Unit]\n", generated_header());
+        fs::write(dir.path().join(&configured), &generated).unwrap();
+        fs::write(dir.path().join("opt-old.mount"), &generated).unwrap();
+        fs::write(dir.path().join("srv-data.mount"), "[Unit]\n").unwrap();
+        fs::write(dir.path().join("btrbk.service"), &generated).unwrap();
+
+        assert_eq!(orphaned_mount_units(&cfg, unit_dir), vec!["opt-old.mount"]);
+
+        let runner = MockRunner::default();
+        assert_eq!(
+            prune_orphaned_units(&runner, &cfg, unit_dir, true, false).unwrap(),
+            1
+        );
+        assert_eq!(
+            runner.calls(),
+            vec!["systemctl disable --now opt-old.mount"]
+        );
+        assert!(!dir.path().join("opt-old.mount").exists());
+        assert!(dir.path().join(&configured).exists());
+        assert!(dir.path().join("srv-data.mount").exists());
+    }
+}
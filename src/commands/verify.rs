@@ -0,0 +1,135 @@
+//! Check that the generated units are still wanted at boot
+//!
+//! `systemctl enable` only creates symlinks under `<target>.wants`. If those
+//! are removed (e.g. by `systemctl preset-all`) the units stay on disk but
+//! never start, so verify looks at the links themselves. It also checks that
+//! the wsl.conf boot command still runs this version of wslarc.
+
+use anyhow::{bail, Result};
+use console::style;
+use std::fs;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::generators::systemd;
+use crate::utils::prompt::{confirm_or_yes, info, section, success, warn};
+use crate::utils::scope::SYSTEM_UNIT_DIR;
+use crate::utils::shell::run_or_dry;
+use crate::utils::wsl::boot_command_problem;
+
+#[derive(Debug, PartialEq)]
+enum LinkState {
+    Linked,
+    /// Unit file exists but nothing in `<target>.wants` points to it
+    Missing,
+    /// Unit file itself is gone; enabling would not help
+    NoUnitFile,
+}
+
+pub fn run(config: &Config, yes: bool, dry_run: bool) -> Result<()> {
+    println!("{}", style("Verify Boot Links").bold().cyan());
+
+    section("Wanted units");
+    let mut missing = Vec::new();
+    let mut no_unit_file = Vec::new();
+    for (unit, wanted_by) in systemd::wanted_units(config) {
+        match link_state(Path::new(SYSTEM_UNIT_DIR), &unit, wanted_by) {
+            LinkState::Linked => success(&format!("{} (wanted by {})", unit, wanted_by)),
+            LinkState::Missing => {
+                warn(&format!("{} is not linked in {}.wants", unit, wanted_by));
+                missing.push(unit);
+            }
+            LinkState::NoUnitFile => {
+                warn(&format!("{} unit file is missing", unit));
+                no_unit_file.push(unit);
+            }
+        }
+    }
+
+    section("Boot command");
+    match boot_command_problem() {
+        None => success("wsl.conf [boot] command runs this wslarc version"),
+        Some(problem) => {
+            warn(&problem);
+            info("Run 'sudo wslarc mount' to reinstall the boot binary");
+        }
+    }
+    println!();
+
+    if !no_unit_file.is_empty() {
+        info("Run 'sudo wslarc mount' to regenerate missing unit files");
+    }
+
+    if missing.is_empty() {
+        if no_unit_file.is_empty() {
+            println!("{}", style("Verify complete!").bold().green());
+            return Ok(());
+        }
+        bail!("{} unit file(s) missing", no_unit_file.len());
+    }
+
+    if !confirm_or_yes(
+        &format!("Re-enable {} unit(s) to recreate the links?", missing.len()),
+        true,
+        yes,
+    )? {
+        bail!("{} unit(s) not wanted at boot", missing.len());
+    }
+    for unit in &missing {
+        run_or_dry("systemctl", &["enable", unit], dry_run)?;
+    }
+    if dry_run {
+        return Ok(());
+    }
+    success(&format!("Re-enabled {} unit(s)", missing.len()));
+
+    if !no_unit_file.is_empty() {
+        bail!("{} unit file(s) missing", no_unit_file.len());
+    }
+    println!();
+    println!("{}", style("Verify complete!").bold().green());
+
+    Ok(())
+}
+
+fn link_state(unit_dir: &Path, unit: &str, wanted_by: &str) -> LinkState {
+    if !unit_dir.join(unit).exists() {
+        return LinkState::NoUnitFile;
+    }
+    // symlink_metadata so a dangling link still counts as present
+    let link = unit_dir.join(format!("{}.wants", wanted_by)).join(unit);
+    if fs::symlink_metadata(link).is_ok() {
+        LinkState::Linked
+    } else {
+        LinkState::Missing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn link_state_distinguishes_missing_links_and_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let unit_dir = dir.path();
+        fs::write(unit_dir.join("usr.mount"), "[Mount]\n").unwrap();
+
+        assert_eq!(
+            link_state(unit_dir, "usr.mount", "multi-user.target"),
+            LinkState::Missing
+        );
+        assert_eq!(
+            link_state(unit_dir, "opt.mount", "multi-user.target"),
+            LinkState::NoUnitFile
+        );
+
+        let wants = unit_dir.join("multi-user.target.wants");
+        fs::create_dir(&wants).unwrap();
+        std::os::unix::fs::symlink(unit_dir.join("usr.mount"), wants.join("usr.mount")).unwrap();
+        assert_eq!(
+            link_state(unit_dir, "usr.mount", "multi-user.target"),
+            LinkState::Linked
+        );
+    }
+}
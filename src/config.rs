@@ -1,9 +1,16 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs;
+use std::io::{self, Read};
 use std::path::Path;
 
+use crate::generators::btrbk;
+use crate::utils::mount_options;
+
+/// `--config -` reads the config from stdin
+pub const STDIN_PATH: &str = "-";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub vhdx: VhdxConfig,
@@ -14,6 +21,12 @@ pub struct Config {
     /// Ext4 root sync config (for systemd version sync)
     #[serde(default)]
     pub ext4_sync: Ext4SyncConfig,
+    /// WSL instance settings
+    #[serde(default)]
+    pub wsl: WslConfig,
+    /// Scheduled scrub and balance
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
 
     /// UUID of the Btrfs filesystem (set after formatting)
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -38,12 +51,75 @@ impl Default for Ext4SyncConfig {
     }
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MaintenanceConfig {
+    /// OnCalendar= schedule for `wslarc scrub` (unset: no timer)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scrub_schedule: Option<String>,
+    /// OnCalendar= schedule for `wslarc balance` (unset: no timer)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub balance_schedule: Option<String>,
+    /// OnCalendar= schedule for `wslarc trim` (unset: no timer)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trim_schedule: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WslConfig {
+    /// Distro that owns the bare-mounted VHDX (default: $WSL_DISTRO_NAME)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub distro_name: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VhdxConfig {
     /// Windows path to the VHDX file
     pub path: String,
     /// Btrfs label
     pub label: String,
+    /// How init finds the device after `wsl.exe --mount --bare`
+    #[serde(default, skip_serializing_if = "DeviceDetect::is_default")]
+    pub detect: DeviceDetect,
+    /// Virtual disk size for `detect = "by-size"` (e.g. "256G")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeviceDetect {
+    /// Any device that was not present before attaching
+    #[default]
+    LsblkDiff,
+    /// A new device without a filesystem
+    BlankDevice,
+    /// A new device whose size equals `vhdx.size`
+    BySize,
+}
+
+impl DeviceDetect {
+    fn is_default(&self) -> bool {
+        *self == DeviceDetect::default()
+    }
+}
+
+/// Parse a size like "256G" or "512MiB" into bytes (binary units)
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number: u64 = number.parse().ok()?;
+    let shift = match unit.trim().trim_end_matches("iB").trim_end_matches('B') {
+        "" => 0,
+        "K" | "k" => 10,
+        "M" | "m" => 20,
+        "G" | "g" => 30,
+        "T" | "t" => 40,
+        _ => return None,
+    };
+    number.checked_mul(1 << shift)
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -53,6 +129,48 @@ pub struct UserConfig {
     /// useradd options (e.g., "-m -G wheel -s /bin/zsh")
     #[serde(default = "default_useradd_options")]
     pub options: String,
+    /// Further users, each with an @home_<user> subvolume at /home/<user>
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra: Vec<String>,
+    /// Login shell for created users; overrides -s/--shell in options
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<String>,
+}
+
+impl UserConfig {
+    /// Login shell new users get: `shell`, else -s/--shell from `options`
+    pub fn login_shell(&self) -> Option<String> {
+        self.shell.clone().or_else(|| {
+            let mut tokens = self.options.split_whitespace();
+            while let Some(token) = tokens.next() {
+                if token == "-s" || token == "--shell" {
+                    return tokens.next().map(str::to_string);
+                }
+                if let Some(shell) = token.strip_prefix("--shell=") {
+                    return Some(shell.to_string());
+                }
+            }
+            None
+        })
+    }
+
+    /// useradd arguments for `user`, with any shell option replaced by `login_shell`
+    pub fn useradd_args(&self, user: &str) -> Vec<String> {
+        let mut args = Vec::new();
+        let mut tokens = self.options.split_whitespace();
+        while let Some(token) = tokens.next() {
+            if token == "-s" || token == "--shell" {
+                tokens.next();
+            } else if !token.starts_with("--shell=") {
+                args.push(token.to_string());
+            }
+        }
+        if let Some(shell) = self.login_shell() {
+            args.extend(["-s".to_string(), shell]);
+        }
+        args.push(user.to_string());
+        args
+    }
 }
 
 fn default_useradd_options() -> String {
@@ -63,15 +181,48 @@ fn default_useradd_options() -> String {
 pub struct MountConfig {
     /// Base mount point for Btrfs volume
     pub base: String,
-    /// Mount options for base volume (default: compress=zstd:3,noatime,nofail)
-    #[serde(default = "default_base_options")]
-    pub options: String,
+    /// Mount options for base volume; overrides `compression` when set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub options: Option<String>,
+    /// Compression for the default options, e.g. "zstd:1" or "none" (default: zstd:3)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<String>,
+    /// Order subvolume mounts with path-based RequiresMountsFor= instead of unit names
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub requires_mounts_for: bool,
+    /// Enable btrfs quotas at init so status can report per-subvolume usage
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub quotas: bool,
+    /// Mount the base volume read-only; snapshot and restore remount it
+    /// read-write while they run
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub base_readonly: bool,
 }
 
-fn default_base_options() -> String {
-    "compress=zstd:3,noatime,nofail".to_string()
+impl MountConfig {
+    /// Options the base volume and subvolumes without their own are mounted with
+    pub fn effective_options(&self) -> String {
+        self.options
+            .clone()
+            .unwrap_or_else(|| default_base_options(self.compression.as_deref()))
+    }
 }
 
+/// Default mount options: `compress=<compression>,noatime,nofail`, where
+/// "none" (or "no") leaves compression off
+pub fn default_base_options(compression: Option<&str>) -> String {
+    match compression.unwrap_or(DEFAULT_COMPRESSION) {
+        "none" | "no" => "noatime,nofail".to_string(),
+        compression => format!("compress={},noatime,nofail", compression),
+    }
+}
+
+const DEFAULT_COMPRESSION: &str = "zstd:3";
+
+/// `mount.options` that configs written before `mount.compression` existed
+/// always carry; it means "the default", not a deliberate override
+const LEGACY_DEFAULT_OPTIONS: &str = "compress=zstd:3,noatime,nofail";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SubvolumesConfig {
     /// A-class: backup targets (subvol_name -> config)
@@ -82,6 +233,42 @@ pub struct SubvolumesConfig {
     pub transfer: HashMap<String, TransferSubvol>,
 }
 
+/// Layout template for `init --layout`: only a `[subvolumes]` section, whose
+/// classes replace the configured ones when present
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SubvolumeLayout {
+    subvolumes: LayoutSubvolumes,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LayoutSubvolumes {
+    backup: Option<HashMap<String, BackupSubvol>>,
+    exclude: Option<ExcludeConfig>,
+    transfer: Option<HashMap<String, TransferSubvol>>,
+}
+
+impl SubvolumeLayout {
+    pub fn load(path: &str) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read layout file: {}", path))?;
+        let layout: SubvolumeLayout = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse layout file: {}", path))?;
+        let subvolumes = &layout.subvolumes;
+        if subvolumes.backup.is_none()
+            && subvolumes.exclude.is_none()
+            && subvolumes.transfer.is_none()
+        {
+            bail!(
+                "Layout file {} sets none of subvolumes.backup, .exclude or .transfer",
+                path
+            );
+        }
+        Ok(layout)
+    }
+}
+
 /// A-class backup subvolume config
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
@@ -93,6 +280,10 @@ pub enum BackupSubvol {
         mount: String,
         #[serde(default = "default_subvol_options")]
         options: Option<String>,
+        /// Options added after `options` (or the global options), replacing
+        /// any with the same key
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        options_append: Option<String>,
     },
 }
 
@@ -110,6 +301,13 @@ impl BackupSubvol {
             BackupSubvol::Full { options, .. } => options.as_deref(),
         }
     }
+
+    pub fn options_append(&self) -> Option<&str> {
+        match self {
+            BackupSubvol::Simple(_) => None,
+            BackupSubvol::Full { options_append, .. } => options_append.as_deref(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -130,6 +328,10 @@ pub struct TransferSubvol {
     /// Custom mount options (default: compress=zstd:3,noatime,nofail)
     #[serde(default = "default_subvol_options")]
     pub options: Option<String>,
+    /// Options added after `options` (or the global options), replacing any
+    /// with the same key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub options_append: Option<String>,
 }
 
 fn default_subvol_options() -> Option<String> {
@@ -146,30 +348,90 @@ pub struct BtrbkConfig {
     pub preserve: String,
     /// Systemd timer schedule
     pub timer_schedule: String,
+    /// Backup targets (local directories or ssh://[user@]host[:port]/path)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<String>,
+    /// Glob patterns selecting snapshotted subvolumes from those on disk
+    /// (default: the backup subvolumes and @etc)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// Glob patterns removed from the snapshot set (e.g. "@var_*")
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// Accept snapshotting only one of @usr and @var_lib_pacman
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub allow_split_pacman_db: bool,
+    /// Attributes init applies when creating the snapshot_dir subvolume
+    #[serde(default, skip_serializing_if = "SnapshotDirOptions::is_default")]
+    pub snapshot_dir_options: SnapshotDirOptions,
+    /// btrbk binary to run (default: btrbk from $PATH)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub binary: Option<String>,
+    /// Where btrbk.conf is written and read (default: /etc/btrbk/btrbk.conf)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_path: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotDirOptions {
+    /// Whether to disable COW (chattr +C)
+    #[serde(default)]
+    pub nodatacow: bool,
+    /// Btrfs compression property (e.g. "zstd"; btrfs property set)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<String>,
+}
+
+impl SnapshotDirOptions {
+    fn is_default(&self) -> bool {
+        *self == SnapshotDirOptions::default()
+    }
 }
 
 impl Config {
     /// Load config from file, or return default if file doesn't exist
     pub fn load_or_default(path: &str) -> Result<Self> {
-        if Path::new(path).exists() {
+        if path == STDIN_PATH || Path::new(path).exists() {
             Self::load(path)
         } else {
             Ok(Self::default())
         }
     }
 
-    /// Load config from file
+    /// Load config from file; `-` reads TOML from stdin
     pub fn load(path: &str) -> Result<Self> {
-        let content = fs::read_to_string(path)
+        if path == STDIN_PATH {
+            return Self::load_from_reader(io::stdin().lock(), "stdin");
+        }
+        let file = fs::File::open(path)
             .with_context(|| format!("Failed to read config file: {}", path))?;
+        Self::load_from_reader(file, path)
+    }
+
+    /// Load config from any reader; `source` names it in error messages
+    pub fn load_from_reader(mut reader: impl Read, source: &str) -> Result<Self> {
+        let mut content = String::new();
+        reader
+            .read_to_string(&mut content)
+            .with_context(|| format!("Failed to read config file: {}", source))?;
         let mut config: Config = toml::from_str(&content)
-            .with_context(|| format!("Failed to parse config file: {}", path))?;
+            .with_context(|| format!("Failed to parse config file: {}", source))?;
+        // Otherwise the stored default would silently override mount.compression
+        if config.mount.options.as_deref() == Some(LEGACY_DEFAULT_OPTIONS) {
+            config.mount.options = None;
+        }
         config.expand_variables();
+        config
+            .validate()
+            .with_context(|| format!("Invalid config file: {}", source))?;
         Ok(config)
     }
 
     /// Save config to file
     pub fn save(&self, path: &str) -> Result<()> {
+        if path == STDIN_PATH {
+            bail!("Config was read from stdin; pass --config <file> to save changes");
+        }
         let dir = Path::new(path).parent().unwrap_or(Path::new("/"));
         fs::create_dir_all(dir)
             .with_context(|| format!("Failed to create config directory: {}", dir.display()))?;
@@ -181,7 +443,7 @@ impl Config {
     }
 
     /// Expand $USER and other variables in paths
-    fn expand_variables(&mut self) {
+    pub fn expand_variables(&mut self) {
         let user = self.get_user();
 
         // Expand in backup subvolumes
@@ -196,6 +458,205 @@ impl Config {
         for subvol in self.subvolumes.transfer.values_mut() {
             subvol.mount = subvol.mount.replace("$USER", &user);
         }
+
+        // Extra users get their own home subvolume unless one is configured
+        for extra in &self.user.extra {
+            self.subvolumes
+                .backup
+                .entry(extra_home_subvolume(extra))
+                .or_insert_with(|| BackupSubvol::Simple(format!("/home/{}", extra)));
+        }
+    }
+
+    /// Reject subvolume names and paths that are not safe to pass unquoted to
+    /// btrfs, mount and systemd units: names must match `@[A-Za-z0-9_][A-Za-z0-9_.-]*`, mount
+    /// points must be absolute paths without `.`, `..` or special characters
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+        for (name, mount) in self.subvolume_mounts() {
+            problems.extend(subvolume_name_problem(&name));
+            problems.extend(mount_point_problem(&mount).map(|p| format!("{}: {}", name, p)));
+        }
+        problems.extend(subvolume_name_problem(&self.subvolumes.exclude.parent));
+        for path in &self.subvolumes.exclude.paths {
+            problems.extend(exclude_path_problem(path));
+        }
+        problems
+            .extend(mount_point_problem(&self.mount.base).map(|p| format!("mount.base: {}", p)));
+        if let Err(e) = btrbk::parse_preserve(&self.btrbk.preserve) {
+            problems.push(format!("btrbk.preserve: {}", e));
+        }
+        if let Err(e) = btrbk::parse_preserve_min(&self.btrbk.preserve_min) {
+            problems.push(format!("btrbk.preserve_min: {}", e));
+        }
+
+        if !problems.is_empty() {
+            bail!("{}", problems.join("\n"));
+        }
+        Ok(())
+    }
+
+    /// Non-fatal configuration problems worth showing before acting on the config
+    pub fn warnings(&self) -> Vec<String> {
+        let source = if self.mount.options.is_some() {
+            "mount.options"
+        } else {
+            "mount.compression"
+        };
+        let mut warnings: Vec<String> =
+            mount_options::check_options(&self.mount.effective_options())
+                .into_iter()
+                .map(|warning| format!("{}: {}", source, warning))
+                .collect();
+        if let (Some(_), Some(compression)) = (&self.mount.options, &self.mount.compression) {
+            warnings.push(format!(
+                "mount.compression = \"{}\" is ignored because mount.options is set; \
+                 put compress= in mount.options or unset it",
+                compression
+            ));
+        }
+
+        // options and options_append are checked together
+        let own_options = |options: Option<&str>, append: Option<&str>| {
+            let parts: Vec<&str> = options.into_iter().chain(append).collect();
+            (!parts.is_empty()).then(|| parts.join(","))
+        };
+        let mut subvol_options: Vec<(&String, String)> = self
+            .subvolumes
+            .backup
+            .iter()
+            .filter_map(|(name, backup)| {
+                own_options(backup.options(), backup.options_append()).map(|o| (name, o))
+            })
+            .chain(
+                self.subvolumes
+                    .transfer
+                    .iter()
+                    .filter_map(|(name, transfer)| {
+                        own_options(
+                            transfer.options.as_deref(),
+                            transfer.options_append.as_deref(),
+                        )
+                        .map(|o| (name, o))
+                    }),
+            )
+            .collect();
+        subvol_options.sort();
+        for (name, options) in subvol_options {
+            for warning in mount_options::check_options(&options) {
+                warnings.push(format!("{} options: {}", name, warning));
+            }
+        }
+
+        warnings.extend(self.exclude_warnings());
+        warnings.extend(self.snapshot_dir_warnings());
+        warnings
+    }
+
+    /// btrbk.snapshot_dir is relative to the volume; an absolute path or `..`
+    /// could leave the Btrfs filesystem, where snapshots cannot be created
+    pub fn snapshot_dir_location_problem(&self) -> Option<String> {
+        let snapshot_dir = &self.btrbk.snapshot_dir;
+        let escapes = snapshot_dir.starts_with('/')
+            || snapshot_dir.is_empty()
+            || snapshot_dir.split('/').any(|part| part == "..");
+        escapes.then(|| {
+            format!(
+                "btrbk.snapshot_dir '{}' must be a relative path inside the Btrfs volume \
+                 (e.g. '.snapshots'), so snapshots stay on the same filesystem",
+                snapshot_dir
+            )
+        })
+    }
+
+    /// Snapshots stored inside a snapshotted subvolume are captured by the next
+    /// snapshot of that subvolume, so usage grows with every run
+    fn snapshot_dir_warnings(&self) -> Vec<String> {
+        if let Some(problem) = self.snapshot_dir_location_problem() {
+            return vec![problem];
+        }
+        let snapshot_dir = self.btrbk.snapshot_dir.trim_matches('/');
+        let top_level = snapshot_dir.split('/').next().unwrap_or_default();
+
+        let mut snapshotted: Vec<&str> = self
+            .subvolumes
+            .backup
+            .keys()
+            .map(String::as_str)
+            .chain(["@etc"])
+            .collect();
+        snapshotted.sort();
+
+        snapshotted
+            .into_iter()
+            .filter(|subvol| *subvol == top_level)
+            .map(|subvol| {
+                format!(
+                    "btrbk.snapshot_dir '{}' is inside snapshotted subvolume '{}'; every snapshot \
+                     would include the previous ones. Use a top-level subvolume such as '.snapshots'",
+                    self.btrbk.snapshot_dir, subvol
+                )
+            })
+            .collect()
+    }
+
+    /// Excludes are nested in the parent subvolume, so they only land in the
+    /// user's home if the parent is mounted there
+    fn exclude_warnings(&self) -> Vec<String> {
+        let exclude = &self.subvolumes.exclude;
+        if exclude.paths.is_empty() {
+            return Vec::new();
+        }
+
+        let Some(parent) = self.subvolumes.backup.get(&exclude.parent) else {
+            return vec![format!(
+                "exclude parent '{}' is not a backup subvolume; excluded paths will not be mounted",
+                exclude.parent
+            )];
+        };
+
+        let user = self.get_user();
+        if user.is_empty() {
+            return Vec::new();
+        }
+        let home = format!("/home/{}", user);
+        if parent.mount().trim_end_matches('/') == home {
+            return Vec::new();
+        }
+
+        vec![format!(
+            "exclude parent '{}' is mounted at {}, not {}; excluded paths will appear as {}/{} instead of {}/{}",
+            exclude.parent,
+            parent.mount(),
+            home,
+            parent.mount().trim_end_matches('/'),
+            exclude.paths[0],
+            home,
+            exclude.paths[0]
+        )]
+    }
+
+    /// Name of the current WSL distro, from config or $WSL_DISTRO_NAME
+    pub fn distro_name(&self) -> Option<String> {
+        self.wsl
+            .distro_name
+            .clone()
+            .or_else(|| std::env::var("WSL_DISTRO_NAME").ok())
+            .filter(|name| !name.is_empty())
+    }
+
+    /// Backup subvolumes sorted by name, so output and operation order are stable
+    pub fn sorted_backup(&self) -> Vec<(&String, &BackupSubvol)> {
+        let mut entries: Vec<_> = self.subvolumes.backup.iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        entries
+    }
+
+    /// Transfer subvolumes sorted by name, so output and operation order are stable
+    pub fn sorted_transfer(&self) -> Vec<(&String, &TransferSubvol)> {
+        let mut entries: Vec<_> = self.subvolumes.transfer.iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        entries
     }
 
     /// Get the target user
@@ -203,11 +664,284 @@ impl Config {
         self.user.name.clone()
     }
 
+    /// Replace the subvolume classes that `layout` sets, keeping the rest
+    ///
+    /// Paths keep their `$USER` placeholders: init may not know the user yet,
+    /// so expanding and validating the result is left to the caller.
+    pub fn apply_layout(&mut self, layout: SubvolumeLayout) {
+        let LayoutSubvolumes {
+            backup,
+            exclude,
+            transfer,
+        } = layout.subvolumes;
+        if let Some(backup) = backup {
+            self.subvolumes.backup = backup;
+        }
+        if let Some(exclude) = exclude {
+            self.subvolumes.exclude = exclude;
+        }
+        if let Some(transfer) = transfer {
+            self.subvolumes.transfer = transfer;
+        }
+    }
+
+    /// The target user followed by the extra users
+    pub fn users(&self) -> Vec<String> {
+        std::iter::once(&self.user.name)
+            .chain(&self.user.extra)
+            .filter(|user| !user.is_empty())
+            .cloned()
+            .collect()
+    }
+
+    /// (user, home subvolume) pairs; excluded paths are nested in each home
+    pub fn home_subvolumes(&self) -> Vec<(String, String)> {
+        let mut homes = vec![(self.get_user(), self.subvolumes.exclude.parent.clone())];
+        homes.extend(
+            self.user
+                .extra
+                .iter()
+                .map(|user| (user.clone(), extra_home_subvolume(user))),
+        );
+        homes
+    }
+
     /// Set user and expand variables in paths
     pub fn set_user(&mut self, user: &str) {
         self.user.name = user.to_string();
         self.expand_variables();
     }
+
+    /// Fields that differ from `self` to `other`, sorted by dotted key
+    pub fn diff(&self, other: &Config) -> Result<Vec<FieldChange>> {
+        let old = flatten_fields(self)?;
+        let new = flatten_fields(other)?;
+        let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+        Ok(keys
+            .into_iter()
+            .filter(|key| old.get(*key) != new.get(*key))
+            .map(|key| FieldChange {
+                key: key.clone(),
+                old: old.get(key).cloned(),
+                new: new.get(key).cloned(),
+            })
+            .collect())
+    }
+
+    /// Backup and transfer mount points by subvolume name, sorted by name
+    pub fn subvolume_mounts(&self) -> Vec<(String, String)> {
+        let mut mounts: Vec<(String, String)> = self
+            .subvolumes
+            .backup
+            .iter()
+            .map(|(name, backup)| (name.clone(), backup.mount().to_string()))
+            .chain(
+                self.subvolumes
+                    .transfer
+                    .iter()
+                    .map(|(name, transfer)| (name.clone(), transfer.mount.clone())),
+            )
+            .collect();
+        mounts.sort();
+        mounts
+    }
+
+    /// Mount points that clash after `$USER` expansion: two subvolumes on the
+    /// same path, or a subvolume on, inside or around the base volume mount
+    pub fn mount_conflicts(&self) -> Vec<MountConflict> {
+        let mounts: Vec<(String, String)> = self
+            .subvolume_mounts()
+            .into_iter()
+            .map(|(name, mount)| (name, normalize_mount(&mount)))
+            .collect();
+        let base = normalize_mount(&self.mount.base);
+        let mut conflicts = Vec::new();
+
+        for (i, (subvol, mount)) in mounts.iter().enumerate() {
+            if let Some((other, _)) = mounts[..i].iter().find(|(_, other)| other == mount) {
+                conflicts.push(MountConflict {
+                    subvol: subvol.clone(),
+                    mount: mount.clone(),
+                    other: Some(other.clone()),
+                    other_mount: mount.clone(),
+                    nested: false,
+                });
+            }
+        }
+        for (subvol, mount) in &mounts {
+            let same = *mount == base;
+            if same || is_nested(mount, &base) || is_nested(&base, mount) {
+                conflicts.push(MountConflict {
+                    subvol: subvol.clone(),
+                    mount: mount.clone(),
+                    other: None,
+                    other_mount: base.clone(),
+                    nested: !same,
+                });
+            }
+        }
+        conflicts
+    }
+
+    /// Point `subvol` at a new mount path; false if it is not configured
+    pub fn set_subvolume_mount(&mut self, subvol: &str, mount: &str) -> bool {
+        if let Some(backup) = self.subvolumes.backup.get_mut(subvol) {
+            match backup {
+                BackupSubvol::Simple(m) => *m = mount.to_string(),
+                BackupSubvol::Full { mount: m, .. } => *m = mount.to_string(),
+            }
+            return true;
+        }
+        if let Some(transfer) = self.subvolumes.transfer.get_mut(subvol) {
+            transfer.mount = mount.to_string();
+            return true;
+        }
+        false
+    }
+
+    /// Drop a backup or transfer subvolume from the config
+    pub fn remove_subvolume(&mut self, subvol: &str) {
+        self.subvolumes.backup.remove(subvol);
+        self.subvolumes.transfer.remove(subvol);
+    }
+}
+
+/// Two mount points that cannot both be generated as units
+#[derive(Debug, PartialEq)]
+pub struct MountConflict {
+    /// Subvolume to move or drop to resolve the conflict
+    pub subvol: String,
+    pub mount: String,
+    /// Subvolume it clashes with; None for the base volume mount
+    pub other: Option<String>,
+    pub other_mount: String,
+    /// One path is inside the other rather than equal to it
+    pub nested: bool,
+}
+
+impl MountConflict {
+    pub fn describe(&self) -> String {
+        let other = match &self.other {
+            Some(other) => format!("{} ({})", other, self.other_mount),
+            None => format!("the base volume mount ({})", self.other_mount),
+        };
+        if self.nested {
+            format!("{} ({}) nests with {}", self.subvol, self.mount, other)
+        } else {
+            format!("{} and {} both mount at {}", self.subvol, other, self.mount)
+        }
+    }
+}
+
+fn normalize_mount(mount: &str) -> String {
+    match mount.trim_end_matches('/') {
+        "" => "/".to_string(),
+        trimmed => trimmed.to_string(),
+    }
+}
+
+/// Why `name` is not a safe subvolume name, if it isn't
+pub fn subvolume_name_problem(name: &str) -> Option<String> {
+    // '-' and '.' are allowed for user names like john-doe or j.doe, but not
+    // first, so a name never reads as an option or a hidden/relative entry
+    let valid = name.strip_prefix('@').is_some_and(|rest| {
+        rest.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+            && rest
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+    });
+    (!valid).then(|| {
+        format!(
+            "subvolume name '{}' must be '@' followed by a letter, digit or '_', then letters, digits, '_', '-' or '.'",
+            name
+        )
+    })
+}
+
+/// Why `path` is not a safe mount point, if it isn't; one trailing '/' is
+/// tolerated like everywhere else mount points are compared
+pub fn mount_point_problem(path: &str) -> Option<String> {
+    let valid = match path.strip_prefix('/') {
+        Some("") => true,
+        Some(rest) => rest
+            .strip_suffix('/')
+            .unwrap_or(rest)
+            .split('/')
+            .all(is_safe_path_component),
+        None => false,
+    };
+    (!valid).then(|| {
+        format!(
+            "mount point '{}' must be an absolute path without '.' or '..' components, \
+             made of letters, digits, '.', '_' and '-'",
+            path
+        )
+    })
+}
+
+/// Why an exclude path (relative to the parent subvolume) is unsafe, if it is
+fn exclude_path_problem(path: &str) -> Option<String> {
+    let valid = path.split('/').all(is_safe_path_component);
+    (!valid).then(|| {
+        format!(
+            "exclude path '{}' must be a relative path without '.' or '..' components, \
+             made of letters, digits, '.', '_' and '-'",
+            path
+        )
+    })
+}
+
+fn is_safe_path_component(part: &str) -> bool {
+    !part.is_empty()
+        && part != "."
+        && part != ".."
+        && part
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+/// Whether `path` lies strictly below `parent`
+pub fn is_nested(path: &str, parent: &str) -> bool {
+    parent != "/" && path.starts_with(&format!("{}/", parent))
+}
+
+/// Home subvolume name for an entry of `user.extra`
+pub fn extra_home_subvolume(user: &str) -> String {
+    format!("@home_{}", user)
+}
+
+/// A config field that was added, removed or changed, with TOML-formatted values
+#[derive(Debug, PartialEq)]
+pub struct FieldChange {
+    pub key: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// Every leaf value of the config keyed by its dotted TOML path
+fn flatten_fields(config: &Config) -> Result<BTreeMap<String, String>> {
+    let value = toml::Value::try_from(config).context("Failed to serialize config")?;
+    let mut fields = BTreeMap::new();
+    flatten_value("", &value, &mut fields);
+    Ok(fields)
+}
+
+fn flatten_value(prefix: &str, value: &toml::Value, fields: &mut BTreeMap<String, String>) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten_value(&key, value, fields);
+            }
+        }
+        other => {
+            fields.insert(prefix.to_string(), other.to_string());
+        }
+    }
 }
 
 impl Default for Config {
@@ -233,6 +967,7 @@ impl Default for Config {
                 mount: "/var/lib/containers".to_string(),
                 nodatacow: true,
                 options: None,
+                options_append: None,
             },
         );
         transfer.insert(
@@ -241,6 +976,7 @@ impl Default for Config {
                 mount: "/var/cache".to_string(),
                 nodatacow: true,
                 options: None,
+                options_append: None,
             },
         );
         transfer.insert(
@@ -249,6 +985,7 @@ impl Default for Config {
                 mount: "/var/log".to_string(),
                 nodatacow: false,
                 options: None,
+                options_append: None,
             },
         );
         transfer.insert(
@@ -257,6 +994,7 @@ impl Default for Config {
                 mount: "/var/tmp".to_string(),
                 nodatacow: true,
                 options: None,
+                options_append: None,
             },
         );
 
@@ -265,14 +1003,22 @@ impl Default for Config {
                 // Must be provided by user
                 path: String::new(),
                 label: "ArchBtrfs".to_string(),
+                detect: DeviceDetect::default(),
+                size: None,
             },
             user: UserConfig {
                 name: String::new(),
                 options: default_useradd_options(),
+                extra: Vec::new(),
+                shell: None,
             },
             mount: MountConfig {
                 base: "/mnt/btrfs".to_string(),
-                options: default_base_options(),
+                options: None,
+                compression: None,
+                requires_mounts_for: false,
+                quotas: false,
+                base_readonly: false,
             },
             subvolumes: SubvolumesConfig {
                 backup,
@@ -293,8 +1039,17 @@ impl Default for Config {
                 preserve_min: "2d".to_string(),
                 preserve: "14d 4w 2m".to_string(),
                 timer_schedule: "*-*-* 03:00:00".to_string(),
+                targets: Vec::new(),
+                include: Vec::new(),
+                exclude: Vec::new(),
+                allow_split_pacman_db: false,
+                snapshot_dir_options: SnapshotDirOptions::default(),
+                binary: None,
+                config_path: None,
             },
             ext4_sync: Ext4SyncConfig::default(),
+            wsl: WslConfig::default(),
+            maintenance: MaintenanceConfig::default(),
             uuid: None,
         }
     }
@@ -306,6 +1061,124 @@ mod tests {
     use std::io::Write;
     use tempfile::NamedTempFile;
 
+    #[test]
+    fn load_from_reader_expands_variables() {
+        let toml = r#"
+[vhdx]
+path = 'C:\disk.vhdx'
+label = "ArchBtrfs"
+
+[user]
+name = "alice"
+
+[mount]
+base = "/mnt/btrfs"
+
+[subvolumes.backup]
+"@home" = "/home/$USER"
+
+[subvolumes.exclude]
+parent = "@home"
+paths = []
+
+[subvolumes.transfer]
+
+[btrbk]
+snapshot_dir = ".snapshots"
+preserve_min = "2d"
+preserve = "14d"
+timer_schedule = "daily"
+"#;
+        let cfg = Config::load_from_reader(toml.as_bytes(), "stdin").unwrap();
+        assert_eq!(cfg.subvolumes.backup["@home"].mount(), "/home/alice");
+
+        let err = Config::load_from_reader("[vhdx".as_bytes(), "stdin").unwrap_err();
+        assert!(err.to_string().contains("stdin"));
+    }
+
+    #[test]
+    fn parse_size_accepts_binary_suffixes() {
+        assert_eq!(parse_size("512"), Some(512));
+        assert_eq!(parse_size("256G"), Some(256 << 30));
+        assert_eq!(parse_size("512MiB"), Some(512 << 20));
+        assert_eq!(parse_size("1TB"), Some(1 << 40));
+        assert_eq!(parse_size("G"), None);
+        assert_eq!(parse_size("12X"), None);
+    }
+
+    #[test]
+    fn vhdx_detect_defaults_to_lsblk_diff() {
+        let cfg: VhdxConfig = toml::from_str(
+            "path = 'C:\\disk.vhdx'\nlabel = 'L'\ndetect = 'by-size'\nsize = '256G'",
+        )
+        .unwrap();
+        assert_eq!(cfg.detect, DeviceDetect::BySize);
+
+        let cfg: VhdxConfig = toml::from_str("path = 'C:\\disk.vhdx'\nlabel = 'L'").unwrap();
+        assert_eq!(cfg.detect, DeviceDetect::LsblkDiff);
+        assert!(!toml::to_string(&cfg).unwrap().contains("detect"));
+    }
+
+    #[test]
+    fn effective_options_compose_compression_unless_overridden() {
+        let mut mount = Config::default().mount;
+        mount.compression = Some("zstd:1".to_string());
+        assert_eq!(mount.effective_options(), "compress=zstd:1,noatime,nofail");
+
+        mount.compression = Some("none".to_string());
+        assert_eq!(mount.effective_options(), "noatime,nofail");
+
+        mount.options = Some("compress=lzo,relatime".to_string());
+        assert_eq!(mount.effective_options(), "compress=lzo,relatime");
+    }
+
+    #[test]
+    fn load_treats_legacy_default_options_as_unset() {
+        // Written by versions that always stored the default options
+        let toml = r#"
+[vhdx]
+path = 'C:\disk.vhdx'
+label = "ArchBtrfs"
+
+[user]
+name = "alice"
+
+[mount]
+base = "/mnt/btrfs"
+options = "compress=zstd:3,noatime,nofail"
+compression = "zstd:1"
+
+[subvolumes.backup]
+"@home" = "/home/$USER"
+
+[subvolumes.exclude]
+parent = "@home"
+paths = []
+
+[subvolumes.transfer]
+
+[btrbk]
+snapshot_dir = ".snapshots"
+preserve_min = "2d"
+preserve = "14d"
+timer_schedule = "daily"
+"#;
+        let cfg = Config::load_from_reader(toml.as_bytes(), "baseline.toml").unwrap();
+        assert!(cfg.mount.options.is_none());
+        assert_eq!(
+            cfg.mount.effective_options(),
+            "compress=zstd:1,noatime,nofail"
+        );
+        assert!(cfg.warnings().is_empty());
+
+        let custom = toml.replace("compress=zstd:3,noatime", "compress=lzo,noatime");
+        let cfg = Config::load_from_reader(custom.as_bytes(), "custom.toml").unwrap();
+        assert_eq!(cfg.mount.effective_options(), "compress=lzo,noatime,nofail");
+        let warnings = cfg.warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("mount.compression = \"zstd:1\" is ignored"));
+    }
+
     #[test]
     fn test_default_config() {
         let cfg = Config::default();
@@ -313,7 +1186,10 @@ mod tests {
         assert!(cfg.vhdx.path.is_empty());
         assert_eq!(cfg.vhdx.label, "ArchBtrfs");
         assert_eq!(cfg.mount.base, "/mnt/btrfs");
-        assert!(cfg.mount.options.contains("compress=zstd:3"));
+        assert_eq!(
+            cfg.mount.effective_options(),
+            "compress=zstd:3,noatime,nofail"
+        );
         assert!(cfg.uuid.is_none());
     }
 
@@ -329,6 +1205,7 @@ mod tests {
         let subvol = BackupSubvol::Full {
             mount: "/data".to_string(),
             options: Some("noatime".to_string()),
+            options_append: None,
         };
         assert_eq!(subvol.mount(), "/data");
         assert_eq!(subvol.options(), Some("noatime"));
@@ -409,6 +1286,335 @@ timer_schedule = "*-*-* 02:00:00"
         assert_eq!(loaded.uuid, cfg.uuid);
     }
 
+    #[test]
+    fn test_warnings_flag_mount_option_typos() {
+        let mut cfg = Config::default();
+        assert!(cfg.warnings().is_empty());
+
+        cfg.mount.options = Some("compres=zstd:3,noatime".to_string());
+        cfg.subvolumes.backup.insert(
+            "@data".to_string(),
+            BackupSubvol::Full {
+                mount: "/data".to_string(),
+                options: Some("noatme".to_string()),
+                options_append: None,
+            },
+        );
+
+        let warnings = cfg.warnings();
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].starts_with("mount.options: Unknown mount option 'compres'"));
+        assert!(warnings[1].starts_with("@data options: Unknown mount option 'noatme'"));
+    }
+
+    #[test]
+    fn test_warnings_flag_divergent_exclude_parent() {
+        let mut cfg = Config::default();
+        cfg.set_user("alice");
+        assert!(cfg.warnings().is_empty());
+
+        cfg.subvolumes.backup.insert(
+            "@home".to_string(),
+            BackupSubvol::Simple("/data/alice".to_string()),
+        );
+        let warnings = cfg.warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("mounted at /data/alice, not /home/alice"));
+        assert!(warnings[0].contains("/data/alice/.cache instead of /home/alice/.cache"));
+
+        cfg.subvolumes.exclude.parent = "@homes".to_string();
+        let warnings = cfg.warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("'@homes' is not a backup subvolume"));
+    }
+
+    #[test]
+    fn test_warnings_flag_snapshot_dir_inside_backup_subvolume() {
+        let mut cfg = Config::default();
+        cfg.btrbk.snapshot_dir = "@home/.snapshots".to_string();
+
+        let warnings = cfg.warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("inside snapshotted subvolume '@home'"));
+
+        cfg.btrbk.snapshot_dir = "@etc/snaps".to_string();
+        assert!(cfg.warnings()[0].contains("'@etc'"));
+
+        cfg.btrbk.snapshot_dir = "@var_cache/.snapshots".to_string();
+        assert!(cfg.warnings().is_empty());
+    }
+
+    #[test]
+    fn diff_reports_changed_added_and_removed_fields() {
+        let old = Config::default();
+        let mut new = old.clone();
+        assert!(old.diff(&new).unwrap().is_empty());
+
+        new.uuid = Some("1234".to_string());
+        new.subvolumes.backup.remove("@opt");
+        let changes = old.diff(&new).unwrap();
+        assert_eq!(
+            changes,
+            vec![
+                FieldChange {
+                    key: "subvolumes.backup.@opt".to_string(),
+                    old: Some("\"/opt\"".to_string()),
+                    new: None,
+                },
+                FieldChange {
+                    key: "uuid".to_string(),
+                    old: None,
+                    new: Some("\"1234\"".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn extra_users_with_hyphens_and_dots_pass_validation() {
+        let mut cfg = Config::default();
+        cfg.user.extra = vec!["john-doe".to_string(), "j.doe".to_string()];
+        cfg.set_user("alice");
+
+        assert_eq!(
+            cfg.subvolumes.backup["@home_john-doe"].mount(),
+            "/home/john-doe"
+        );
+        assert_eq!(cfg.subvolumes.backup["@home_j.doe"].mount(), "/home/j.doe");
+        cfg.validate().unwrap();
+    }
+
+    #[test]
+    fn extra_users_get_home_subvolumes() {
+        let mut cfg = Config::default();
+        cfg.user.extra = vec!["bob".to_string()];
+        cfg.set_user("alice");
+
+        assert_eq!(cfg.users(), vec!["alice", "bob"]);
+        assert_eq!(cfg.subvolumes.backup["@home_bob"].mount(), "/home/bob");
+        assert_eq!(
+            cfg.home_subvolumes(),
+            vec![
+                ("alice".to_string(), "@home".to_string()),
+                ("bob".to_string(), "@home_bob".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn apply_layout_replaces_only_given_classes() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(
+            file,
+            r#"
+[subvolumes.backup]
+"@home" = "/home/$USER"
+"@srv" = {{ mount = "/srv", options = "noatime" }}
+"#
+        )
+        .unwrap();
+
+        // init applies the layout before it asks for the user
+        let mut cfg = Config::default();
+        assert!(cfg.user.name.is_empty());
+        let transfer = cfg.subvolumes.transfer.len();
+        cfg.apply_layout(SubvolumeLayout::load(file.path().to_str().unwrap()).unwrap());
+
+        let mut backup: Vec<_> = cfg.subvolumes.backup.keys().cloned().collect();
+        backup.sort();
+        assert_eq!(backup, vec!["@home", "@srv"]);
+        assert_eq!(cfg.subvolumes.backup["@home"].mount(), "/home/$USER");
+        assert_eq!(cfg.subvolumes.transfer.len(), transfer);
+
+        cfg.set_user("alice");
+        assert_eq!(cfg.subvolumes.backup["@home"].mount(), "/home/alice");
+        cfg.validate().unwrap();
+    }
+
+    #[test]
+    fn layout_rejects_other_sections() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "[mount]\nbase = \"/mnt/x\"").unwrap();
+        assert!(SubvolumeLayout::load(file.path().to_str().unwrap()).is_err());
+
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "[subvolumes]").unwrap();
+        let err = SubvolumeLayout::load(file.path().to_str().unwrap()).unwrap_err();
+        assert!(err.to_string().contains("sets none of"));
+    }
+
+    #[test]
+    fn test_snapshot_dir_must_stay_on_volume() {
+        let mut cfg = Config::default();
+        assert!(cfg.snapshot_dir_location_problem().is_none());
+
+        cfg.btrbk.snapshot_dir = "snaps/btrbk".to_string();
+        assert!(cfg.snapshot_dir_location_problem().is_none());
+
+        for outside in ["/var/snapshots", "../snapshots", "snaps/../../x", ""] {
+            cfg.btrbk.snapshot_dir = outside.to_string();
+            let warnings = cfg.warnings();
+            assert_eq!(warnings.len(), 1, "{}", outside);
+            assert!(warnings[0].contains("inside the Btrfs volume"));
+        }
+    }
+
+    #[test]
+    fn mount_conflicts_find_duplicates_and_base_nesting() {
+        let mut cfg = Config::default();
+        cfg.set_user("alice");
+        assert!(cfg.mount_conflicts().is_empty());
+
+        cfg.subvolumes.transfer.insert(
+            "@cache".to_string(),
+            TransferSubvol {
+                mount: "/home/alice/".to_string(),
+                nodatacow: true,
+                options: None,
+                options_append: None,
+            },
+        );
+        cfg.subvolumes.backup.insert(
+            "@data".to_string(),
+            BackupSubvol::Simple("/mnt/btrfs/data".to_string()),
+        );
+
+        let conflicts = cfg.mount_conflicts();
+        assert_eq!(conflicts.len(), 2);
+        assert_eq!(conflicts[0].subvol, "@home");
+        assert_eq!(conflicts[0].other.as_deref(), Some("@cache"));
+        assert!(!conflicts[0].nested);
+        assert_eq!(conflicts[1].subvol, "@data");
+        assert_eq!(conflicts[1].other, None);
+        assert!(conflicts[1].nested);
+
+        assert!(cfg.set_subvolume_mount("@cache", "/home/alice/.cache"));
+        cfg.remove_subvolume("@data");
+        assert!(cfg.mount_conflicts().is_empty());
+    }
+
+    #[test]
+    fn validate_rejects_unsafe_subvolume_names_and_mounts() {
+        let mut cfg = Config::default();
+        cfg.set_user("alice");
+        cfg.validate().unwrap();
+
+        for name in [
+            "@a b",
+            "@x;rm -rf /",
+            "@$(id)",
+            "@'q'",
+            "home",
+            "@",
+            "@../etc",
+            "@.hidden",
+            "@-o",
+        ] {
+            assert!(subvolume_name_problem(name).is_some(), "{}", name);
+        }
+        for mount in [
+            "var/log",
+            "/var/../etc",
+            "/var/./log",
+            "/a//b",
+            "/a b",
+            "/x;y",
+            "/$(id)",
+        ] {
+            assert!(mount_point_problem(mount).is_some(), "{}", mount);
+        }
+        assert!(mount_point_problem("/var/lib/my-app/").is_none());
+
+        cfg.subvolumes.backup.insert(
+            "@evil`id`".to_string(),
+            BackupSubvol::Simple("/srv/x y".to_string()),
+        );
+        cfg.subvolumes.exclude.paths.push("../.ssh".to_string());
+        let err = cfg.validate().unwrap_err().to_string();
+        assert!(err.contains("@evil`id`"));
+        assert!(err.contains("/srv/x y"));
+        assert!(err.contains("../.ssh"));
+    }
+
+    #[test]
+    fn validate_rejects_malformed_preserve_policy() {
+        let mut cfg = Config::default();
+        cfg.set_user("alice");
+        cfg.btrbk.preserve = "14d 4x 2m".to_string();
+
+        let err = cfg.validate().unwrap_err().to_string();
+        assert!(err.contains("btrbk.preserve: '4x'"), "{}", err);
+
+        // Values only valid for the other setting
+        cfg.btrbk.preserve = "latest".to_string();
+        cfg.btrbk.preserve_min = "14d 4w".to_string();
+        let err = cfg.validate().unwrap_err().to_string();
+        assert!(err.contains("btrbk.preserve: 'latest'"), "{}", err);
+        assert!(err.contains("btrbk.preserve_min: '14d 4w'"), "{}", err);
+    }
+
+    #[test]
+    fn load_rejects_unsafe_names_from_expanded_user() {
+        let toml = r#"
+[vhdx]
+path = 'C:\disk.vhdx'
+label = "ArchBtrfs"
+
+[user]
+name = "alice"
+extra = ["bob;reboot"]
+
+[mount]
+base = "/mnt/btrfs"
+
+[subvolumes.backup]
+
+[subvolumes.exclude]
+parent = "@home"
+paths = []
+
+[subvolumes.transfer]
+
+[btrbk]
+snapshot_dir = ".snapshots"
+preserve_min = "2d"
+preserve = "14d"
+timer_schedule = "daily"
+"#;
+        let err = Config::load_from_reader(toml.as_bytes(), "stdin").unwrap_err();
+        assert!(format!("{:#}", err).contains("@home_bob;reboot"));
+    }
+
+    #[test]
+    fn useradd_args_take_shell_from_field_or_options() {
+        let mut user = UserConfig {
+            name: "alice".to_string(),
+            options: "-m -s /bin/zsh -G wheel".to_string(),
+            extra: Vec::new(),
+            shell: None,
+        };
+        assert_eq!(user.login_shell().as_deref(), Some("/bin/zsh"));
+        assert_eq!(
+            user.useradd_args("alice"),
+            vec!["-m", "-G", "wheel", "-s", "/bin/zsh", "alice"]
+        );
+
+        user.shell = Some("/usr/bin/fish".to_string());
+        assert_eq!(
+            user.useradd_args("bob"),
+            vec!["-m", "-G", "wheel", "-s", "/usr/bin/fish", "bob"]
+        );
+
+        user.shell = None;
+        user.options = "-M --shell=/bin/dash".to_string();
+        assert_eq!(user.login_shell().as_deref(), Some("/bin/dash"));
+        user.options = "-M -G wheel".to_string();
+        assert_eq!(
+            user.useradd_args("carol"),
+            vec!["-M", "-G", "wheel", "carol"]
+        );
+    }
+
     #[test]
     fn test_ext4_sync_default() {
         let sync = Ext4SyncConfig::default();
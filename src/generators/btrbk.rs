@@ -1,18 +1,225 @@
+use anyhow::{bail, Result};
+
 use crate::config::Config;
+use crate::generators::generated_header;
 use crate::generators::systemd::path_to_unit_name;
+use crate::utils::cli::{ensure_dependencies, find_command, Dependency};
+
+/// Default location of the generated btrbk config
+pub const CONFIG_PATH: &str = "/etc/btrbk/btrbk.conf";
+
+/// Global btrbk lock; scheduled runs and `snapshot run` hold it
+pub const LOCKFILE: &str = "/var/lock/btrbk.lock";
+
+/// Fallback when btrbk cannot be found in $PATH
+pub const DEFAULT_BINARY: &str = "/usr/bin/btrbk";
+
+/// Retention units in the order btrbk expects them in `snapshot_preserve`
+const PRESERVE_UNITS: &str = "hdwmy";
+
+/// Check a `snapshot_preserve_min` value before it reaches btrbk.conf: one of
+/// `all`, `latest`, `no`, or a single duration such as `2d`
+pub fn parse_preserve_min(spec: &str) -> Result<()> {
+    match spec.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [] => bail!("preserve_min is empty"),
+        ["all" | "latest" | "no"] => Ok(()),
+        [token] => preserve_token(token, spec, false).map(|_| ()),
+        _ => bail!(
+            "'{}' must be a single value: all, latest, no or a duration like 2d",
+            spec
+        ),
+    }
+}
+
+/// Check a `snapshot_preserve` value before it reaches btrbk.conf: `no`, or
+/// tokens such as `48h 14d 4w *m` (a count or `*`, then h, d, w, m or y, in
+/// that order and each unit at most once)
+pub fn parse_preserve(spec: &str) -> Result<()> {
+    let tokens: Vec<&str> = spec.split_whitespace().collect();
+    match tokens.as_slice() {
+        [] => bail!("preserve policy is empty"),
+        ["no"] => return Ok(()),
+        _ => {}
+    }
+
+    let mut last = None;
+    for token in tokens {
+        let unit = preserve_token(token, spec, true)?;
+        if last.is_some_and(|last| unit <= last) {
+            bail!(
+                "'{}' must list each unit at most once, in the order h d w m y",
+                spec
+            );
+        }
+        last = Some(unit);
+    }
+    Ok(())
+}
+
+/// Position in PRESERVE_UNITS of a `<count><unit>` token; the count is a
+/// number, or also `*` ("keep all") when `allow_all` is set
+fn preserve_token(token: &str, spec: &str, allow_all: bool) -> Result<usize> {
+    let Some(unit) = token
+        .chars()
+        .last()
+        .and_then(|unit| PRESERVE_UNITS.find(unit))
+    else {
+        bail!(
+            "'{}' in '{}' must end in h, d, w, m or y (e.g. 14d)",
+            token,
+            spec
+        );
+    };
+    let count = &token[..token.len() - 1];
+    let numeric = !count.is_empty() && count.bytes().all(|b| b.is_ascii_digit());
+    let valid = numeric || (allow_all && count == "*");
+    if !valid {
+        let expected = if allow_all {
+            "a number or *"
+        } else {
+            "a number"
+        };
+        bail!(
+            "'{}' in '{}' needs {} before the unit",
+            token,
+            spec,
+            expected
+        );
+    }
+    Ok(unit)
+}
+
+/// btrbk.conf location: `btrbk.config_path` or CONFIG_PATH
+pub fn config_path(config: &Config) -> &str {
+    config.btrbk.config_path.as_deref().unwrap_or(CONFIG_PATH)
+}
+
+/// btrbk binary: `btrbk.binary`, else btrbk from $PATH, else DEFAULT_BINARY
+///
+/// A bare `btrbk.binary` name is resolved through $PATH as well, so
+/// btrbk.service gets an absolute ExecStart even when systemd's PATH differs.
+pub fn binary(config: &Config) -> String {
+    let (name, fallback) = match &config.btrbk.binary {
+        Some(binary) => (binary.as_str(), binary.as_str()),
+        None => ("btrbk", DEFAULT_BINARY),
+    };
+    find_command(name)
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_else(|| fallback.to_string())
+}
+
+/// Fail early when the btrbk binary wslarc would run is missing
+pub fn ensure_binary(config: &Config) -> Result<()> {
+    match &config.btrbk.binary {
+        Some(binary) if find_command(binary).is_none() => {
+            bail!("btrbk.binary '{}' not found", binary)
+        }
+        Some(_) => Ok(()),
+        None => ensure_dependencies(&[Dependency::new("btrbk", &["btrbk"])]),
+    }
+}
+
+/// Subvolumes btrbk snapshots, sorted by name
+///
+/// `present` is the top-level subvolume list from disk; include patterns are
+/// matched against it, falling back to the configured names when it is empty.
+pub fn snapshot_subvolumes(config: &Config, present: &[String]) -> Vec<String> {
+    let configured: Vec<String> = config
+        .sorted_backup()
+        .into_iter()
+        .map(|(name, _)| name.clone())
+        .chain(["@etc".to_string()])
+        .collect();
+
+    let mut selected: Vec<String> = if config.btrbk.include.is_empty() {
+        configured
+    } else {
+        let candidates = if present.is_empty() {
+            &configured
+        } else {
+            present
+        };
+        candidates
+            .iter()
+            .filter(|name| *name != &config.btrbk.snapshot_dir && !name.contains('/'))
+            .filter(|name| matches_any(&config.btrbk.include, name))
+            .cloned()
+            .collect()
+    };
+
+    selected.retain(|name| !matches_any(&config.btrbk.exclude, name));
+    selected.sort();
+    selected.dedup();
+    selected
+}
+
+/// pacman's database and the files it describes; snapshots of one without
+/// the other restore to a state where the two disagree
+const PACMAN_PAIR: [&str; 2] = ["@usr", "@var_lib_pacman"];
+
+/// Warnings for snapshot sets that split @usr from @var_lib_pacman
+pub fn coupling_warnings(config: &Config, selected: &[String]) -> Vec<String> {
+    if config.btrbk.allow_split_pacman_db {
+        return Vec::new();
+    }
+    let [usr, pacman] = PACMAN_PAIR.map(|name| selected.iter().any(|subvol| subvol == name));
+    if usr == pacman {
+        return Vec::new();
+    }
+    let (taken, missing) = if usr {
+        (PACMAN_PAIR[0], PACMAN_PAIR[1])
+    } else {
+        (PACMAN_PAIR[1], PACMAN_PAIR[0])
+    };
+    vec![format!(
+        "btrbk snapshots {} but not {}; a restore would leave the package database out \
+         of step with /usr. Snapshot both, or set btrbk.allow_split_pacman_db = true",
+        taken, missing
+    )]
+}
+
+fn matches_any(patterns: &[String], name: &str) -> bool {
+    patterns.iter().any(|pattern| glob_match(pattern, name))
+}
+
+/// Shell-style glob supporting `*` and `?`
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            n = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
 
 /// Generate btrbk.conf content
-pub fn generate_config(config: &Config) -> String {
+pub fn generate_config(config: &Config, present: &[String]) -> String {
     let mut lines = Vec::new();
 
-    lines.push("# /etc/btrbk/btrbk.conf".to_string());
-    lines.push("# Generated by wslarc".to_string());
+    lines.push(format!("# {}", config_path(config)));
+    lines.push(generated_header());
     lines.push(String::new());
 
     // Global settings
     lines.push("# Global settings".to_string());
     lines.push("transaction_log         /var/log/btrbk.log".to_string());
-    lines.push("lockfile                /var/lock/btrbk.lock".to_string());
+    lines.push(format!("lockfile                {}", LOCKFILE));
     lines.push("timestamp_format        long".to_string());
     lines.push(String::new());
 
@@ -28,23 +235,24 @@ pub fn generate_config(config: &Config) -> String {
     // Volume configuration
     lines.push(format!("volume {}", config.mount.base));
     lines.push(format!("  snapshot_dir {}", config.btrbk.snapshot_dir));
+    for target in &config.btrbk.targets {
+        lines.push(format!("  target {}", target));
+    }
     lines.push(String::new());
 
-    // A-class subvolumes (backup targets)
+    // A-class subvolumes (backup targets) plus @etc, which is snapshot-only
+    // (not mounted to /etc), narrowed by btrbk.include/exclude
     lines.push("  # A-class: Backup targets".to_string());
-    for subvol in config.subvolumes.backup.keys() {
+    for subvol in snapshot_subvolumes(config, present) {
         let name = subvol.trim_start_matches('@');
+        if subvol == "@etc" {
+            lines.push("  # @etc: snapshot-only (not mounted to /etc)".to_string());
+        }
         lines.push(format!("  subvolume {}", subvol));
         lines.push(format!("    snapshot_name {}", name));
         lines.push(String::new());
     }
 
-    // @etc: snapshot-only (not mounted, but still snapshotted)
-    lines.push("  # @etc: snapshot-only (not mounted to /etc)".to_string());
-    lines.push("  subvolume @etc".to_string());
-    lines.push("    snapshot_name etc".to_string());
-    lines.push(String::new());
-
     // Note about excluded subvolumes
     lines.push("# B-class nested subvolumes are automatically excluded".to_string());
     lines.push("# C-class transfer subvolumes are not snapshotted".to_string());
@@ -53,28 +261,62 @@ pub fn generate_config(config: &Config) -> String {
 }
 
 /// Generate btrbk.service content
-pub fn generate_service(config: &Config) -> String {
-    let base_mount_unit = format!("{}.mount", path_to_unit_name(&config.mount.base));
+///
+/// btrbk needs CAP_SYS_ADMIN for the btrfs ioctls and write access to the
+/// Btrfs mount, its log and lock, so hardening stops at read-only /usr, /etc
+/// and home directories (ssh keys stay readable for remote targets).
+pub fn generate_service(config: &Config, btrbk_binary: &str) -> String {
+    let base_mount_unit = service_base_mount_unit(config);
+    let config_path = config_path(config);
+    // A bind remount only flips this mount point, and only inside the
+    // service's private mount namespace (ProtectSystem=), so the base volume
+    // stays read-only for everyone else
+    let remount = if config.mount.base_readonly {
+        format!(
+            "ExecStartPre=/usr/bin/mount -o remount,bind,rw {}\n",
+            config.mount.base
+        )
+    } else {
+        String::new()
+    };
+
+    let header = generated_header();
 
     format!(
-        r#"[Unit]
+        r#"{header}
+[Unit]
 Description=btrbk Btrfs Snapshot Backup
 Requires={base_mount_unit}
 After={base_mount_unit}
 
 [Service]
 Type=oneshot
-ExecStart=/usr/bin/btrbk -q run
+{remount}ExecStart={btrbk_binary} -c {config_path} -q run
 Nice=19
 IOSchedulingClass=idle
+ProtectSystem=full
+ProtectHome=read-only
+PrivateTmp=yes
+NoNewPrivileges=yes
+ProtectControlGroups=yes
+ProtectKernelModules=yes
+ProtectHostname=yes
+RestrictRealtime=yes
+LockPersonality=yes
 "#
     )
 }
 
+/// Mount unit btrbk.service requires before it can snapshot
+pub fn service_base_mount_unit(config: &Config) -> String {
+    format!("{}.mount", path_to_unit_name(&config.mount.base))
+}
+
 /// Generate btrbk.timer content
 pub fn generate_timer(schedule: &str) -> String {
     format!(
-        r#"[Unit]
+        r#"{}
+[Unit]
 Description=btrbk Daily Snapshot Timer
 
 [Timer]
@@ -85,6 +327,7 @@ Persistent=true
 [Install]
 WantedBy=timers.target
 "#,
+        generated_header(),
         schedule
     )
 }
@@ -93,8 +336,9 @@ WantedBy=timers.target
 mod tests {
     use super::*;
     use crate::config::{
-        BackupSubvol, BtrbkConfig, Config, ExcludeConfig, Ext4SyncConfig, MountConfig,
-        SubvolumesConfig, UserConfig, VhdxConfig,
+        BackupSubvol, BtrbkConfig, Config, DeviceDetect, ExcludeConfig, Ext4SyncConfig,
+        MaintenanceConfig, MountConfig, SnapshotDirOptions, SubvolumesConfig, UserConfig,
+        VhdxConfig, WslConfig,
     };
     use std::collections::HashMap;
 
@@ -110,14 +354,22 @@ mod tests {
             vhdx: VhdxConfig {
                 path: r"C:\Users\test\.local\share\wsl\btrfs.vhdx".to_string(),
                 label: "TestBtrfs".to_string(),
+                detect: DeviceDetect::default(),
+                size: None,
             },
             user: UserConfig {
                 name: "testuser".to_string(),
                 options: "-M -G wheel".to_string(),
+                extra: Vec::new(),
+                shell: None,
             },
             mount: MountConfig {
                 base: "/mnt/btrfs".to_string(),
-                options: "compress=zstd:3,noatime,nofail".to_string(),
+                options: None,
+                compression: None,
+                requires_mounts_for: false,
+                quotas: false,
+                base_readonly: false,
             },
             subvolumes: SubvolumesConfig {
                 backup,
@@ -132,8 +384,17 @@ mod tests {
                 preserve_min: "2d".to_string(),
                 preserve: "14d 4w 2m".to_string(),
                 timer_schedule: "*-*-* 03:00:00".to_string(),
+                targets: Vec::new(),
+                include: Vec::new(),
+                exclude: Vec::new(),
+                allow_split_pacman_db: false,
+                snapshot_dir_options: SnapshotDirOptions::default(),
+                binary: None,
+                config_path: None,
             },
             ext4_sync: Ext4SyncConfig::default(),
+            wsl: WslConfig::default(),
+            maintenance: MaintenanceConfig::default(),
             uuid: Some("12345678-1234-1234-1234-123456789abc".to_string()),
         }
     }
@@ -141,7 +402,7 @@ mod tests {
     #[test]
     fn test_generate_config() {
         let cfg = test_config();
-        let output = generate_config(&cfg);
+        let output = generate_config(&cfg, &[]);
 
         assert!(output.contains("# /etc/btrbk/btrbk.conf"));
         assert!(output.contains("volume /mnt/btrfs"));
@@ -154,22 +415,134 @@ mod tests {
     #[test]
     fn test_generate_config_includes_backup_subvols() {
         let cfg = test_config();
-        let output = generate_config(&cfg);
+        let output = generate_config(&cfg, &[]);
 
         assert!(output.contains("subvolume @home") || output.contains("subvolume @usr"));
     }
 
+    #[test]
+    fn test_generate_config_includes_targets() {
+        let mut cfg = test_config();
+        cfg.btrbk.targets = vec!["ssh://backup/srv/btrbk".to_string()];
+        let output = generate_config(&cfg, &[]);
+
+        assert!(output.contains("  target ssh://backup/srv/btrbk"));
+    }
+
+    #[test]
+    fn test_generate_config_orders_subvolumes_by_name() {
+        let output = generate_config(&test_config(), &[]);
+
+        let home = output.find("subvolume @home").unwrap();
+        let usr = output.find("subvolume @usr").unwrap();
+        assert!(home < usr);
+        assert_eq!(output, generate_config(&test_config(), &[]));
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("@var_*", "@var_cache"));
+        assert!(glob_match("@*", "@usr"));
+        assert!(glob_match("@?sr", "@usr"));
+        assert!(glob_match("*cache*", "@var_cache_pkg"));
+        assert!(!glob_match("@var_*", "@var"));
+        assert!(!glob_match("@usr", "@usr2"));
+    }
+
+    #[test]
+    fn test_snapshot_subvolumes_defaults_to_backup_and_etc() {
+        let cfg = test_config();
+        assert_eq!(
+            snapshot_subvolumes(&cfg, &[]),
+            vec!["@etc", "@home", "@usr"]
+        );
+    }
+
+    #[test]
+    fn test_snapshot_subvolumes_expands_include_and_exclude() {
+        let mut cfg = test_config();
+        cfg.btrbk.include = vec!["@*".to_string()];
+        cfg.btrbk.exclude = vec!["@var_*".to_string()];
+        let present: Vec<String> = [
+            "@etc",
+            "@home",
+            "@home/.cache",
+            "@usr",
+            "@var_cache",
+            "@var_log",
+            ".snapshots",
+        ]
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+
+        assert_eq!(
+            snapshot_subvolumes(&cfg, &present),
+            vec!["@etc", "@home", "@usr"]
+        );
+
+        let output = generate_config(&cfg, &present);
+        assert!(!output.contains("@var_cache"));
+        assert!(output.contains("  subvolume @home"));
+    }
+
     #[test]
     fn test_generate_service() {
         let cfg = test_config();
-        let output = generate_service(&cfg);
+        let output = generate_service(&cfg, DEFAULT_BINARY);
 
         assert!(output.contains("[Unit]"));
         assert!(output.contains("[Service]"));
-        assert!(output.contains("ExecStart=/usr/bin/btrbk -q run"));
+        assert!(output.contains("ExecStart=/usr/bin/btrbk -c /etc/btrbk/btrbk.conf -q run"));
+        assert!(output.contains("ProtectSystem=full"));
+        assert!(output.contains("ProtectHome=read-only"));
         assert!(output.contains(".mount"));
     }
 
+    #[test]
+    fn binary_resolves_configured_name_through_path() {
+        let mut cfg = test_config();
+        cfg.btrbk.binary = Some("sh".to_string());
+        assert!(binary(&cfg).starts_with('/'));
+        assert!(binary(&cfg).ends_with("/sh"));
+
+        cfg.btrbk.binary = Some("/opt/missing/btrbk".to_string());
+        assert_eq!(binary(&cfg), "/opt/missing/btrbk");
+    }
+
+    #[test]
+    fn test_generate_service_uses_given_binary() {
+        let cfg = test_config();
+        let output = generate_service(&cfg, "/usr/local/bin/btrbk");
+
+        assert!(output.contains("ExecStart=/usr/local/bin/btrbk -c /etc/btrbk/btrbk.conf -q run"));
+    }
+
+    #[test]
+    fn test_generate_service_remounts_readonly_base() {
+        let mut cfg = test_config();
+        assert!(!generate_service(&cfg, DEFAULT_BINARY).contains("remount"));
+
+        cfg.mount.base_readonly = true;
+        let output = generate_service(&cfg, DEFAULT_BINARY);
+        assert!(output.contains(
+            "ExecStartPre=/usr/bin/mount -o remount,bind,rw /mnt/btrfs\nExecStart=/usr/bin/btrbk"
+        ));
+    }
+
+    #[test]
+    fn test_custom_config_path_in_service_and_header() {
+        let mut cfg = test_config();
+        cfg.btrbk.config_path = Some("/srv/btrbk/test.conf".to_string());
+
+        let service = generate_service(&cfg, DEFAULT_BINARY);
+        assert!(service.contains("ExecStart=/usr/bin/btrbk -c /srv/btrbk/test.conf -q run"));
+        assert!(generate_config(&cfg, &[]).starts_with("# /srv/btrbk/test.conf\n"));
+
+        cfg.btrbk.binary = Some("/opt/btrbk/bin/btrbk".to_string());
+        assert_eq!(binary(&cfg), "/opt/btrbk/bin/btrbk");
+    }
+
     #[test]
     fn test_generate_timer() {
         let output = generate_timer("*-*-* 03:00:00");
@@ -181,4 +554,51 @@ mod tests {
         assert!(output.contains("Persistent=true"));
         assert!(output.contains("WantedBy=timers.target"));
     }
+
+    #[test]
+    fn test_coupling_warnings_flag_split_pacman_db() {
+        let mut cfg = test_config();
+        let both = vec!["@usr".to_string(), "@var_lib_pacman".to_string()];
+        assert!(coupling_warnings(&cfg, &both).is_empty());
+        assert!(coupling_warnings(&cfg, &["@home".to_string()]).is_empty());
+
+        let warnings = coupling_warnings(&cfg, &both[..1]);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("snapshots @usr but not @var_lib_pacman"));
+
+        cfg.btrbk.allow_split_pacman_db = true;
+        assert!(coupling_warnings(&cfg, &both[..1]).is_empty());
+    }
+
+    #[test]
+    fn parse_preserve_accepts_btrbk_retention_policies() {
+        for spec in ["14d 4w 2m", "2d", "48h *d", "1y", "no"] {
+            assert!(parse_preserve(spec).is_ok(), "{}", spec);
+        }
+        for spec in [
+            "",
+            "14d 4x 2m",
+            "14 days",
+            "d",
+            "-1d",
+            "1.5d",
+            "14d 2d",
+            "4w 14d",
+            "no 2d",
+            "latest",
+            "all",
+        ] {
+            assert!(parse_preserve(spec).is_err(), "{:?}", spec);
+        }
+    }
+
+    #[test]
+    fn parse_preserve_min_accepts_a_single_value() {
+        for spec in ["2d", "48h", "1y", "all", "latest", "no"] {
+            assert!(parse_preserve_min(spec).is_ok(), "{}", spec);
+        }
+        for spec in ["", "14d 4w", "*d", "2x", "d", "latest 2d"] {
+            assert!(parse_preserve_min(spec).is_err(), "{:?}", spec);
+        }
+    }
 }
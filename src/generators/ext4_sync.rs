@@ -1,6 +1,7 @@
 use anyhow::Result;
 
 use crate::config::Config;
+use crate::generators::generated_header;
 use crate::generators::systemd::path_to_unit_name;
 use crate::utils::cli::{find_mount_uuid, pacman_query_depends};
 
@@ -14,9 +15,11 @@ pub fn get_ext4_root_uuid() -> Option<String> {
 /// Generate systemd mount unit for ext4 root
 pub fn generate_ext4_mount(config: &Config, uuid: &str) -> String {
     let mount_point = &config.ext4_sync.mount_point;
+    let header = generated_header();
 
     format!(
-        r#"[Unit]
+        r#"{header}
+[Unit]
 Description=Mount ext4 root for sync
 
 [Mount]
@@ -32,10 +35,12 @@ WantedBy=multi-user.target
 }
 
 pub fn generate_pacman_hook(targets: &[String]) -> String {
-    let mut lines = Vec::new();
-    lines.push("[Trigger]".to_string());
-    lines.push("Operation = Upgrade".to_string());
-    lines.push("Type = Package".to_string());
+    let mut lines = vec![
+        generated_header(),
+        "[Trigger]".to_string(),
+        "Operation = Upgrade".to_string(),
+        "Type = Package".to_string(),
+    ];
     for target in targets {
         lines.push(format!("Target = {}", target));
     }
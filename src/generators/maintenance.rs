@@ -0,0 +1,128 @@
+use crate::config::Config;
+use crate::generators::btrbk::service_base_mount_unit;
+use crate::generators::generated_header;
+
+/// Periodic maintenance task run through `wslarc <task>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Task {
+    Scrub,
+    Balance,
+    Trim,
+}
+
+impl Task {
+    /// Subcommand name, also used in the unit names
+    pub fn name(self) -> &'static str {
+        match self {
+            Task::Scrub => "scrub",
+            Task::Balance => "balance",
+            Task::Trim => "trim",
+        }
+    }
+
+    pub fn service_unit(self) -> String {
+        format!("wslarc-{}.service", self.name())
+    }
+
+    pub fn timer_unit(self) -> String {
+        format!("wslarc-{}.timer", self.name())
+    }
+}
+
+/// Tasks with a schedule in `[maintenance]`, in a fixed order
+pub fn scheduled_tasks(config: &Config) -> Vec<(Task, &str)> {
+    [
+        (Task::Scrub, config.maintenance.scrub_schedule.as_deref()),
+        (
+            Task::Balance,
+            config.maintenance.balance_schedule.as_deref(),
+        ),
+        (Task::Trim, config.maintenance.trim_schedule.as_deref()),
+    ]
+    .into_iter()
+    .filter_map(|(task, schedule)| schedule.map(|schedule| (task, schedule)))
+    .collect()
+}
+
+/// Generate wslarc-<task>.service content
+pub fn generate_service(config: &Config, task: Task, wslarc_binary: &str) -> String {
+    let base_mount_unit = service_base_mount_unit(config);
+    let name = task.name();
+    let header = generated_header();
+
+    format!(
+        r#"{header}
+[Unit]
+Description=wslarc Btrfs {name}
+Requires={base_mount_unit}
+After={base_mount_unit}
+
+[Service]
+Type=oneshot
+ExecStart={wslarc_binary} {name}
+Nice=19
+IOSchedulingClass=idle
+"#
+    )
+}
+
+/// Generate wslarc-<task>.timer content
+pub fn generate_timer(task: Task, schedule: &str) -> String {
+    format!(
+        r#"{}
+[Unit]
+Description=wslarc Btrfs {} Timer
+
+[Timer]
+OnCalendar={}
+RandomizedDelaySec=1h
+Persistent=true
+
+[Install]
+WantedBy=timers.target
+"#,
+        generated_header(),
+        task.name(),
+        schedule
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scheduled_tasks_skips_unscheduled() {
+        let mut cfg = Config::default();
+        assert!(scheduled_tasks(&cfg).is_empty());
+
+        cfg.maintenance.balance_schedule = Some("weekly".to_string());
+        assert_eq!(scheduled_tasks(&cfg), vec![(Task::Balance, "weekly")]);
+
+        cfg.maintenance.trim_schedule = Some("daily".to_string());
+        assert_eq!(
+            scheduled_tasks(&cfg),
+            vec![(Task::Balance, "weekly"), (Task::Trim, "daily")]
+        );
+        assert_eq!(Task::Trim.timer_unit(), "wslarc-trim.timer");
+    }
+
+    #[test]
+    fn test_generate_service_runs_wslarc_task() {
+        let cfg = Config::default();
+        let output = generate_service(&cfg, Task::Scrub, "/usr/local/bin/wslarc");
+
+        assert!(output.contains("ExecStart=/usr/local/bin/wslarc scrub\n"));
+        assert!(output.contains("Type=oneshot"));
+        assert!(output.contains(&format!("Requires={}", service_base_mount_unit(&cfg))));
+    }
+
+    #[test]
+    fn test_generate_timer() {
+        let output = generate_timer(Task::Balance, "Sun *-*-* 04:00:00");
+
+        assert!(output.contains("OnCalendar=Sun *-*-* 04:00:00"));
+        assert!(output.contains("WantedBy=timers.target"));
+        assert_eq!(Task::Balance.timer_unit(), "wslarc-balance.timer");
+    }
+}
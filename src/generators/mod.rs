@@ -1,3 +1,39 @@
 pub mod btrbk;
 pub mod ext4_sync;
+pub mod maintenance;
 pub mod systemd;
+
+/// Start of the header line in every file wslarc writes, so managed files can
+/// be found with grep and told apart from files written by hand
+pub const GENERATED_MARKER: &str = "# Generated by wslarc";
+
+/// Header line for generated files, stamped with the wslarc version
+pub fn generated_header() -> String {
+    format!(
+        "{} v{} \u{2014} do not edit",
+        GENERATED_MARKER,
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+/// Whether a file was written by wslarc (any version)
+pub fn is_generated(content: &str) -> bool {
+    content
+        .lines()
+        .any(|line| line.trim_start().starts_with(GENERATED_MARKER))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_header_carries_version_and_is_recognized() {
+        let header = generated_header();
+        assert!(header.contains(env!("CARGO_PKG_VERSION")));
+        assert!(is_generated(&format!("{}\n[Unit]\n", header)));
+        // Units from before the version was added to the header
+        assert!(is_generated("# Generated by wslarc\n[Unit]\n"));
+        assert!(!is_generated("[Unit]\nDescription=Hand-written\n"));
+    }
+}
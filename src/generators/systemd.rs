@@ -1,6 +1,8 @@
 use std::process::Command;
 
-use crate::config::Config;
+use crate::config::{is_nested, Config};
+use crate::generators::{generated_header, maintenance};
+use crate::utils::mount_options::append_options;
 
 pub fn path_to_unit_name(path: &str) -> String {
     Command::new("systemd-escape")
@@ -9,7 +11,38 @@ pub fn path_to_unit_name(path: &str) -> String {
         .ok()
         .and_then(|o| String::from_utf8(o.stdout).ok())
         .map(|s| s.trim().to_string())
-        .unwrap_or_else(|| path.trim_start_matches('/').replace('/', "-"))
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| escape_path(path))
+}
+
+/// `systemd-escape --path` without systemd: '/' becomes '-', and anything but
+/// ASCII alphanumerics, ':', '_' and a non-leading '.' becomes `\xNN`
+fn escape_path(path: &str) -> String {
+    let trimmed: Vec<&str> = path.split('/').filter(|part| !part.is_empty()).collect();
+    if trimmed.is_empty() {
+        return "-".to_string();
+    }
+    let mut escaped = String::new();
+    for (i, byte) in trimmed.join("/").bytes().enumerate() {
+        match byte {
+            b'/' => escaped.push('-'),
+            b'.' if i > 0 => escaped.push('.'),
+            b if b.is_ascii_alphanumeric() || b == b':' || b == b'_' => escaped.push(b as char),
+            b => escaped.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    escaped
+}
+
+/// Mount options for the base volume: the effective mount options, plus ro
+/// with mount.base_readonly
+pub fn base_mount_options(config: &Config) -> String {
+    let options = config.mount.effective_options();
+    if config.mount.base_readonly {
+        format!("{},ro", options)
+    } else {
+        options
+    }
 }
 
 /// Generate base Btrfs mount unit
@@ -17,7 +50,8 @@ pub fn generate_base_mount(config: &Config) -> String {
     let uuid = config.uuid.as_deref().unwrap_or("REPLACE_WITH_UUID");
 
     format!(
-        r#"[Unit]
+        r#"{}
+[Unit]
 Description=Mount Btrfs Volume
 
 [Mount]
@@ -29,48 +63,120 @@ Options={}
 [Install]
 WantedBy=multi-user.target
 "#,
-        uuid, config.mount.base, config.mount.options
+        generated_header(),
+        uuid,
+        config.mount.base,
+        base_mount_options(config)
     )
 }
 
+/// Ordering dependencies of a subvolume mount unit
+#[derive(Debug, PartialEq)]
+pub struct MountDependencies {
+    /// Mount paths this mount needs first (RequiresMountsFor= mode)
+    pub required_paths: Vec<String>,
+    /// Units listed in both Requires= and After=
+    pub requires: Vec<String>,
+    /// Units that must start after this mount (Before=)
+    pub before: Vec<String>,
+}
+
+/// Derive Requires/After/Before for a subvolume mounted at `mount_point`
+pub fn subvol_mount_dependencies(config: &Config, mount_point: &str) -> MountDependencies {
+    // Handle dependencies for nested mounts (e.g., ~/.local/share/containers)
+    let homes: Vec<String> = config
+        .users()
+        .iter()
+        .map(|user| format!("/home/{}", user))
+        .collect();
+    let is_home_mount = homes.iter().any(|home| mount_point == home);
+    let mut required_paths = vec![config.mount.base.clone()];
+    let parent = homes
+        .iter()
+        .cloned()
+        .chain(
+            config
+                .subvolume_mounts()
+                .into_iter()
+                .map(|(_, mount)| mount.trim_end_matches('/').to_string()),
+        )
+        .filter(|parent| is_nested(mount_point, parent))
+        .max_by_key(|parent| parent.len());
+    if let Some(parent) = parent {
+        // Nested under another mount (usually a home), need that mount first
+        required_paths.push(parent);
+    }
+    let requires = required_paths
+        .iter()
+        .map(|path| mount_unit_filename(path))
+        .collect();
+
+    // Home mount should complete before user@.service starts
+    let before = if is_home_mount {
+        vec!["user@.service".to_string()]
+    } else {
+        Vec::new()
+    };
+
+    MountDependencies {
+        required_paths,
+        requires,
+        before,
+    }
+}
+
+/// Mount options for a subvolume: subvol + custom options or the base
+/// options, then `options_append` on top
+pub fn subvol_mount_options(
+    config: &Config,
+    subvol: &str,
+    custom_options: Option<&str>,
+    options_append: Option<&str>,
+) -> String {
+    let base = match custom_options {
+        Some(options) => options.to_string(),
+        None => config.mount.effective_options(),
+    };
+    match options_append {
+        Some(append) => format!("subvol={},{}", subvol, append_options(&base, append)),
+        None => format!("subvol={},{}", subvol, base),
+    }
+}
+
 /// Generate subvolume mount unit
 pub fn generate_subvol_mount(
     config: &Config,
     subvol: &str,
     mount_point: &str,
     custom_options: Option<&str>,
+    options_append: Option<&str>,
 ) -> String {
     let uuid = config.uuid.as_deref().unwrap_or("REPLACE_WITH_UUID");
-    let base_unit = path_to_unit_name(&config.mount.base);
 
-    // Build options: subvol + custom_options or default base options
-    let base_opts = custom_options.unwrap_or(&config.mount.options);
-    let opts = format!("subvol={},{}", subvol, base_opts);
+    let opts = subvol_mount_options(config, subvol, custom_options, options_append);
 
-    // Handle dependencies for nested mounts (e.g., ~/.local/share/containers)
-    let user = config.get_user();
-    let home_path = format!("/home/{}", user);
-    let is_home_mount = mount_point == home_path;
-    let requires = if mount_point.starts_with(&home_path) && !is_home_mount {
-        // Nested under home, need home mount first
-        let home_unit = path_to_unit_name(&home_path);
-        format!("{}.mount {}.mount", base_unit, home_unit)
+    let dependencies = subvol_mount_dependencies(config, mount_point);
+    // systemd resolves RequiresMountsFor= paths to whatever units mount them
+    let ordering = if config.mount.requires_mounts_for {
+        format!(
+            "RequiresMountsFor={}",
+            dependencies.required_paths.join(" ")
+        )
     } else {
-        format!("{}.mount", base_unit)
+        let requires = dependencies.requires.join(" ");
+        format!("Requires={}\nAfter={}", requires, requires)
     };
-
-    // Home mount should complete before user@.service starts
-    let before = if is_home_mount {
-        "Before=user@.service"
+    let before = if dependencies.before.is_empty() {
+        String::new()
     } else {
-        ""
+        format!("Before={}", dependencies.before.join(" "))
     };
 
     format!(
-        r#"[Unit]
+        r#"{}
+[Unit]
 Description=Mount {} subvolume
-Requires={}
-After={}
+{}
 {}
 
 [Mount]
@@ -82,7 +188,13 @@ Options={}
 [Install]
 WantedBy=multi-user.target
 "#,
-        subvol, requires, requires, before, uuid, mount_point, opts
+        generated_header(),
+        subvol,
+        ordering,
+        before,
+        uuid,
+        mount_point,
+        opts
     )
 }
 
@@ -91,12 +203,41 @@ pub fn mount_unit_filename(mount_point: &str) -> String {
     format!("{}.mount", path_to_unit_name(mount_point))
 }
 
+/// All mount units wslarc manages: base first, then backup and transfer by name
+pub fn mount_unit_names(config: &Config) -> Vec<String> {
+    let mut units = vec![mount_unit_filename(&config.mount.base)];
+
+    for (_, backup) in config.sorted_backup() {
+        units.push(mount_unit_filename(backup.mount()));
+    }
+
+    for (_, transfer) in config.sorted_transfer() {
+        units.push(mount_unit_filename(&transfer.mount));
+    }
+
+    units
+}
+
+/// Units `wslarc mount` enables, with the target whose `.wants` links them
+pub fn wanted_units(config: &Config) -> Vec<(String, &'static str)> {
+    let mut units: Vec<(String, &'static str)> = mount_unit_names(config)
+        .into_iter()
+        .map(|unit| (unit, "multi-user.target"))
+        .collect();
+    units.push(("btrbk.timer".to_string(), "timers.target"));
+    for (task, _) in maintenance::scheduled_tasks(config) {
+        units.push((task.timer_unit(), "timers.target"));
+    }
+    units
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::config::{
-        BackupSubvol, BtrbkConfig, Config, ExcludeConfig, Ext4SyncConfig, MountConfig,
-        SubvolumesConfig, TransferSubvol, UserConfig, VhdxConfig,
+        BackupSubvol, BtrbkConfig, Config, DeviceDetect, ExcludeConfig, Ext4SyncConfig,
+        MaintenanceConfig, MountConfig, SnapshotDirOptions, SubvolumesConfig, TransferSubvol,
+        UserConfig, VhdxConfig, WslConfig,
     };
     use std::collections::HashMap;
 
@@ -115,6 +256,7 @@ mod tests {
                 mount: "/var/lib/containers".to_string(),
                 nodatacow: true,
                 options: None,
+                options_append: None,
             },
         );
 
@@ -122,14 +264,22 @@ mod tests {
             vhdx: VhdxConfig {
                 path: r"C:\Users\test\.local\share\wsl\btrfs.vhdx".to_string(),
                 label: "TestBtrfs".to_string(),
+                detect: DeviceDetect::default(),
+                size: None,
             },
             user: UserConfig {
                 name: "testuser".to_string(),
                 options: "-M -G wheel".to_string(),
+                extra: Vec::new(),
+                shell: None,
             },
             mount: MountConfig {
                 base: "/mnt/btrfs".to_string(),
-                options: "compress=zstd:3,noatime,nofail".to_string(),
+                options: None,
+                compression: None,
+                requires_mounts_for: false,
+                quotas: false,
+                base_readonly: false,
             },
             subvolumes: SubvolumesConfig {
                 backup,
@@ -144,18 +294,59 @@ mod tests {
                 preserve_min: "2d".to_string(),
                 preserve: "14d 4w 2m".to_string(),
                 timer_schedule: "*-*-* 03:00:00".to_string(),
+                targets: Vec::new(),
+                include: Vec::new(),
+                exclude: Vec::new(),
+                allow_split_pacman_db: false,
+                snapshot_dir_options: SnapshotDirOptions::default(),
+                binary: None,
+                config_path: None,
             },
             ext4_sync: Ext4SyncConfig::default(),
+            wsl: WslConfig::default(),
+            maintenance: MaintenanceConfig::default(),
             uuid: Some("12345678-1234-1234-1234-123456789abc".to_string()),
         }
     }
 
+    #[test]
+    fn test_mount_unit_names_are_sorted_and_stable() {
+        let mut cfg = test_config();
+        cfg.subvolumes
+            .backup
+            .insert("@opt".to_string(), BackupSubvol::Simple("/opt".to_string()));
+
+        let units = mount_unit_names(&cfg);
+        assert_eq!(
+            units,
+            vec![
+                mount_unit_filename("/mnt/btrfs"),
+                mount_unit_filename("/home/testuser"),
+                mount_unit_filename("/opt"),
+                mount_unit_filename("/usr"),
+                mount_unit_filename("/var/lib/containers"),
+            ]
+        );
+        for _ in 0..5 {
+            assert_eq!(mount_unit_names(&cfg.clone()), units);
+        }
+    }
+
     #[test]
     fn test_path_to_unit_name_fallback() {
         let result = path_to_unit_name("/mnt/btrfs");
         assert!(result == "mnt-btrfs" || result == "mnt\\x2dbtrfs" || !result.is_empty());
     }
 
+    #[test]
+    fn escape_path_matches_systemd_escape() {
+        assert_eq!(escape_path("/"), "-");
+        assert_eq!(escape_path("/mnt/btrfs/"), "mnt-btrfs");
+        assert_eq!(escape_path("/var/lib/my-app"), "var-lib-my\\x2dapp");
+        assert_eq!(escape_path("/home/a b/.cache"), "home-a\\x20b-.cache");
+        assert_eq!(escape_path("/.hidden"), "\\x2ehidden");
+    }
+
     #[test]
     fn test_mount_unit_filename() {
         let filename = mount_unit_filename("/mnt/btrfs");
@@ -168,6 +359,7 @@ mod tests {
         let cfg = test_config();
         let output = generate_base_mount(&cfg);
 
+        assert!(output.starts_with(&generated_header()));
         assert!(output.contains("[Unit]"));
         assert!(output.contains("[Mount]"));
         assert!(output.contains("[Install]"));
@@ -186,10 +378,22 @@ mod tests {
         assert!(output.contains("REPLACE_WITH_UUID"));
     }
 
+    #[test]
+    fn test_generate_base_mount_readonly() {
+        let mut cfg = test_config();
+        cfg.mount.base_readonly = true;
+
+        let output = generate_base_mount(&cfg);
+        assert!(output.contains("Options=compress=zstd:3,noatime,nofail,ro\n"));
+        // Subvolumes stay writable
+        let subvol = generate_subvol_mount(&cfg, "@usr", "/usr", None, None);
+        assert!(!subvol.contains(",ro"));
+    }
+
     #[test]
     fn test_generate_subvol_mount() {
         let cfg = test_config();
-        let output = generate_subvol_mount(&cfg, "@usr", "/usr", None);
+        let output = generate_subvol_mount(&cfg, "@usr", "/usr", None, None);
 
         assert!(output.contains("Description=Mount @usr subvolume"));
         assert!(output.contains("Where=/usr"));
@@ -200,17 +404,81 @@ mod tests {
     #[test]
     fn test_generate_subvol_mount_custom_options() {
         let cfg = test_config();
-        let output = generate_subvol_mount(&cfg, "@data", "/data", Some("noatime,nofail"));
+        let output = generate_subvol_mount(&cfg, "@data", "/data", Some("noatime,nofail"), None);
 
         assert!(output.contains("subvol=@data,noatime,nofail"));
         assert!(!output.contains("compress=zstd:3"));
     }
 
+    #[test]
+    fn test_generate_subvol_mount_nested_dependency_modes() {
+        let mut cfg = test_config();
+        let mount_point = "/home/testuser/.local/share/containers";
+        let base_unit = mount_unit_filename("/mnt/btrfs");
+        let home_unit = mount_unit_filename("/home/testuser");
+
+        let output = generate_subvol_mount(&cfg, "@containers_user", mount_point, None, None);
+        assert!(output.contains(&format!("Requires={} {}", base_unit, home_unit)));
+        assert!(output.contains(&format!("After={} {}", base_unit, home_unit)));
+        assert!(!output.contains("RequiresMountsFor="));
+
+        cfg.mount.requires_mounts_for = true;
+        let output = generate_subvol_mount(&cfg, "@containers_user", mount_point, None, None);
+        assert!(output.contains("RequiresMountsFor=/mnt/btrfs /home/testuser\n"));
+        assert!(!output.contains("Requires=mnt"));
+        assert!(!output.contains("After="));
+    }
+
+    #[test]
+    fn test_generate_subvol_mount_appends_to_base_options() {
+        let cfg = test_config();
+        let output = generate_subvol_mount(&cfg, "@vm", "/var/lib/vm", None, Some("nodatacow"));
+        assert!(output.contains("Options=subvol=@vm,compress=zstd:3,noatime,nofail,nodatacow\n"));
+
+        let output = generate_subvol_mount(
+            &cfg,
+            "@vm",
+            "/var/lib/vm",
+            Some("compress=zstd:3,noatime"),
+            Some("compress=zstd:1"),
+        );
+        assert!(output.contains("Options=subvol=@vm,noatime,compress=zstd:1\n"));
+    }
+
     #[test]
     fn test_generate_subvol_mount_home() {
         let cfg = test_config();
-        let output = generate_subvol_mount(&cfg, "@home", "/home/testuser", None);
+        let output = generate_subvol_mount(&cfg, "@home", "/home/testuser", None, None);
 
         assert!(output.contains("Before=user@.service"));
     }
+
+    #[test]
+    fn test_subvol_mount_dependencies_extra_user_home() {
+        let mut cfg = test_config();
+        cfg.user.extra = vec!["bob".to_string()];
+
+        let home = subvol_mount_dependencies(&cfg, "/home/bob");
+        assert_eq!(home.before, vec!["user@.service"]);
+
+        let nested = subvol_mount_dependencies(&cfg, "/home/bob/.cache");
+        assert_eq!(nested.required_paths, vec!["/mnt/btrfs", "/home/bob"]);
+    }
+
+    #[test]
+    fn test_subvol_mount_dependencies_innermost_parent_mount() {
+        let cfg = test_config();
+
+        let nested = subvol_mount_dependencies(&cfg, "/usr/local");
+        assert_eq!(nested.required_paths, vec!["/mnt/btrfs", "/usr"]);
+
+        let deeper = subvol_mount_dependencies(&cfg, "/var/lib/containers/storage");
+        assert_eq!(
+            deeper.required_paths,
+            vec!["/mnt/btrfs", "/var/lib/containers"]
+        );
+
+        let top = subvol_mount_dependencies(&cfg, "/srv");
+        assert_eq!(top.required_paths, vec!["/mnt/btrfs"]);
+    }
 }
@@ -1,7 +1,12 @@
-use anyhow::Result;
-use clap::{Parser, Subcommand};
+use anyhow::{bail, Result};
+use clap::{CommandFactory, Parser, Subcommand};
 use log::debug;
 
+use commands::status::OutputFormat;
+use utils::report::with_report;
+use utils::scope::Scope;
+use utils::shell::RealRunner;
+
 mod commands;
 mod config;
 mod generators;
@@ -12,7 +17,7 @@ mod utils;
 #[command(about = "WSL2 Btrfs backup and restore tool", long_about = None)]
 #[command(version)]
 struct Cli {
-    /// Path to config file
+    /// Path to config file ('-' reads it from stdin)
     #[arg(short, long, global = true)]
     config: Option<String>,
 
@@ -20,6 +25,18 @@ struct Cli {
     #[arg(short, long, global = true)]
     yes: bool,
 
+    /// Use ~/.config for config and generated units (default when not root)
+    #[arg(long, global = true)]
+    user_scope: bool,
+
+    /// Print every external command before running it
+    #[arg(long, global = true)]
+    show_commands: bool,
+
+    /// Output format; json is supported by status and snapshot list
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
     /// Verbose output (can be repeated: -v, -vv, -vvv)
     #[arg(short, long, global = true, action = clap::ArgAction::Count)]
     verbose: u8,
@@ -31,28 +48,210 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Initialize Btrfs VHDX and create subvolumes
+    #[command(after_help = "Examples:
+  sudo wslarc init
+  sudo wslarc init --config ./config.toml --yes
+  sudo wslarc init --reuse
+  sudo wslarc init --dry-run --probe-device")]
     Init {
         /// Only show what would be done
         #[arg(long)]
         dry_run: bool,
+
+        /// With --dry-run, really attach the VHDX and inspect the device
+        /// (detection, fstype, label, UUID); still formats and creates nothing
+        #[arg(long, requires = "dry_run", conflicts_with = "reuse")]
+        probe_device: bool,
+
+        /// Adopt an existing Btrfs disk and subvolumes without formatting
+        #[arg(long)]
+        reuse: bool,
+
+        /// Only check that the disk matches the config (label, fstype, subvolumes); never format
+        #[arg(long, conflicts_with_all = ["dry_run", "reuse"])]
+        format_check_only: bool,
+
+        /// TOML file with a [subvolumes] section replacing the default layout
+        #[arg(long, value_name = "FILE")]
+        layout: Option<String>,
+
+        /// Warn instead of failing when rsync cannot copy some files into a subvolume
+        #[arg(long)]
+        continue_on_copy_error: bool,
+
+        /// Login shell for created users (saved as user.shell; must be installed)
+        #[arg(long, value_name = "PATH")]
+        user_shell: Option<String>,
+
+        /// Format over another filesystem, or use a Btrfs disk whose label differs
+        /// from vhdx.label; --yes alone refuses both
+        #[arg(long, conflicts_with_all = ["reuse", "format_check_only"])]
+        force_format: bool,
+
+        /// Seconds to wait for the attached VHDX to appear in lsblk
+        #[arg(long, value_name = "SECONDS", default_value_t = 10)]
+        attach_timeout: u64,
+
+        /// Continue an interrupted init: keep the saved answers and skip the
+        /// steps already done (disk attached and formatted, config saved, base mounted)
+        #[arg(long, conflicts_with_all = ["reuse", "format_check_only"])]
+        resume: bool,
+
+        /// Write a JSON record of the steps and commands run to this file
+        #[arg(long, value_name = "PATH")]
+        report_file: Option<String>,
     },
 
     /// Generate and install systemd mount units
+    #[command(after_help = "Examples:
+  sudo wslarc mount
+  sudo wslarc mount --dry-run
+  sudo wslarc mount --defer-enable
+  wslarc mount --output-dir ./rootfs")]
     Mount {
         /// Only generate files, don't install
         #[arg(long)]
         dry_run: bool,
+
+        /// Write units and link them into .wants directories without systemctl
+        #[arg(long)]
+        defer_enable: bool,
+
+        /// Only rewrite files whose content changed; skip daemon-reload otherwise
+        #[arg(long)]
+        only_new: bool,
+
+        /// Recovery: mount everything directly with mount(8), bypassing systemd units
+        #[arg(long, conflicts_with_all = ["defer_enable", "only_new"])]
+        emergency: bool,
+
+        /// Generate and validate units and btrbk.conf in a temp dir; install nothing
+        #[arg(long, conflicts_with_all = ["dry_run", "defer_enable", "only_new", "emergency"])]
+        check_only: bool,
+
+        /// Only regenerate btrbk.conf, btrbk.service and btrbk.timer
+        #[arg(long, conflicts_with_all = ["emergency", "check_only"])]
+        btrbk_only: bool,
+
+        /// Write all generated files below this directory, mirroring the system
+        /// layout; no systemctl, no install
+        #[arg(
+            long,
+            value_name = "DIR",
+            conflicts_with_all = ["dry_run", "defer_enable", "emergency", "check_only", "btrbk_only"]
+        )]
+        output_dir: Option<String>,
+
+        /// Regenerate, validate and re-enable only this subvolume's mount unit
+        #[arg(
+            long,
+            value_name = "SUBVOL",
+            conflicts_with_all = ["emergency", "check_only", "btrbk_only", "output_dir"]
+        )]
+        only: Option<String>,
+
+        /// Offer to disable and delete wslarc mount units no longer in the config
+        #[arg(
+            long,
+            conflicts_with_all = ["defer_enable", "emergency", "check_only", "btrbk_only", "output_dir", "only"]
+        )]
+        prune: bool,
+
+        /// Write a JSON record of the steps and commands run to this file
+        #[arg(long, value_name = "PATH")]
+        report_file: Option<String>,
+    },
+
+    /// Compare zstd compression levels on a sample of existing files
+    #[command(after_help = "Examples:
+  wslarc benchmark
+  wslarc benchmark --path /home/alice --levels 1,3,6")]
+    Benchmark {
+        /// Directory to sample files from
+        #[arg(long, default_value = "/usr")]
+        path: String,
+
+        /// Maximum sample size in MiB
+        #[arg(long, default_value_t = 64)]
+        sample_size: u64,
+
+        /// Comma-separated zstd levels to try
+        #[arg(long, value_delimiter = ',', default_value = "1,3,6,9")]
+        levels: Vec<u32>,
     },
 
     /// Disable systemd mount units
+    #[command(after_help = "Examples:
+  sudo wslarc unmount
+  sudo wslarc unmount --keep-units
+  sudo wslarc unmount --keep-timer")]
     Unmount {
         /// Only show what would be done
         #[arg(long)]
         dry_run: bool,
+
+        /// Leave the mount units enabled; only disable and stop the timers
+        #[arg(long)]
+        keep_units: bool,
+
+        /// Leave the timers enabled; only disable the mount units
+        #[arg(long, conflicts_with = "keep_units")]
+        keep_timer: bool,
     },
 
     /// Show current status (mounts, subvolumes, snapshots)
-    Status,
+    #[command(after_help = "Examples:
+  wslarc status
+  wslarc status --short
+  wslarc status --check-exit
+  wslarc status --units
+  wslarc status --watch 5
+  wslarc status --format json")]
+    Status {
+        /// Print a single-line summary (exit code reflects overall health)
+        #[arg(long)]
+        short: bool,
+
+        /// Health probe: print nothing (unless -v) and exit 0 when healthy, or
+        /// 2 not mounted, 3 failed mount units, 4 inactive mount units, 5 timer inactive
+        #[arg(long, conflicts_with = "short")]
+        check_exit: bool,
+
+        /// Only list the mount units with their enabled/active state
+        #[arg(long, alias = "summary-only", conflicts_with_all = ["short", "check_exit"])]
+        units: bool,
+
+        /// Redraw the status every SECONDS (default 2) until Ctrl-C
+        #[arg(
+            long,
+            value_name = "SECONDS",
+            num_args = 0..=1,
+            default_missing_value = "2",
+            value_parser = clap::value_parser!(u64).range(1..),
+            conflicts_with_all = ["short", "check_exit", "units"]
+        )]
+        watch: Option<u64>,
+    },
+
+    /// Show Requires/After relationships between generated units
+    #[command(after_help = "Examples:
+  wslarc graph
+  wslarc graph --dot | dot -Tsvg > wslarc.svg")]
+    Graph {
+        /// Emit Graphviz DOT instead of a text list
+        #[arg(long)]
+        dot: bool,
+    },
+
+    /// Check that generated units are still linked into their boot targets
+    #[command(after_help = "Examples:
+  wslarc verify
+  sudo wslarc verify -y")]
+    Verify {
+        /// Only report what would be re-enabled
+        #[arg(long)]
+        dry_run: bool,
+    },
 
     /// Snapshot operations
     Snapshot {
@@ -60,11 +259,114 @@ enum Commands {
         action: SnapshotAction,
     },
 
+    /// Mount all snapshots of a subvolume read-only side by side
+    #[command(after_help = "Examples:
+  sudo wslarc browse @home
+  sudo wslarc browse etc --keep
+  sudo wslarc browse --cleanup")]
+    Browse {
+        /// Backup subvolume whose snapshots to mount (e.g. @home)
+        #[arg(required_unless_present = "cleanup")]
+        subvolume: Option<String>,
+
+        /// Leave the snapshots mounted after exiting
+        #[arg(long)]
+        keep: bool,
+
+        /// Unmount and remove trees left by earlier browse runs
+        #[arg(long, conflicts_with_all = ["subvolume", "keep"])]
+        cleanup: bool,
+    },
+
+    /// Edit the config file
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// Pause, resume or inspect the btrbk snapshot timer
+    Timer {
+        #[command(subcommand)]
+        action: TimerAction,
+    },
+
     /// Restore from a snapshot
+    #[command(after_help = "Examples:
+  sudo wslarc restore
+  sudo wslarc restore --snapshot usr.20240115T0300 --recompress")]
     Restore {
         /// Snapshot name to restore from
         #[arg(short, long)]
         snapshot: Option<String>,
+
+        /// Recompress restored data with the current compression mount option
+        #[arg(long)]
+        recompress: bool,
+
+        /// Read every file in the snapshot to verify checksums before restoring
+        #[arg(long)]
+        deep_check: bool,
+
+        /// Skip checking that the remounted subvolume is mounted and readable
+        #[arg(long = "no-verify-after", action = clap::ArgAction::SetFalse)]
+        verify_after: bool,
+
+        /// Compare free space with the snapshot size before restoring
+        #[arg(long)]
+        check_space: bool,
+
+        /// Write a JSON record of the steps and commands run to this file
+        #[arg(long, value_name = "PATH")]
+        report_file: Option<String>,
+    },
+
+    /// Verify all data and metadata checksums (btrfs scrub)
+    Scrub,
+
+    /// Compact partially used chunks (btrfs balance with usage filters)
+    Balance,
+
+    /// Discard unused blocks so the VHDX can shrink (fstrim)
+    Trim,
+
+    /// Collect config, unit state and journals into a file for bug reports
+    #[command(after_help = "Examples:
+  sudo wslarc diagnostics --out wslarc-diagnostics.txt
+  wslarc diagnostics --list-failed")]
+    Diagnostics {
+        /// Bundle file to write
+        #[arg(long, default_value = "wslarc-diagnostics.txt")]
+        out: String,
+
+        /// Only print managed units that are in the failed state
+        #[arg(long)]
+        list_failed: bool,
+    },
+
+    /// Check required tools, wsl.exe, interop and systemd
+    #[command(after_help = "Examples:
+  wslarc doctor")]
+    Doctor,
+
+    /// Restore a lost or damaged config from the copy init saved in @etc
+    #[command(after_help = "Examples:
+  sudo wslarc recover-config
+  sudo wslarc recover-config --device /dev/sdd")]
+    RecoverConfig {
+        /// Btrfs device to mount when mount.base is not mounted
+        /// (default: the device labeled vhdx.label)
+        #[arg(long, value_name = "DEVICE")]
+        device: Option<String>,
+    },
+
+    /// Rename the Btrfs filesystem label and update the config
+    Relabel {
+        /// New filesystem label
+        new_label: String,
+
+        /// Only show what would be done
+        #[arg(long)]
+        dry_run: bool,
     },
 
     /// Sync systemd packages to ext4 root (called by pacman hook)
@@ -74,15 +376,131 @@ enum Commands {
     },
 
     /// Attach Btrfs VHDX if not already mounted (called by wsl.conf at boot)
-    Attach,
+    Attach {
+        /// Return right after wsl.exe --mount instead of waiting for the device
+        #[arg(long)]
+        no_wait: bool,
+
+        /// Seconds to wait for the Btrfs device to appear
+        #[arg(long, default_value_t = 30)]
+        wait_timeout: u64,
+    },
+
+    /// List all subcommands and their flags
+    #[command(name = "commands", hide = true)]
+    CommandList {
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
 }
 
 #[derive(Subcommand)]
+#[command(after_help = "Examples:
+  sudo wslarc snapshot run
+  sudo wslarc snapshot run --dry-run
+  wslarc snapshot list")]
 enum SnapshotAction {
     /// Create a new snapshot (runs btrbk)
-    Run,
-    /// List available snapshots
+    Run {
+        /// Suppress btrbk output and print only the new snapshot names
+        #[arg(short, long)]
+        quiet: bool,
+
+        /// Print btrbk's plan (btrbk dryrun) without syncing /etc or snapshotting
+        #[arg(long, conflicts_with = "quiet")]
+        dry_run: bool,
+
+        /// Send backups to the targets with up to N parallel btrbk processes
+        #[arg(long, value_name = "N", default_value_t = 1,
+              value_parser = clap::value_parser!(u32).range(1..))]
+        concurrency: u32,
+    },
+    /// List available snapshots (--format json: subvol, timestamp, name)
     List,
+    /// Find the snapshots between which a file or directory last changed
+    #[command(after_help = "Examples:
+  wslarc snapshot find /etc/pacman.conf
+  wslarc snapshot find /home/alice/.config/nvim")]
+    Find {
+        /// Live path inside a snapshotted subvolume
+        path: String,
+    },
+    /// Export a snapshot to a single file for archival
+    #[command(after_help = "Examples:
+  sudo wslarc snapshot export @home.20240115T0300 /mnt/d/archive/home.btrfs
+  sudo wslarc snapshot export @home.20240115T0300 /mnt/d/archive/home.tar --format tar --compress")]
+    Export {
+        /// Snapshot name as shown by 'snapshot list'
+        name: String,
+
+        /// Output file
+        out: String,
+
+        /// Archive format
+        #[arg(long, value_enum, default_value = "btrfs-stream")]
+        format: commands::snapshot::ExportFormat,
+
+        /// Compress the result with zstd (writes <out>.zst)
+        #[arg(long)]
+        compress: bool,
+    },
+    /// Show the paths added, changed and deleted between two snapshots
+    #[command(after_help = "Examples:
+  sudo wslarc snapshot diff home.20240114T0300 home.20240115T0300")]
+    Diff {
+        /// Snapshot name as shown by 'snapshot list'
+        a: String,
+
+        /// Another snapshot of the same subvolume
+        b: String,
+    },
+    /// Delete a single snapshot without waiting for the retention policy
+    #[command(after_help = "Examples:
+  sudo wslarc snapshot delete home.20240115T0300
+  sudo wslarc snapshot delete usr.20240115T0300 --yes")]
+    Delete {
+        /// Snapshot name as shown by 'snapshot list'
+        name: String,
+    },
+    /// Receive an exported btrfs stream back into the snapshot directory
+    Import {
+        /// File written by 'snapshot export' (.zst is decompressed)
+        file: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum TimerAction {
+    /// Stop btrbk.timer until resumed or the next boot
+    Pause,
+    /// Start btrbk.timer again
+    Resume,
+    /// Show whether the timer is running and when it fires next
+    Status,
+}
+
+#[derive(Subcommand)]
+#[command(after_help = "Examples:
+  sudo wslarc config set mount.options compress=zstd:1,noatime,nofail
+  sudo wslarc config set btrbk.targets '[\"ssh://backup/srv/btrbk\"]'
+  wslarc config print
+  wslarc config diff /mnt/d/backup/other-machine.toml")]
+enum ConfigAction {
+    /// Set a dotted key (e.g. btrbk.preserve) and save the config
+    Set {
+        /// Dotted config key, e.g. mount.options
+        key: String,
+        /// New value; arrays and tables use TOML syntax
+        value: String,
+    },
+    /// Print the effective config ($USER expanded, defaults filled in) as TOML
+    Print,
+    /// Compare the effective config with another config file, field by field
+    Diff {
+        /// Config file to compare against (`-` reads stdin)
+        other: String,
+    },
 }
 
 fn main() -> Result<()> {
@@ -102,35 +520,305 @@ fn main() -> Result<()> {
 
     debug!("Log level: {:?}", log_level);
 
-    let config_path = cli.config.as_deref().unwrap_or("/etc/wslarc/config.toml");
+    utils::shell::set_show_commands(cli.show_commands);
+
+    let scope = Scope::detect(cli.user_scope);
+    let default_config_path = scope.default_config_path(cli.user_scope);
+    let config_path = cli.config.as_deref().unwrap_or(&default_config_path);
     debug!("Loading config from: {}", config_path);
-    let cfg = config::Config::load_or_default(config_path)?;
+    let cfg = match config::Config::load_or_default(config_path) {
+        // A damaged config is what recover-config replaces
+        Err(e) if matches!(cli.command, Commands::RecoverConfig { .. }) => {
+            debug!("Ignoring unreadable config: {:#}", e);
+            config::Config::default()
+        }
+        result => result?,
+    };
+
+    let supports_json = matches!(
+        cli.command,
+        Commands::Status {
+            short: false,
+            check_exit: false,
+            units: false,
+            watch: None
+        } | Commands::Snapshot {
+            action: SnapshotAction::List
+        }
+    );
+    if cli.format == OutputFormat::Json && !supports_json {
+        bail!(
+            "--format json is only supported by plain 'wslarc status' and 'wslarc snapshot list'"
+        );
+    }
 
     match cli.command {
-        Commands::Init { dry_run } => {
-            commands::init::run(&cfg, cli.yes, dry_run)?;
+        Commands::Init {
+            dry_run,
+            probe_device,
+            reuse,
+            format_check_only,
+            layout,
+            continue_on_copy_error,
+            user_shell,
+            force_format,
+            attach_timeout,
+            resume,
+            report_file,
+        } => {
+            if !dry_run || probe_device {
+                scope.require_root("init")?;
+            }
+            let options = commands::init::InitOptions {
+                dry_run,
+                probe_device,
+                reuse,
+                format_check_only,
+                layout,
+                continue_on_copy_error,
+                user_shell,
+                force_format,
+                attach_timeout: std::time::Duration::from_secs(attach_timeout),
+                resume,
+            };
+            with_report("init", report_file.as_deref(), || {
+                commands::init::run(&cfg, cli.yes, &options, &RealRunner)
+            })?;
+        }
+        Commands::Mount {
+            dry_run,
+            defer_enable,
+            only_new,
+            emergency,
+            check_only,
+            btrbk_only,
+            output_dir,
+            only,
+            prune,
+            report_file,
+        } => {
+            if emergency {
+                if !dry_run {
+                    scope.require_root("mount --emergency")?;
+                }
+                with_report("mount", report_file.as_deref(), || {
+                    commands::mount::run_emergency(&cfg, dry_run, &RealRunner)
+                })?;
+            } else {
+                let mut cfg = cfg.clone();
+                if commands::config::resolve_mount_conflicts(&mut cfg, cli.yes)?
+                    && !dry_run
+                    && !check_only
+                    && output_dir.is_none()
+                {
+                    if config_path == config::STDIN_PATH {
+                        // Nowhere to save to; show the resolved config so it can be kept
+                        println!(
+                            "Config was read from stdin, so the resolved config is not saved:"
+                        );
+                        commands::config::print(&cfg, config_path)?;
+                    } else {
+                        cfg.save(config_path)?;
+                        println!("Saved to {}", config_path);
+                    }
+                }
+                let options = commands::mount::MountOptions {
+                    dry_run,
+                    defer_enable,
+                    scope,
+                    only_new,
+                    check_only,
+                    btrbk_only,
+                    output_dir,
+                    only,
+                    prune,
+                };
+                with_report("mount", report_file.as_deref(), || {
+                    commands::mount::run(&cfg, cli.yes, &options, &RealRunner)
+                })?;
+            }
+        }
+        Commands::Benchmark {
+            path,
+            sample_size,
+            levels,
+        } => {
+            commands::benchmark::run(&path, sample_size, &levels)?;
         }
-        Commands::Mount { dry_run } => {
-            commands::mount::run(&cfg, cli.yes, dry_run)?;
+        Commands::Unmount {
+            dry_run,
+            keep_units,
+            keep_timer,
+        } => {
+            if !dry_run {
+                scope.require_root("unmount")?;
+            }
+            let options = commands::unmount::UnmountOptions {
+                dry_run,
+                keep_units,
+                keep_timer,
+            };
+            commands::unmount::run(&cfg, cli.yes, &options)?;
         }
-        Commands::Unmount { dry_run } => {
-            commands::unmount::run(&cfg, cli.yes, dry_run)?;
+        Commands::Status {
+            short,
+            check_exit,
+            units,
+            watch,
+        } => {
+            if check_exit {
+                let code = commands::status::run_check_exit(&cfg, cli.verbose > 0)?;
+                if code != 0 {
+                    std::process::exit(code);
+                }
+            } else if units {
+                commands::status::run_units(&cfg)?;
+            } else if let Some(seconds) = watch {
+                commands::status::watch(&cfg, std::time::Duration::from_secs(seconds))?;
+            } else if short {
+                if !commands::status::run_short(&cfg)? {
+                    std::process::exit(1);
+                }
+            } else {
+                commands::status::run(&cfg, cli.format)?;
+            }
         }
-        Commands::Status => {
-            commands::status::run(&cfg)?;
+        Commands::Graph { dot } => {
+            commands::graph::run(&cfg, dot)?;
+        }
+        Commands::Verify { dry_run } => {
+            commands::verify::run(&cfg, cli.yes, dry_run)?;
         }
         Commands::Snapshot { action } => match action {
-            SnapshotAction::Run => commands::snapshot::run(&cfg)?,
-            SnapshotAction::List => commands::snapshot::list(&cfg)?,
+            SnapshotAction::Run {
+                quiet,
+                dry_run,
+                concurrency,
+            } => {
+                scope.require_root("snapshot run")?;
+                commands::snapshot::run(&cfg, cli.yes, quiet, concurrency as usize, dry_run)?
+            }
+            SnapshotAction::List => commands::snapshot::list(&cfg, cli.format)?,
+            SnapshotAction::Find { path } => commands::snapshot::find(&cfg, &path)?,
+            SnapshotAction::Export {
+                name,
+                out,
+                format,
+                compress,
+            } => {
+                scope.require_root("snapshot export")?;
+                commands::snapshot::export(&cfg, &name, &out, format, compress)?
+            }
+            SnapshotAction::Diff { a, b } => commands::snapshot::diff(&cfg, &a, &b)?,
+            SnapshotAction::Delete { name } => {
+                scope.require_root("snapshot delete")?;
+                commands::snapshot::delete(&cfg, &name, cli.yes)?
+            }
+            SnapshotAction::Import { file } => {
+                scope.require_root("snapshot import")?;
+                commands::snapshot::import(&cfg, &file)?
+            }
+        },
+        Commands::Browse {
+            subvolume,
+            keep,
+            cleanup,
+        } => {
+            scope.require_root("browse")?;
+            if cleanup {
+                commands::browse::cleanup()?;
+            } else if let Some(subvolume) = subvolume {
+                commands::browse::run(&cfg, &subvolume, keep)?;
+            }
+        }
+        Commands::Config { action } => match action {
+            ConfigAction::Set { key, value } => {
+                commands::config::set(&cfg, config_path, &key, &value)?
+            }
+            ConfigAction::Print => commands::config::print(&cfg, config_path)?,
+            ConfigAction::Diff { other } => commands::config::diff(&cfg, config_path, &other)?,
+        },
+        Commands::Timer { action } => match action {
+            TimerAction::Pause => {
+                scope.require_root("timer pause")?;
+                commands::timer::pause()?
+            }
+            TimerAction::Resume => {
+                scope.require_root("timer resume")?;
+                commands::timer::resume()?
+            }
+            TimerAction::Status => commands::timer::status()?,
         },
-        Commands::Restore { snapshot } => {
-            commands::restore::run(&cfg, snapshot, cli.yes)?;
+        Commands::Restore {
+            snapshot,
+            recompress,
+            deep_check,
+            verify_after,
+            check_space,
+            report_file,
+        } => {
+            scope.require_root("restore")?;
+            let options = commands::restore::RestoreOptions {
+                snapshot,
+                recompress,
+                deep_check,
+                verify_after,
+                check_space,
+            };
+            with_report("restore", report_file.as_deref(), || {
+                commands::restore::run(&cfg, cli.yes, &options, &RealRunner)
+            })?;
+        }
+        Commands::RecoverConfig { device } => {
+            scope.require_root("recover-config")?;
+            commands::recover_config::run(
+                &cfg,
+                config_path,
+                device.as_deref(),
+                cli.yes,
+                &RealRunner,
+            )?;
+        }
+        Commands::Relabel { new_label, dry_run } => {
+            if !dry_run {
+                scope.require_root("relabel")?;
+            }
+            commands::relabel::run(&cfg, config_path, &new_label, cli.yes, dry_run)?;
+        }
+        Commands::Scrub => {
+            scope.require_root("scrub")?;
+            commands::maintenance::scrub(&cfg)?;
+        }
+        Commands::Balance => {
+            scope.require_root("balance")?;
+            commands::maintenance::balance(&cfg)?;
+        }
+        Commands::Trim => {
+            scope.require_root("trim")?;
+            commands::maintenance::trim(&cfg)?;
+        }
+        Commands::Diagnostics { out, list_failed } => {
+            if list_failed {
+                commands::diagnostics::list_failed(&cfg)?;
+            } else {
+                commands::diagnostics::run(&cfg, &out)?;
+            }
+        }
+        Commands::Doctor => {
+            commands::doctor::run(&cfg)?;
         }
         Commands::HookSyncSystemd { dry_run } => {
             commands::hook_sync_systemd::run(&cfg, dry_run)?;
         }
-        Commands::Attach => {
-            commands::attach::run(&cfg)?;
+        Commands::Attach {
+            no_wait,
+            wait_timeout,
+        } => {
+            let wait = (!no_wait).then(|| std::time::Duration::from_secs(wait_timeout));
+            commands::attach::run(&cfg, wait)?;
+        }
+        Commands::CommandList { json } => {
+            commands::command_list::run(&Cli::command(), json)?;
         }
     }
 
@@ -0,0 +1,150 @@
+//! Btrfs subvolume helpers built on the btrfs CLI wrappers in `cli`
+
+use anyhow::{Context, Result};
+use log::debug;
+
+use crate::config::Config;
+use crate::utils::cli::{is_read_only, list_subvolumes, show_subvolume};
+use crate::utils::prompt::warn;
+use crate::utils::shell::{run as shell_run, CommandRunner};
+
+/// Keeps a read-only base volume (mount.base_readonly) remounted read-write;
+/// dropping it remounts read-only, whether the operation succeeded or not.
+/// Silent unless remounting fails, so `snapshot run --quiet` output is unchanged
+///
+/// Uses `remount,bind` so only the base mount point flips: a plain remount
+/// changes the superblock and with it every other subvolume mount.
+pub struct WritableBase<'a> {
+    base: Option<String>,
+    runner: &'a dyn CommandRunner,
+}
+
+impl<'a> WritableBase<'a> {
+    pub fn acquire(config: &Config, runner: &'a dyn CommandRunner) -> Result<Self> {
+        let base = &config.mount.base;
+        if !config.mount.base_readonly || !is_read_only(base) {
+            return Ok(Self { base: None, runner });
+        }
+        Self::remount(base, runner)
+    }
+
+    fn remount(base: &str, runner: &'a dyn CommandRunner) -> Result<Self> {
+        runner
+            .run("mount", &["-o", "remount,bind,rw", base])
+            .with_context(|| format!("Failed to remount {} read-write", base))?;
+        debug!("Remounted {} read-write", base);
+        Ok(Self {
+            base: Some(base.to_string()),
+            runner,
+        })
+    }
+}
+
+impl Drop for WritableBase<'_> {
+    fn drop(&mut self) {
+        if let Some(base) = &self.base {
+            match self.runner.run("mount", &["-o", "remount,bind,ro", base]) {
+                Ok(_) => debug!("Remounted {} read-only", base),
+                Err(e) => warn(&format!("Failed to remount {} read-only: {}", base, e)),
+            }
+        }
+    }
+}
+
+/// Delete a subvolume together with every subvolume nested below it
+///
+/// `btrfs subvolume delete` refuses a parent that still contains child
+/// subvolumes (e.g. `@home` with its excluded `.cache`), so the children are
+/// removed first, deepest first.
+pub fn delete_subvolume_recursive(path: &str) -> Result<()> {
+    let path = path.trim_end_matches('/');
+    let info = show_subvolume(path).with_context(|| format!("{} is not a subvolume", path))?;
+    let listed = list_subvolumes(path)?;
+
+    for child in nested_deletion_order(path, &info.path, &listed) {
+        shell_run("btrfs", &["subvolume", "delete", &child])?;
+    }
+    shell_run("btrfs", &["subvolume", "delete", path])?;
+    Ok(())
+}
+
+/// Absolute paths of subvolumes below `relative` (its path from the filesystem
+/// root), deepest first so every child goes before its parent
+fn nested_deletion_order(path: &str, relative: &str, listed: &[String]) -> Vec<String> {
+    let prefix = format!("{}/", relative.trim_matches('/'));
+    let mut children: Vec<&str> = listed
+        .iter()
+        .filter_map(|subvol| subvol.strip_prefix(&prefix))
+        .collect();
+    children.sort_by(|a, b| {
+        b.matches('/')
+            .count()
+            .cmp(&a.matches('/').count())
+            .then_with(|| a.cmp(b))
+    });
+    children
+        .into_iter()
+        .map(|child| format!("{}/{}", path, child))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::shell::MockRunner;
+
+    #[test]
+    fn writable_base_bind_remounts_only_the_base_mount() {
+        let runner = MockRunner::default();
+        {
+            let _writable = WritableBase::remount("/mnt/btrfs", &runner).unwrap();
+            assert_eq!(runner.calls(), ["mount -o remount,bind,rw /mnt/btrfs"]);
+        }
+        assert_eq!(
+            runner.calls(),
+            [
+                "mount -o remount,bind,rw /mnt/btrfs",
+                "mount -o remount,bind,ro /mnt/btrfs",
+            ]
+        );
+    }
+
+    #[test]
+    fn writable_base_leaves_a_writable_base_alone() {
+        let runner = MockRunner::default();
+        let config = Config::default();
+        assert!(!config.mount.base_readonly);
+        drop(WritableBase::acquire(&config, &runner).unwrap());
+        assert!(runner.calls().is_empty());
+    }
+
+    #[test]
+    fn nested_deletion_order_removes_children_bottom_up() {
+        // @home.restore-backup with excluded caches, one of them nested again
+        let listed: Vec<String> = [
+            "@usr",
+            "@home.restore-backup",
+            "@home.restore-backup/.cache",
+            "@home.restore-backup/.local/share/containers",
+            "@home.restore-backup/.cache/mozilla",
+            "@home.restore-backup-other",
+        ]
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+
+        assert_eq!(
+            nested_deletion_order(
+                "/mnt/btrfs/@home.restore-backup",
+                "@home.restore-backup",
+                &listed
+            ),
+            vec![
+                "/mnt/btrfs/@home.restore-backup/.local/share/containers",
+                "/mnt/btrfs/@home.restore-backup/.cache/mozilla",
+                "/mnt/btrfs/@home.restore-backup/.cache",
+            ]
+        );
+        assert!(nested_deletion_order("/mnt/btrfs/@usr", "@usr", &listed).is_empty());
+    }
+}
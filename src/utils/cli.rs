@@ -1,11 +1,14 @@
 use anyhow::{bail, Context, Result};
-use serde::Deserialize;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
 use std::fs;
-use std::path::Path;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
-use crate::utils::shell::run as shell_run;
+use crate::utils::shell::{echo_command, run as shell_run};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Dependency {
@@ -24,9 +27,13 @@ pub struct BlockDevice {
     pub name: String,
     pub label: Option<String>,
     pub fstype: Option<String>,
+    /// Size in bytes
+    pub size: Option<u64>,
+    /// Filesystem UUID
+    pub uuid: Option<String>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct MountInfo {
     pub target: String,
     pub source: String,
@@ -35,6 +42,23 @@ pub struct MountInfo {
     pub uuid: Option<String>,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubvolumeInfo {
+    /// Path relative to the filesystem root (first line of `btrfs subvolume show`)
+    pub path: String,
+    pub uuid: Option<String>,
+    pub readonly: bool,
+    /// Filesystem generation when the subvolume was created ("Gen at creation")
+    pub gen_at_creation: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SshTarget {
+    pub user: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+}
+
 pub fn ensure_dependencies(dependencies: &[Dependency]) -> Result<()> {
     let mut missing = Vec::new();
 
@@ -74,23 +98,45 @@ pub fn ensure_dependencies(dependencies: &[Dependency]) -> Result<()> {
 }
 
 pub fn command_exists(command: &str) -> bool {
+    find_command(command).is_some()
+}
+
+/// Resolve a command to the executable path that would run, searching $PATH
+pub fn find_command(command: &str) -> Option<PathBuf> {
     let path = Path::new(command);
     if path.is_absolute() {
-        return path.is_file();
+        return path.is_file().then(|| path.to_path_buf());
     }
 
-    env::var_os("PATH")
-        .map(|paths| env::split_paths(&paths).any(|dir| dir.join(command).is_file()))
+    env::var_os("PATH").and_then(|paths| {
+        env::split_paths(&paths)
+            .map(|dir| dir.join(command))
+            .find(|candidate| candidate.is_file())
+    })
+}
+
+/// Whether `path` is a regular file with an execute bit set
+pub fn is_executable(path: &str) -> bool {
+    fs::metadata(path)
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
         .unwrap_or(false)
 }
 
 pub fn find_btrfs_device_by_label(label: &str) -> Result<Option<String>> {
+    Ok(find_btrfs_devices_by_label(label)?
+        .into_iter()
+        .next()
+        .map(|device| format!("/dev/{}", device.name)))
+}
+
+/// Every Btrfs device carrying `label` (clones share the label)
+pub fn find_btrfs_devices_by_label(label: &str) -> Result<Vec<BlockDevice>> {
     Ok(list_block_devices()?
         .into_iter()
-        .find(|device| {
+        .filter(|device| {
             device.fstype.as_deref() == Some("btrfs") && device.label.as_deref() == Some(label)
         })
-        .map(|device| format!("/dev/{}", device.name)))
+        .collect())
 }
 
 pub fn list_block_device_names() -> Result<Vec<String>> {
@@ -100,8 +146,27 @@ pub fn list_block_device_names() -> Result<Vec<String>> {
         .collect())
 }
 
+/// Whole-disk block devices with label, fstype and size
+pub fn list_block_devices() -> Result<Vec<BlockDevice>> {
+    let output = shell_run(
+        "lsblk",
+        &["-J", "-b", "-d", "-o", "NAME,LABEL,FSTYPE,SIZE,UUID"],
+    )?;
+    parse_lsblk_devices(&output)
+}
+
 pub fn read_block_device(device: &str) -> Result<Option<BlockDevice>> {
-    let output = shell_run("lsblk", &["-J", "-d", "-o", "NAME,LABEL,FSTYPE", device])?;
+    let output = shell_run(
+        "lsblk",
+        &[
+            "-J",
+            "-b",
+            "-d",
+            "-o",
+            "NAME,LABEL,FSTYPE,SIZE,UUID",
+            device,
+        ],
+    )?;
     Ok(parse_lsblk_devices(&output)?.into_iter().next())
 }
 
@@ -120,8 +185,10 @@ pub fn list_btrfs_mounts() -> Result<Vec<MountInfo>> {
 }
 
 pub fn find_mount(path: &str) -> Result<Option<MountInfo>> {
+    let args = ["-J", path, "-o", "TARGET,SOURCE,FSTYPE,OPTIONS,UUID"];
+    echo_command("findmnt", &args);
     let output = Command::new("findmnt")
-        .args(["-J", path, "-o", "TARGET,SOURCE,FSTYPE,OPTIONS,UUID"])
+        .args(args)
         .output()
         .with_context(|| format!("Failed to execute: findmnt -J {}", path))?;
 
@@ -157,7 +224,16 @@ pub fn systemctl_property(unit: &str, property: &str) -> Result<String> {
     shell_run("systemctl", &["show", unit, &property_arg, "--value"])
 }
 
+/// Unit property value, or None when unset, "n/a" or systemctl fails
+pub fn read_unit_property(unit: &str, property: &str) -> Option<String> {
+    systemctl_property(unit, property)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty() && value != "n/a")
+}
+
 pub fn pacman_query_version(package: &str) -> Result<Option<String>> {
+    echo_command("pacman", &["-Q", package]);
     let output = Command::new("pacman")
         .args(["-Q", package])
         .output()
@@ -172,6 +248,7 @@ pub fn pacman_query_version(package: &str) -> Result<Option<String>> {
 }
 
 pub fn pacman_query_depends(package: &str) -> Result<Vec<String>> {
+    echo_command("pacman", &["-Qi", package]);
     let output = Command::new("pacman")
         .args(["-Qi", package])
         .output()
@@ -185,6 +262,209 @@ pub fn pacman_query_depends(package: &str) -> Result<Vec<String>> {
     Ok(parse_pacman_depends(&stdout))
 }
 
+/// Parse an ssh:// btrbk target into its connection parts
+pub fn parse_ssh_target(target: &str) -> Option<SshTarget> {
+    let rest = target.strip_prefix("ssh://")?;
+    let authority = rest.split('/').next().unwrap_or("");
+
+    let (user, host_port) = match authority.split_once('@') {
+        Some((user, host_port)) => (Some(user.to_string()), host_port),
+        None => (None, authority),
+    };
+    let (host, port) = match host_port.split_once(':') {
+        Some((host, port)) => (host, Some(port.parse().ok()?)),
+        None => (host_port, None),
+    };
+
+    if host.is_empty() {
+        return None;
+    }
+
+    Some(SshTarget {
+        user,
+        host: host.to_string(),
+        port,
+    })
+}
+
+/// Check that an ssh target accepts a non-interactive connection within the timeout
+pub fn ssh_reachable(target: &SshTarget, timeout_secs: u32) -> bool {
+    let connect_timeout = format!("ConnectTimeout={}", timeout_secs);
+    let destination = match &target.user {
+        Some(user) => format!("{}@{}", user, target.host),
+        None => target.host.clone(),
+    };
+
+    let port = target.port.map(|port| port.to_string());
+    let mut args = vec!["-o", "BatchMode=yes", "-o", &connect_timeout];
+    if let Some(port) = &port {
+        args.extend(["-p", port.as_str()]);
+    }
+    args.extend([destination.as_str(), "true"]);
+
+    echo_command("ssh", &args);
+    Command::new("ssh")
+        .args(&args)
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+/// Return the ssh:// targets that fail a short connection test
+pub fn find_unreachable_ssh_targets(targets: &[String]) -> Vec<String> {
+    targets
+        .iter()
+        .filter(|target| {
+            parse_ssh_target(target)
+                .map(|ssh| !ssh_reachable(&ssh, 5))
+                .unwrap_or(false)
+        })
+        .cloned()
+        .collect()
+}
+
+/// List subvolume paths below a mounted Btrfs filesystem (btrfs subvolume list)
+pub fn list_subvolumes(path: &str) -> Result<Vec<String>> {
+    let output = shell_run("btrfs", &["subvolume", "list", path])?;
+    Ok(parse_subvolume_list(&output))
+}
+
+/// Space accounted to a subvolume by its level-0 qgroup
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubvolumeUsage {
+    pub path: String,
+    pub referenced: u64,
+    pub exclusive: u64,
+}
+
+/// Per-subvolume usage from btrfs qgroups; fails when quotas are disabled
+pub fn subvolume_usage(path: &str) -> Result<Vec<SubvolumeUsage>> {
+    let list = shell_run("btrfs", &["subvolume", "list", path])?;
+    let qgroups = shell_run("btrfs", &["qgroup", "show", "--raw", path])?;
+    Ok(parse_subvolume_usage(&list, &qgroups))
+}
+
+/// Read subvolume metadata; fails when the path is not a valid subvolume
+pub fn show_subvolume(path: &str) -> Result<SubvolumeInfo> {
+    let output = shell_run("btrfs", &["subvolume", "show", path])?;
+    Ok(parse_subvolume_show(&output))
+}
+
+/// Bytes allocated on the filesystem containing `path` (btrfs filesystem usage "Used")
+pub fn btrfs_used_bytes(path: &str) -> Result<u64> {
+    let output = shell_run("btrfs", &["filesystem", "usage", "-b", path])?;
+    parse_btrfs_usage_used(&output)
+        .with_context(|| format!("Could not read used bytes for {}", path))
+}
+
+/// Estimated free bytes on the filesystem containing `path` ("Free (estimated)")
+pub fn btrfs_free_bytes(path: &str) -> Result<u64> {
+    let output = shell_run("btrfs", &["filesystem", "usage", "-b", path])?;
+    parse_btrfs_usage_free(&output)
+        .with_context(|| format!("Could not read free bytes for {}", path))
+}
+
+/// Total bytes referenced by the files under `path` (btrfs filesystem du -s)
+pub fn btrfs_du_total_bytes(path: &str) -> Result<u64> {
+    let output = shell_run("btrfs", &["filesystem", "du", "-s", "--raw", path])?;
+    parse_btrfs_du_total(&output).with_context(|| format!("Could not read size of {}", path))
+}
+
+/// Whether the No_COW (`C`) attribute is set on `path` (lsattr -d)
+pub fn has_nocow_attr(path: &str) -> Result<bool> {
+    let output = shell_run("lsattr", &["-d", path])?;
+    parse_lsattr_flags(&output)
+        .map(|flags| flags.contains('C'))
+        .with_context(|| format!("Could not read attributes of {}", path))
+}
+
+/// Numeric (uid, gid) of a user (id -u / id -g)
+pub fn user_ids(user: &str) -> Result<(u32, u32)> {
+    let uid = shell_run("id", &["-u", user])?;
+    let gid = shell_run("id", &["-g", user])?;
+    Ok((
+        uid.trim()
+            .parse()
+            .with_context(|| format!("Invalid uid for {}: {}", user, uid))?,
+        gid.trim()
+            .parse()
+            .with_context(|| format!("Invalid gid for {}: {}", user, gid))?,
+    ))
+}
+
+/// Whether the filesystem holding `path` (or its nearest existing ancestor) is mounted read-only
+pub fn is_read_only(path: &str) -> bool {
+    let existing = Path::new(path)
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .unwrap_or(Path::new("/"));
+    shell_run(
+        "findmnt",
+        &["-n", "-o", "OPTIONS", "-T", &existing.to_string_lossy()],
+    )
+    .map(|options| has_ro_option(&options))
+    .unwrap_or(false)
+}
+
+fn has_ro_option(options: &str) -> bool {
+    options.trim().split(',').any(|option| option == "ro")
+}
+
+/// (pid, command name) of processes whose cwd, root, executable, open files
+/// or memory mappings lie under `path`, read from /proc
+pub fn processes_using(path: &str) -> Vec<(u32, String)> {
+    let prefix = Path::new(path);
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+
+    let mut holders = Vec::new();
+    for entry in entries.flatten() {
+        let Some(pid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse().ok())
+        else {
+            continue;
+        };
+        let proc_dir = entry.path();
+        let mut links: Vec<PathBuf> = ["cwd", "root", "exe"]
+            .iter()
+            .filter_map(|link| fs::read_link(proc_dir.join(link)).ok())
+            .collect();
+        if let Ok(fds) = fs::read_dir(proc_dir.join("fd")) {
+            links.extend(fds.flatten().filter_map(|fd| fs::read_link(fd.path()).ok()));
+        }
+        let maps = fs::read_to_string(proc_dir.join("maps")).unwrap_or_default();
+
+        if links.iter().any(|link| link.starts_with(prefix)) || maps_reference(&maps, prefix) {
+            let comm = fs::read_to_string(proc_dir.join("comm")).unwrap_or_default();
+            holders.push((pid, comm.trim().to_string()));
+        }
+    }
+    holders.sort();
+    holders
+}
+
+/// Whether a /proc/<pid>/maps listing maps a file under `prefix`
+fn maps_reference(maps: &str, prefix: &Path) -> bool {
+    maps.lines()
+        .filter_map(|line| line.split_whitespace().nth(5))
+        .any(|file| Path::new(file).starts_with(prefix))
+}
+
+/// Entries in `path` when it is a plain directory that a mount would hide
+///
+/// Returns 0 for mountpoints, missing paths and empty directories.
+pub fn shadowed_entry_count(path: &str) -> usize {
+    if is_mountpoint(path) {
+        return 0;
+    }
+    fs::read_dir(path)
+        .map(|entries| entries.count())
+        .unwrap_or(0)
+}
+
 pub fn list_directory_names(path: &str) -> Result<Vec<String>> {
     let mut entries = fs::read_dir(path)?
         .map(|entry| entry.map(|item| item.file_name().to_string_lossy().to_string()))
@@ -193,11 +473,6 @@ pub fn list_directory_names(path: &str) -> Result<Vec<String>> {
     Ok(entries)
 }
 
-fn list_block_devices() -> Result<Vec<BlockDevice>> {
-    let output = shell_run("lsblk", &["-J", "-d", "-o", "NAME,LABEL,FSTYPE"])?;
-    parse_lsblk_devices(&output)
-}
-
 fn parse_lsblk_devices(output: &str) -> Result<Vec<BlockDevice>> {
     let parsed: LsblkOutput = serde_json::from_str(output).context("Failed to parse lsblk JSON")?;
     Ok(parsed
@@ -207,6 +482,8 @@ fn parse_lsblk_devices(output: &str) -> Result<Vec<BlockDevice>> {
             name: device.name,
             label: device.label,
             fstype: device.fstype,
+            size: device.size,
+            uuid: device.uuid,
         })
         .collect())
 }
@@ -235,6 +512,141 @@ fn flatten_filesystem(filesystem: &FindmntFilesystem, mounts: &mut Vec<MountInfo
     }
 }
 
+/// Parse the timestamp suffix of a btrbk snapshot name (e.g. usr.20240115T0300)
+pub fn parse_snapshot_timestamp(name: &str) -> Option<NaiveDateTime> {
+    parse_snapshot_name(name).map(|(_, stamp)| stamp)
+}
+
+/// Subvolume part of a btrbk snapshot name (usr.20240115T0300_1 -> usr),
+/// or None when the suffix is not a btrbk timestamp
+pub fn parse_snapshot_subvolume(name: &str) -> Option<&str> {
+    parse_snapshot_name(name).map(|(subvol, _)| subvol)
+}
+
+/// Split a btrbk snapshot name into its subvolume and timestamp
+/// (usr.20240115T0300_1 -> ("usr", 2024-01-15 03:00))
+pub fn parse_snapshot_name(name: &str) -> Option<(&str, NaiveDateTime)> {
+    let (subvol, suffix) = name.rsplit_once('.')?;
+    if subvol.is_empty() {
+        return None;
+    }
+    // Drop btrbk's collision counter (`_N`) and long-iso timezone offset
+    let stamp = suffix.split(['_', '+', '-']).next()?;
+
+    let stamp = match stamp.len() {
+        8 => chrono::NaiveDate::parse_from_str(stamp, "%Y%m%d")
+            .ok()
+            .and_then(|date| date.and_hms_opt(0, 0, 0)),
+        13 => NaiveDateTime::parse_from_str(stamp, "%Y%m%dT%H%M").ok(),
+        15 => NaiveDateTime::parse_from_str(stamp, "%Y%m%dT%H%M%S").ok(),
+        _ => None,
+    }?;
+    Some((subvol, stamp))
+}
+
+/// `name`, or `name_N` with the lowest free N when `name` is taken, the
+/// counter btrbk appends when two snapshots get the same timestamp
+pub fn unique_snapshot_name(existing: &[String], name: &str) -> String {
+    if !existing.iter().any(|entry| entry == name) {
+        return name.to_string();
+    }
+    (1..)
+        .map(|counter| format!("{}_{}", name, counter))
+        .find(|candidate| !existing.contains(candidate))
+        .expect("some counter is free")
+}
+
+/// Flag column of `lsattr -d` output ("---------------C------ /path")
+fn parse_lsattr_flags(output: &str) -> Option<&str> {
+    output.lines().next()?.split_whitespace().next()
+}
+
+fn parse_subvolume_list(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| line.split_once(" path ").map(|(_, path)| path.trim()))
+        .filter(|path| !path.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Join `btrfs qgroup show --raw` rows to subvolume paths by id (qgroup 0/<id>)
+fn parse_subvolume_usage(list: &str, qgroups: &str) -> Vec<SubvolumeUsage> {
+    let paths: HashMap<u64, &str> = list
+        .lines()
+        .filter_map(|line| {
+            let id = line.strip_prefix("ID ")?.split_whitespace().next()?;
+            let (_, path) = line.split_once(" path ")?;
+            Some((id.parse().ok()?, path.trim()))
+        })
+        .collect();
+
+    let mut usage: Vec<SubvolumeUsage> = qgroups
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let id: u64 = fields.next()?.strip_prefix("0/")?.parse().ok()?;
+            Some(SubvolumeUsage {
+                path: paths.get(&id)?.to_string(),
+                referenced: fields.next()?.parse().ok()?,
+                exclusive: fields.next()?.parse().ok()?,
+            })
+        })
+        .collect();
+    usage.sort_by(|a, b| a.path.cmp(&b.path));
+    usage
+}
+
+fn parse_subvolume_show(output: &str) -> SubvolumeInfo {
+    let mut info = SubvolumeInfo {
+        path: output.lines().next().unwrap_or("").trim().to_string(),
+        uuid: None,
+        readonly: false,
+        gen_at_creation: None,
+    };
+
+    for line in output.lines() {
+        let Some((key, value)) = line.trim().split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match key.trim() {
+            "UUID" if value != "-" => info.uuid = Some(value.to_string()),
+            "Flags" => info.readonly = value.split(',').any(|flag| flag.trim() == "readonly"),
+            "Gen at creation" => info.gen_at_creation = value.parse().ok(),
+            _ => {}
+        }
+    }
+
+    info
+}
+
+fn parse_btrfs_usage_used(output: &str) -> Option<u64> {
+    output.lines().find_map(|line| {
+        let value = line.trim().strip_prefix("Used:")?;
+        value.trim().parse().ok()
+    })
+}
+
+fn parse_btrfs_usage_free(output: &str) -> Option<u64> {
+    output.lines().find_map(|line| {
+        let value = line.trim().strip_prefix("Free (estimated):")?;
+        value.split_whitespace().next()?.parse().ok()
+    })
+}
+
+/// First column of the summary row below the "Total Exclusive ..." header
+fn parse_btrfs_du_total(output: &str) -> Option<u64> {
+    output
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with("Total"))
+        .nth(1)?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
 fn parse_pacman_query_version(output: &str) -> Option<String> {
     let line = output.lines().next()?.trim();
     let (_, version) = line.split_once(char::is_whitespace)?;
@@ -294,6 +706,10 @@ struct LsblkDevice {
     label: Option<String>,
     #[serde(default)]
     fstype: Option<String>,
+    #[serde(default)]
+    size: Option<u64>,
+    #[serde(default)]
+    uuid: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -318,17 +734,52 @@ struct FindmntFilesystem {
     children: Vec<FindmntFilesystem>,
 }
 
+/// Human-readable size with binary units
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::path::PathBuf;
     use tempfile::tempdir;
 
+    #[test]
+    fn maps_reference_matches_mapped_files_by_component() {
+        let maps = "\
+7f00-7f10 r-xp 00000000 00:1f 1234 /usr/lib/libc.so.6
+7f20-7f30 rw-p 00000000 00:00 0
+7ffc-7ffd rw-p 00000000 00:00 0 [stack]
+";
+        assert!(maps_reference(maps, Path::new("/usr")));
+        assert!(!maps_reference(maps, Path::new("/us")));
+        assert!(!maps_reference(maps, Path::new("/home/alice")));
+    }
+
+    #[test]
+    fn has_ro_option_matches_whole_option() {
+        assert!(has_ro_option("ro,relatime\n"));
+        assert!(has_ro_option("relatime,ro"));
+        assert!(!has_ro_option("rw,relatime,errors=remount-ro"));
+    }
+
     #[test]
     fn parse_lsblk_devices_reads_json() {
         let output = r#"{
             "blockdevices": [
-                {"name":"sda","label":"ArchBtrfs","fstype":"btrfs"},
+                {"name":"sda","label":"ArchBtrfs","fstype":"btrfs","size":274877906944,"uuid":"1234-abcd"},
                 {"name":"sdb","label":null,"fstype":"ext4"}
             ]
         }"#;
@@ -339,6 +790,9 @@ mod tests {
         assert_eq!(devices[0].name, "sda");
         assert_eq!(devices[0].label.as_deref(), Some("ArchBtrfs"));
         assert_eq!(devices[0].fstype.as_deref(), Some("btrfs"));
+        assert_eq!(devices[0].size, Some(274877906944));
+        assert_eq!(devices[1].size, None);
+        assert_eq!(devices[0].uuid.as_deref(), Some("1234-abcd"));
     }
 
     #[test]
@@ -377,6 +831,192 @@ mod tests {
         assert_eq!(version.as_deref(), Some("260.1-1"));
     }
 
+    #[test]
+    fn parse_subvolume_list_extracts_paths() {
+        let output = "\
+ID 256 gen 120 top level 5 path @usr
+ID 257 gen 118 top level 5 path @home
+ID 260 gen 20 top level 257 path @home/.cache
+ID 300 gen 119 top level 5 path .snapshots/usr.20240115T0300";
+
+        assert_eq!(
+            parse_subvolume_list(output),
+            vec![
+                "@usr",
+                "@home",
+                "@home/.cache",
+                ".snapshots/usr.20240115T0300"
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_subvolume_usage_joins_qgroups_to_paths() {
+        let list = "\
+ID 256 gen 120 top level 5 path @usr
+ID 257 gen 118 top level 5 path @home";
+        let qgroups = "\
+qgroupid         rfer         excl
+--------         ----         ----
+0/5             16384        16384
+0/256      3221225472   1073741824
+0/257       524288000    524288000
+1/100       524288000    524288000";
+
+        assert_eq!(
+            parse_subvolume_usage(list, qgroups),
+            vec![
+                SubvolumeUsage {
+                    path: "@home".to_string(),
+                    referenced: 524288000,
+                    exclusive: 524288000,
+                },
+                SubvolumeUsage {
+                    path: "@usr".to_string(),
+                    referenced: 3221225472,
+                    exclusive: 1073741824,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_subvolume_show_reads_uuid_and_readonly_flag() {
+        let output = "\
+.snapshots/usr.20240115T0300
+\tName: \t\t\tusr.20240115T0300
+\tUUID: \t\t\t2b1c5e9a-0d41-4c0e-9a53-0c8f3f0f5a11
+\tParent UUID: \t\t7d3e1f00-5b2a-4c6d-8e9f-1a2b3c4d5e6f
+\tReceived UUID: \t\t-
+\tFlags: \t\t\treadonly
+\tGen at creation: \t1842
+";
+
+        let info = parse_subvolume_show(output);
+
+        assert_eq!(
+            info.uuid.as_deref(),
+            Some("2b1c5e9a-0d41-4c0e-9a53-0c8f3f0f5a11")
+        );
+        assert!(info.readonly);
+        assert_eq!(info.gen_at_creation, Some(1842));
+        assert_eq!(info.path, ".snapshots/usr.20240115T0300");
+        assert!(!parse_subvolume_show("\tFlags: \t\t\t-\n").readonly);
+    }
+
+    #[test]
+    fn parse_btrfs_usage_used_reads_overall_value() {
+        let output = "\
+Overall:
+    Device size:\t\t 161061273600
+    Device allocated:\t  32212254720
+    Used:\t\t\t  28991029248
+    Free (estimated):\t 130023424000\t(min: 114917138432)
+Data,single: Size:30064771072, Used:27917287424 (92.86%)
+";
+
+        assert_eq!(parse_btrfs_usage_used(output), Some(28991029248));
+        assert_eq!(parse_btrfs_usage_free(output), Some(130023424000));
+    }
+
+    #[test]
+    fn parse_btrfs_du_total_reads_summary_row() {
+        let output = "\
+     Total   Exclusive  Set shared  Filename
+5368709120    1048576  5367660544  /mnt/btrfs/.snapshots/usr.20240115T0300
+";
+
+        assert_eq!(parse_btrfs_du_total(output), Some(5368709120));
+        assert_eq!(parse_btrfs_du_total("ERROR: not a btrfs filesystem"), None);
+    }
+
+    #[test]
+    fn shadowed_entry_count_counts_plain_directory_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_str().unwrap();
+        assert_eq!(shadowed_entry_count(path), 0);
+
+        fs::write(dir.path().join("notes.txt"), "").unwrap();
+        assert_eq!(shadowed_entry_count(path), 1);
+        assert_eq!(shadowed_entry_count(&format!("{}/missing", path)), 0);
+    }
+
+    #[test]
+    fn parse_snapshot_timestamp_handles_btrbk_formats() {
+        let expected = NaiveDateTime::parse_from_str("20240115T0300", "%Y%m%dT%H%M").unwrap();
+
+        assert_eq!(
+            parse_snapshot_timestamp("usr.20240115T0300"),
+            Some(expected)
+        );
+        assert_eq!(
+            parse_snapshot_timestamp("usr.20240115T0300_1"),
+            Some(expected)
+        );
+        assert_eq!(
+            parse_snapshot_timestamp("var_lib_pacman.20240115T030000+0100"),
+            Some(expected)
+        );
+        assert!(parse_snapshot_timestamp("usr.20240115").is_some());
+        assert_eq!(parse_snapshot_timestamp("not-a-snapshot"), None);
+    }
+
+    #[test]
+    fn parse_snapshot_subvolume_strips_timestamp_and_counter() {
+        assert_eq!(parse_snapshot_subvolume("usr.20240115T0300"), Some("usr"));
+        assert_eq!(
+            parse_snapshot_subvolume("var_lib_pacman.20240115T0300_2"),
+            Some("var_lib_pacman")
+        );
+        assert_eq!(parse_snapshot_subvolume("usr.restore-backup"), None);
+        assert_eq!(parse_snapshot_subvolume(".20240115T0300"), None);
+    }
+
+    #[test]
+    fn parse_snapshot_name_returns_subvolume_and_timestamp() {
+        let expected = NaiveDateTime::parse_from_str("20240115T0300", "%Y%m%dT%H%M").unwrap();
+        assert_eq!(
+            parse_snapshot_name("var_lib_pacman.20240115T0300_2"),
+            Some(("var_lib_pacman", expected))
+        );
+        assert_eq!(
+            parse_snapshot_name("home.20240115").map(|(_, stamp)| stamp.to_string()),
+            Some("2024-01-15 00:00:00".to_string())
+        );
+        assert_eq!(parse_snapshot_name("usr.restore-backup"), None);
+    }
+
+    #[test]
+    fn unique_snapshot_name_adds_counter_for_same_second_snapshots() {
+        let mut existing = vec!["home.20240115T030000".to_string()];
+        assert_eq!(
+            unique_snapshot_name(&existing, "usr.20240115T030000"),
+            "usr.20240115T030000"
+        );
+
+        let second = unique_snapshot_name(&existing, "home.20240115T030000");
+        assert_eq!(second, "home.20240115T030000_1");
+        existing.push(second);
+        let third = unique_snapshot_name(&existing, "home.20240115T030000");
+        assert_eq!(third, "home.20240115T030000_2");
+
+        existing.push(third.clone());
+        assert_eq!(parse_snapshot_subvolume(&third), Some("home"));
+        assert_eq!(
+            parse_snapshot_timestamp(&third),
+            parse_snapshot_timestamp("home.20240115T030000")
+        );
+    }
+
+    #[test]
+    fn parse_lsattr_flags_reads_first_column() {
+        let flags = parse_lsattr_flags("---------------C------ /mnt/btrfs/@containers\n").unwrap();
+        assert!(flags.contains('C'));
+        let flags = parse_lsattr_flags("---------------------- /mnt/btrfs/@usr").unwrap();
+        assert!(!flags.contains('C'));
+        assert_eq!(parse_lsattr_flags(""), None);
+    }
+
     #[test]
     fn parse_pacman_depends_strips_constraints() {
         let output = "\
@@ -388,6 +1028,29 @@ Optional Deps   : None\n";
         assert_eq!(deps, vec!["glibc", "libcap", "sh"]);
     }
 
+    #[test]
+    fn parse_ssh_target_reads_user_host_and_port() {
+        let target = parse_ssh_target("ssh://root@backup:2222/srv/btrbk").unwrap();
+
+        assert_eq!(target.user.as_deref(), Some("root"));
+        assert_eq!(target.host, "backup");
+        assert_eq!(target.port, Some(2222));
+    }
+
+    #[test]
+    fn parse_ssh_target_ignores_local_paths() {
+        assert_eq!(parse_ssh_target("/mnt/backup/btrbk"), None);
+        assert_eq!(parse_ssh_target("ssh:///srv/btrbk"), None);
+        assert_eq!(
+            parse_ssh_target("ssh://backup/srv/btrbk"),
+            Some(SshTarget {
+                user: None,
+                host: "backup".to_string(),
+                port: None,
+            })
+        );
+    }
+
     #[test]
     fn ensure_dependencies_reports_packages() {
         let dependency = Dependency::new("fakepkg", &["missingcmd"]);
@@ -422,4 +1085,11 @@ Optional Deps   : None\n";
                 .as_ref()
         ));
     }
+
+    #[test]
+    fn format_bytes_uses_binary_units() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    }
 }
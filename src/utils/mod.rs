@@ -1,3 +1,8 @@
+pub mod btrfs;
 pub mod cli;
+pub mod mount_options;
 pub mod prompt;
+pub mod report;
+pub mod scope;
 pub mod shell;
+pub mod wsl;
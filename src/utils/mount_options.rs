@@ -0,0 +1,274 @@
+//! Btrfs mount option parsing and validation
+
+/// Btrfs-specific mount options (see btrfs(5))
+const BTRFS_OPTIONS: &[&str] = &[
+    "acl",
+    "alloc_start",
+    "autodefrag",
+    "barrier",
+    "check_int",
+    "check_int_data",
+    "check_int_print_mask",
+    "clear_cache",
+    "commit",
+    "compress",
+    "compress-force",
+    "datacow",
+    "datasum",
+    "degraded",
+    "device",
+    "discard",
+    "enospc_debug",
+    "fatal_errors",
+    "flushoncommit",
+    "fragment",
+    "inode_cache",
+    "max_inline",
+    "metadata_ratio",
+    "noacl",
+    "noautodefrag",
+    "nobarrier",
+    "nodatacow",
+    "nodatasum",
+    "nodiscard",
+    "noenospc_debug",
+    "noflushoncommit",
+    "noinode_cache",
+    "nologreplay",
+    "norecovery",
+    "nospace_cache",
+    "nossd",
+    "nossd_spread",
+    "notreelog",
+    "recovery",
+    "ref_verify",
+    "rescan_uuid_tree",
+    "rescue",
+    "skip_balance",
+    "space_cache",
+    "ssd",
+    "ssd_spread",
+    "subvol",
+    "subvolid",
+    "thread_pool",
+    "treelog",
+    "usebackuproot",
+    "user_subvol_rm_allowed",
+];
+
+/// Generic mount(8) and systemd options accepted for any filesystem
+const GENERIC_OPTIONS: &[&str] = &[
+    "_netdev",
+    "async",
+    "atime",
+    "auto",
+    "context",
+    "defaults",
+    "dev",
+    "diratime",
+    "dirsync",
+    "exec",
+    "iversion",
+    "lazytime",
+    "mand",
+    "noatime",
+    "noauto",
+    "nodev",
+    "nodiratime",
+    "noexec",
+    "nofail",
+    "noiversion",
+    "nolazytime",
+    "nomand",
+    "norelatime",
+    "nosuid",
+    "nouser",
+    "relatime",
+    "ro",
+    "rw",
+    "silent",
+    "strictatime",
+    "suid",
+    "sync",
+    "user",
+    "users",
+];
+
+const COMPRESS_ALGORITHMS: &[&str] = &["zlib", "lzo", "zstd", "no", "none"];
+
+/// Values of `discard=`; plain `discard` means sync
+const DISCARD_MODES: &[&str] = &["sync", "async"];
+
+/// Return a warning for every option that btrfs or mount(8) would not recognize
+pub fn check_options(options: &str) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    for option in options.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+        let (key, value) = match option.split_once('=') {
+            Some((key, value)) => (key, Some(value)),
+            None => (option, None),
+        };
+
+        // x-systemd.* and other x-* options are passed through to userspace
+        if key.starts_with("x-") {
+            continue;
+        }
+
+        if !is_known_option(key) {
+            match suggest_option(key) {
+                Some(suggestion) => warnings.push(format!(
+                    "Unknown mount option '{}' (did you mean '{}'?)",
+                    key, suggestion
+                )),
+                None => warnings.push(format!("Unknown mount option '{}'", key)),
+            }
+            continue;
+        }
+
+        if key == "discard" {
+            if let Some(mode) = value.filter(|mode| !DISCARD_MODES.contains(mode)) {
+                warnings.push(format!(
+                    "Unknown discard mode '{}' in '{}' (expected one of: {})",
+                    mode,
+                    option,
+                    DISCARD_MODES.join(", ")
+                ));
+            }
+        }
+
+        if key == "compress" || key == "compress-force" {
+            if let Some(value) = value {
+                let algorithm = value.split(':').next().unwrap_or(value);
+                if !COMPRESS_ALGORITHMS.contains(&algorithm) {
+                    warnings.push(format!(
+                        "Unknown compression algorithm '{}' in '{}' (expected one of: {})",
+                        algorithm,
+                        option,
+                        COMPRESS_ALGORITHMS.join(", ")
+                    ));
+                }
+            }
+        }
+    }
+
+    warnings
+}
+
+/// `base` followed by `append`; an appended option replaces every option in
+/// `base` with the same key (`compress=zstd:1` drops `compress=zstd:3`)
+pub fn append_options(base: &str, append: &str) -> String {
+    let key = |option: &str| {
+        option
+            .split('=')
+            .next()
+            .unwrap_or(option)
+            .trim()
+            .to_string()
+    };
+    let appended: Vec<&str> = append
+        .split(',')
+        .map(str::trim)
+        .filter(|o| !o.is_empty())
+        .collect();
+    let replaced: Vec<String> = appended.iter().map(|option| key(option)).collect();
+
+    base.split(',')
+        .map(str::trim)
+        .filter(|o| !o.is_empty() && !replaced.contains(&key(o)))
+        .chain(appended)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn is_known_option(key: &str) -> bool {
+    BTRFS_OPTIONS.contains(&key) || GENERIC_OPTIONS.contains(&key)
+}
+
+/// Closest known option within a small edit distance, for typo hints
+fn suggest_option(key: &str) -> Option<&'static str> {
+    BTRFS_OPTIONS
+        .iter()
+        .chain(GENERIC_OPTIONS)
+        .map(|known| (edit_distance(key, known), *known))
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, known)| known)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn append_options_replaces_same_key_and_keeps_order() {
+        assert_eq!(
+            append_options("compress=zstd:3,noatime,nofail", "nodatacow"),
+            "compress=zstd:3,noatime,nofail,nodatacow"
+        );
+        assert_eq!(
+            append_options("compress=zstd:3,noatime,nofail", "compress=zstd:1, noatime"),
+            "nofail,compress=zstd:1,noatime"
+        );
+        assert_eq!(append_options("noatime", ""), "noatime");
+    }
+
+    #[test]
+    fn check_options_accepts_default_options() {
+        assert!(check_options("compress=zstd:3,noatime,nofail").is_empty());
+        assert!(
+            check_options("subvol=@usr,compress-force=zstd,x-systemd.device-timeout=30").is_empty()
+        );
+    }
+
+    #[test]
+    fn check_options_suggests_typo_fixes() {
+        let warnings = check_options("compres=zstd,noatme,nofial");
+
+        assert_eq!(warnings.len(), 3);
+        assert!(warnings[0].contains("did you mean 'compress'"));
+        assert!(warnings[1].contains("did you mean 'noatime'"));
+        assert!(warnings[2].contains("did you mean 'nofail'"));
+    }
+
+    #[test]
+    fn check_options_flags_unknown_compression_algorithm() {
+        let warnings = check_options("compress=zsdt:3");
+
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("Unknown compression algorithm 'zsdt'"));
+    }
+
+    #[test]
+    fn check_options_accepts_discard_modes() {
+        assert!(check_options("compress=zstd:3,noatime,discard=async").is_empty());
+        assert!(check_options("discard").is_empty());
+
+        let warnings = check_options("discard=asnyc");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("Unknown discard mode 'asnyc'"));
+    }
+
+    #[test]
+    fn check_options_reports_unrelated_options_without_suggestion() {
+        assert_eq!(
+            check_options("frobnicate"),
+            vec!["Unknown mount option 'frobnicate'".to_string()]
+        );
+    }
+}
@@ -1,9 +1,26 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use console::style;
 use dialoguer::{Confirm, Input, Select};
+use std::io::IsTerminal;
+
+use crate::utils::report;
+
+/// dialoguer needs a terminal; piped stdin falls back to the prompt defaults
+pub fn is_interactive() -> bool {
+    std::io::stdin().is_terminal() && std::io::stderr().is_terminal()
+}
+
+/// Report the answer chosen on behalf of a non-interactive caller
+fn non_interactive_answer(prompt: &str, answer: &str) {
+    info(&format!(
+        "{} [non-interactive, using default: {}]",
+        prompt, answer
+    ));
+}
 
 /// Print a step header
 pub fn step(num: u32, total: u32, title: &str) {
+    report::record_step(title);
     println!(
         "\n{} {}",
         style(format!("[{}/{}]", num, total)).cyan().bold(),
@@ -16,6 +33,11 @@ pub fn success(msg: &str) {
     println!("  {} {}", style("✓").green(), msg);
 }
 
+/// Print a failure message
+pub fn failure(msg: &str) {
+    println!("  {} {}", style("✗").red(), msg);
+}
+
 /// Print an info message
 pub fn info(msg: &str) {
     println!("  {} {}", style("→").blue(), msg);
@@ -28,6 +50,10 @@ pub fn warn(msg: &str) {
 
 /// Ask for confirmation
 pub fn confirm(msg: &str, default: bool) -> Result<bool> {
+    if !is_interactive() {
+        non_interactive_answer(msg, if default { "yes" } else { "no" });
+        return Ok(default);
+    }
     Ok(Confirm::new()
         .with_prompt(msg)
         .default(default)
@@ -43,8 +69,26 @@ pub fn confirm_or_yes(msg: &str, default: bool, yes: bool) -> Result<bool> {
     }
 }
 
+/// Ask before a destructive step, or return true if --yes was passed
+///
+/// Unlike `confirm`, a non-interactive caller is not given the default: it
+/// has to pass --yes, so piped stdin never formats or deletes anything.
+pub fn confirm_destructive(msg: &str, yes: bool) -> Result<bool> {
+    if yes {
+        return Ok(true);
+    }
+    if !is_interactive() {
+        bail!("'{}' needs an interactive terminal; rerun with --yes", msg);
+    }
+    Ok(Confirm::new().with_prompt(msg).default(true).interact()?)
+}
+
 /// Ask for text input with a default value
 pub fn input(prompt: &str, default: &str) -> Result<String> {
+    if !is_interactive() {
+        non_interactive_answer(prompt, default);
+        return Ok(default.to_string());
+    }
     Ok(Input::new()
         .with_prompt(prompt)
         .default(default.to_string())
@@ -53,6 +97,16 @@ pub fn input(prompt: &str, default: &str) -> Result<String> {
 
 /// Select from a list of options
 pub fn select(prompt: &str, options: &[&str], default: usize) -> Result<usize> {
+    if !is_interactive() {
+        let Some(option) = options.get(default) else {
+            bail!(
+                "'{}' needs an interactive terminal; rerun with --yes",
+                prompt
+            );
+        };
+        non_interactive_answer(prompt, option);
+        return Ok(default);
+    }
     Ok(Select::new()
         .with_prompt(prompt)
         .items(options)
@@ -69,3 +123,19 @@ pub fn section(title: &str) {
 pub fn kv(key: &str, value: &str) {
     println!("  {}: {}", style(key).dim(), value);
 }
+
+/// Print one changed field: `~` changed, `-` removed, `+` added
+pub fn change(key: &str, old: Option<&str>, new: Option<&str>) {
+    match (old, new) {
+        (Some(old), Some(new)) => println!(
+            "  {} {}: {} → {}",
+            style("~").yellow(),
+            key,
+            style(old).red(),
+            style(new).green()
+        ),
+        (Some(old), None) => println!("  {} {} = {}", style("-").red(), key, style(old).red()),
+        (None, Some(new)) => println!("  {} {} = {}", style("+").green(), key, style(new).green()),
+        (None, None) => {}
+    }
+}
@@ -0,0 +1,178 @@
+//! Structured operation report (--report-file)
+//!
+//! While a report is active, step headers and external commands are recorded
+//! with timestamps. The whole run is written as one JSON document at the end.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs;
+#[cfg(not(test))]
+use std::sync::Mutex;
+
+// One report per process; commands run on worker threads (parallel sends)
+// belong to it too
+#[cfg(not(test))]
+static REPORT: Mutex<Option<Report>> = Mutex::new(None);
+
+// Tests run in parallel threads, so each one gets a report of its own
+#[cfg(test)]
+thread_local! {
+    static REPORT: std::cell::RefCell<Option<Report>> = const { std::cell::RefCell::new(None) };
+}
+
+/// Run `f` on the active report slot
+#[cfg(not(test))]
+fn with_slot<R>(f: impl FnOnce(&mut Option<Report>) -> R) -> R {
+    f(&mut REPORT.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+#[cfg(test)]
+fn with_slot<R>(f: impl FnOnce(&mut Option<Report>) -> R) -> R {
+    REPORT.with(|report| f(&mut report.borrow_mut()))
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    command: String,
+    started_at: String,
+    finished_at: String,
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Entry {
+    Step {
+        at: String,
+        title: String,
+    },
+    Command {
+        at: String,
+        command: String,
+        dry_run: bool,
+        success: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+fn now() -> String {
+    chrono::Local::now().to_rfc3339()
+}
+
+fn push(entry: Entry) {
+    with_slot(|report| {
+        if let Some(report) = report.as_mut() {
+            report.entries.push(entry);
+        }
+    });
+}
+
+/// Record a step header
+pub fn record_step(title: &str) {
+    push(Entry::Step {
+        at: now(),
+        title: title.to_string(),
+    });
+}
+
+/// Record an external command and its outcome
+pub fn record_command(cmd: &str, args: &[&str], dry_run: bool, error: Option<String>) {
+    push(Entry::Command {
+        at: now(),
+        command: format!("{} {}", cmd, args.join(" ")).trim_end().to_string(),
+        dry_run,
+        success: error.is_none(),
+        error,
+    });
+}
+
+/// Run `f`, and when `path` is set write a JSON report of the run to it
+pub fn with_report(
+    command: &str,
+    path: Option<&str>,
+    f: impl FnOnce() -> Result<()>,
+) -> Result<()> {
+    let Some(path) = path else {
+        return f();
+    };
+
+    let started_at = now();
+    with_slot(|report| {
+        *report = Some(Report {
+            command: command.to_string(),
+            started_at,
+            finished_at: String::new(),
+            success: false,
+            error: None,
+            entries: Vec::new(),
+        })
+    });
+
+    let result = f();
+
+    let mut report = with_slot(Option::take).expect("report started above");
+    report.finished_at = now();
+    report.success = result.is_ok();
+    report.error = result.as_ref().err().map(|e| format!("{:#}", e));
+
+    let content = serde_json::to_string_pretty(&report).context("Failed to serialize report")?;
+    fs::write(path, content).with_context(|| format!("Failed to write report file: {}", path))?;
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn with_report_records_steps_commands_and_failure() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+
+        let result = with_report("mount", Some(path), || {
+            record_step("Generating systemd mount units");
+            record_command("systemctl", &["daemon-reload"], false, None);
+            record_command("btrbk", &["dryrun"], true, None);
+            anyhow::bail!("systemctl enable failed")
+        });
+        assert!(result.is_err());
+
+        let report: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(report["command"], "mount");
+        assert_eq!(report["success"], false);
+        assert_eq!(report["error"], "systemctl enable failed");
+        assert_eq!(report["entries"][0]["kind"], "step");
+        assert_eq!(report["entries"][1]["command"], "systemctl daemon-reload");
+        assert_eq!(report["entries"][2]["dry_run"], true);
+        assert_eq!(report["entries"].as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn with_report_records_commands_that_fail_to_start() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+
+        let result = with_report("mount", Some(path), || {
+            crate::utils::shell::run("/nonexistent/wslarc-test", &["--flag"]).map(|_| ())
+        });
+        assert!(result.is_err());
+
+        let report: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        let entries = report["entries"].as_array().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["command"], "/nonexistent/wslarc-test --flag");
+        assert_eq!(entries[0]["success"], false);
+        assert!(entries[0]["error"]
+            .as_str()
+            .unwrap()
+            .contains("No such file"));
+    }
+}
@@ -0,0 +1,121 @@
+//! System vs user scope
+//!
+//! In user scope the config lives in `~/.config/wslarc` and generated units go
+//! to `~/.config/systemd/user`, so config inspection and unit generation work
+//! without root. Commands that touch disks or system mounts still need root.
+
+use anyhow::{bail, Result};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const SYSTEM_CONFIG_PATH: &str = "/etc/wslarc/config.toml";
+pub const SYSTEM_UNIT_DIR: &str = "/etc/systemd/system";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    System,
+    User,
+}
+
+impl Scope {
+    /// `--user-scope` forces user scope; otherwise it is used when not root
+    pub fn detect(user_scope: bool) -> Self {
+        if user_scope || !is_root() {
+            Scope::User
+        } else {
+            Scope::System
+        }
+    }
+
+    /// Config file used when `--config` is not given
+    ///
+    /// An auto-detected user scope without a user config keeps reading the
+    /// system config, so `wslarc status` works as before for regular users.
+    pub fn default_config_path(self, explicit: bool) -> String {
+        if self == Scope::System {
+            return SYSTEM_CONFIG_PATH.to_string();
+        }
+        match user_config_dir() {
+            Some(dir) => {
+                let path = dir.join("wslarc/config.toml");
+                if explicit || path.exists() {
+                    path.to_string_lossy().to_string()
+                } else {
+                    SYSTEM_CONFIG_PATH.to_string()
+                }
+            }
+            None => SYSTEM_CONFIG_PATH.to_string(),
+        }
+    }
+
+    /// Directory generated systemd units are written to
+    pub fn unit_dir(self) -> String {
+        match (self, user_config_dir()) {
+            (Scope::User, Some(dir)) => dir.join("systemd/user").to_string_lossy().to_string(),
+            _ => SYSTEM_UNIT_DIR.to_string(),
+        }
+    }
+
+    /// Fail with a clear message for commands that must run as root
+    pub fn require_root(self, command: &str) -> Result<()> {
+        if self == Scope::User {
+            bail!(
+                "'wslarc {}' changes disks or system mounts and must run as root (use sudo); \
+                 in user scope only config inspection and unit generation are available",
+                command
+            );
+        }
+        Ok(())
+    }
+}
+
+fn user_config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+}
+
+fn is_root() -> bool {
+    fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| parse_effective_uid(&status))
+        == Some(0)
+}
+
+/// Effective UID from /proc/self/status ("Uid: real effective saved fs")
+fn parse_effective_uid(status: &str) -> Option<u32> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Uid:"))
+        .and_then(|ids| ids.split_whitespace().nth(1))
+        .and_then(|uid| uid.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_effective_uid_reads_second_field() {
+        let status =
+            "Name:\twslarc\nUmask:\t0022\nUid:\t1000\t0\t0\t0\nGid:\t1000\t1000\t1000\t1000\n";
+        assert_eq!(parse_effective_uid(status), Some(0));
+        assert_eq!(parse_effective_uid("Name:\twslarc\n"), None);
+    }
+
+    #[test]
+    fn require_root_only_fails_in_user_scope() {
+        assert!(Scope::System.require_root("init").is_ok());
+        let err = Scope::User.require_root("init").unwrap_err().to_string();
+        assert!(err.contains("'wslarc init'"));
+        assert!(err.contains("must run as root"));
+    }
+
+    #[test]
+    fn system_scope_uses_etc_paths() {
+        assert_eq!(Scope::System.default_config_path(true), SYSTEM_CONFIG_PATH);
+        assert_eq!(Scope::System.unit_dir(), SYSTEM_UNIT_DIR);
+    }
+}
@@ -1,20 +1,73 @@
 use anyhow::{bail, Context, Result};
 use console::style;
 use log::{debug, trace};
-use std::io::{BufRead, BufReader};
+use std::fmt;
+use std::io::{BufRead, BufReader, Read};
 use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::utils::report;
+
+static SHOW_COMMANDS: AtomicBool = AtomicBool::new(false);
+
+/// Echo every external command before it runs (--show-commands)
+pub fn set_show_commands(enabled: bool) {
+    SHOW_COMMANDS.store(enabled, Ordering::Relaxed);
+}
+
+/// Print a command line to stderr when --show-commands is active
+pub fn echo_command(cmd: &str, args: &[&str]) {
+    if SHOW_COMMANDS.load(Ordering::Relaxed) {
+        eprintln!("  {} {} {}", style("[exec]").cyan(), cmd, args.join(" "));
+    }
+}
+
+/// Runs external commands for code that needs to be testable without
+/// touching the system
+pub trait CommandRunner {
+    fn run(&self, cmd: &str, args: &[&str]) -> Result<String>;
+
+    /// Print the command instead of running it when `dry_run` is set
+    fn run_or_dry(&self, cmd: &str, args: &[&str], dry_run: bool) -> Result<String> {
+        if dry_run {
+            println!(
+                "  {} {} {}",
+                style("[dry-run]").yellow(),
+                cmd,
+                args.join(" ")
+            );
+            report::record_command(cmd, args, true, None);
+            Ok(String::new())
+        } else {
+            self.run(cmd, args)
+        }
+    }
+}
+
+/// Runs commands for real through [`run`]
+pub struct RealRunner;
+
+impl CommandRunner for RealRunner {
+    fn run(&self, cmd: &str, args: &[&str]) -> Result<String> {
+        run(cmd, args)
+    }
+}
 
 pub fn run(cmd: &str, args: &[&str]) -> Result<String> {
     debug!("Executing: {} {}", cmd, args.join(" "));
+    echo_command(cmd, args);
 
     let output = Command::new(cmd)
         .args(args)
         .output()
+        .inspect_err(|e| report::record_command(cmd, args, false, Some(e.to_string())))
         .with_context(|| format!("Failed to execute: {} {}", cmd, args.join(" ")))?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
+        report::record_command(cmd, args, false, Some(stderr.trim().to_string()));
         bail!(
             "Command failed: {} {}\n{}",
             cmd,
@@ -23,6 +76,7 @@ pub fn run(cmd: &str, args: &[&str]) -> Result<String> {
         );
     }
 
+    report::record_command(cmd, args, false, None);
     let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
     trace!("Output: {}", stdout);
     Ok(stdout)
@@ -30,12 +84,14 @@ pub fn run(cmd: &str, args: &[&str]) -> Result<String> {
 
 pub fn run_with_output(cmd: &str, args: &[&str]) -> Result<()> {
     debug!("Executing (streaming): {} {}", cmd, args.join(" "));
+    echo_command(cmd, args);
 
     let mut child = Command::new(cmd)
         .args(args)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .spawn()
+        .inspect_err(|e| report::record_command(cmd, args, false, Some(e.to_string())))
         .with_context(|| format!("Failed to spawn: {} {}", cmd, args.join(" ")))?;
 
     let stdout_handle = child.stdout.take().map(|stdout| {
@@ -64,22 +120,222 @@ pub fn run_with_output(cmd: &str, args: &[&str]) -> Result<()> {
         let _ = handle.join();
     }
     if !status.success() {
+        report::record_command(cmd, args, false, Some(status.to_string()));
         bail!("Command failed: {} {}", cmd, args.join(" "));
     }
+    report::record_command(cmd, args, false, None);
 
     Ok(())
 }
 
-pub fn run_or_dry(cmd: &str, args: &[&str], dry_run: bool) -> Result<String> {
-    if dry_run {
-        println!(
-            "  {} {} {}",
-            style("[dry-run]").yellow(),
+/// Run a command and return stdout and stderr combined, whatever the exit status
+///
+/// For diagnostics, where e.g. `systemctl status` exits non-zero for inactive
+/// units but its output is still what we want.
+pub fn run_capture(cmd: &str, args: &[&str]) -> Result<String> {
+    debug!("Executing (capture): {} {}", cmd, args.join(" "));
+    echo_command(cmd, args);
+
+    let output = Command::new(cmd)
+        .args(args)
+        .output()
+        .inspect_err(|e| report::record_command(cmd, args, false, Some(e.to_string())))
+        .with_context(|| format!("Failed to execute: {} {}", cmd, args.join(" ")))?;
+    report::record_command(cmd, args, false, None);
+
+    let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    Ok(text.trim_end().to_string())
+}
+
+/// Exit code and stderr of a command whose failure the caller interprets
+pub struct CommandOutput {
+    pub code: Option<i32>,
+    pub stderr: String,
+}
+
+/// Run a command without treating a nonzero exit as an error
+pub fn run_unchecked(cmd: &str, args: &[&str]) -> Result<CommandOutput> {
+    debug!("Executing (unchecked): {} {}", cmd, args.join(" "));
+    echo_command(cmd, args);
+
+    let output = Command::new(cmd)
+        .args(args)
+        .stdout(Stdio::inherit())
+        .output()
+        .inspect_err(|e| report::record_command(cmd, args, false, Some(e.to_string())))
+        .with_context(|| format!("Failed to execute: {} {}", cmd, args.join(" ")))?;
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    let error = (!output.status.success()).then(|| stderr.trim().to_string());
+    report::record_command(cmd, args, false, error);
+
+    Ok(CommandOutput {
+        code: output.status.code(),
+        stderr,
+    })
+}
+
+/// Error returned by `run_with_timeout` when the command was killed
+#[derive(Debug)]
+pub struct CommandTimeout {
+    pub command: String,
+    pub timeout: Duration,
+}
+
+impl fmt::Display for CommandTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} did not finish within {}s and was killed",
+            self.command,
+            self.timeout.as_secs()
+        )
+    }
+}
+
+impl std::error::Error for CommandTimeout {}
+
+/// Like `run`, but kill the command and fail with `CommandTimeout` once
+/// `timeout` has passed
+pub fn run_with_timeout(cmd: &str, args: &[&str], timeout: Duration) -> Result<String> {
+    debug!(
+        "Executing (timeout {}s): {} {}",
+        timeout.as_secs(),
+        cmd,
+        args.join(" ")
+    );
+    echo_command(cmd, args);
+
+    let mut child = Command::new(cmd)
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .inspect_err(|e| report::record_command(cmd, args, false, Some(e.to_string())))
+        .with_context(|| format!("Failed to execute: {} {}", cmd, args.join(" ")))?;
+
+    // Drain both pipes while waiting, so a chatty child cannot block on a full pipe
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            let error = CommandTimeout {
+                command: format!("{} {}", cmd, args.join(" ")),
+                timeout,
+            };
+            report::record_command(cmd, args, false, Some(error.to_string()));
+            return Err(error.into());
+        }
+        thread::sleep(Duration::from_millis(100));
+    };
+
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    if !status.success() {
+        report::record_command(cmd, args, false, Some(stderr.trim().to_string()));
+        bail!(
+            "Command failed: {} {}\n{}",
             cmd,
-            args.join(" ")
+            args.join(" "),
+            stderr.trim()
         );
-        Ok(String::new())
-    } else {
-        run(cmd, args)
+    }
+    report::record_command(cmd, args, false, None);
+    trace!("Output: {}", stdout.trim());
+    Ok(stdout.trim().to_string())
+}
+
+/// Read a child's pipe to the end on a separate thread
+fn drain<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut text = String::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_string(&mut text);
+        }
+        text
+    })
+}
+
+pub fn run_or_dry(cmd: &str, args: &[&str], dry_run: bool) -> Result<String> {
+    RealRunner.run_or_dry(cmd, args, dry_run)
+}
+
+/// Test runner: canned output per command name, every command line recorded
+#[cfg(test)]
+#[derive(Default)]
+pub struct MockRunner {
+    outputs: std::collections::HashMap<String, std::result::Result<String, String>>,
+    calls: std::cell::RefCell<Vec<String>>,
+}
+
+#[cfg(test)]
+impl MockRunner {
+    /// `cmd` succeeds with `output` (commands without an entry print nothing)
+    pub fn with_output(mut self, cmd: &str, output: &str) -> Self {
+        self.outputs.insert(cmd.to_string(), Ok(output.to_string()));
+        self
+    }
+
+    /// `cmd` fails with `message`
+    pub fn with_failure(mut self, cmd: &str, message: &str) -> Self {
+        self.outputs
+            .insert(cmd.to_string(), Err(message.to_string()));
+        self
+    }
+
+    /// Command lines run so far, e.g. "systemctl daemon-reload"
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.borrow().clone()
+    }
+}
+
+#[cfg(test)]
+impl CommandRunner for MockRunner {
+    fn run(&self, cmd: &str, args: &[&str]) -> Result<String> {
+        let line = std::iter::once(cmd)
+            .chain(args.iter().copied())
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.calls.borrow_mut().push(line);
+        match self.outputs.get(cmd) {
+            Some(Ok(output)) => Ok(output.clone()),
+            Some(Err(message)) => bail!("Command failed: {}\n{}", cmd, message),
+            None => Ok(String::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_with_timeout_kills_hanging_command() {
+        let started = Instant::now();
+        let err = run_with_timeout("sleep", &["5"], Duration::from_millis(200)).unwrap_err();
+        assert!(err.downcast_ref::<CommandTimeout>().is_some());
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let output = run_with_timeout("echo", &["done"], Duration::from_secs(5)).unwrap();
+        assert_eq!(output, "done");
+    }
+
+    #[test]
+    fn mock_runner_records_calls_and_skips_dry_runs() {
+        let runner = MockRunner::default()
+            .with_output("blkid", "1234-abcd")
+            .with_failure("mount", "wrong fs type");
+
+        assert_eq!(runner.run("blkid", &["-o", "value"]).unwrap(), "1234-abcd");
+        assert!(runner.run("mount", &["/dev/sdd"]).is_err());
+        assert_eq!(runner.run_or_dry("umount", &["/mnt"], true).unwrap(), "");
+        assert_eq!(runner.calls(), vec!["blkid -o value", "mount /dev/sdd"]);
     }
 }
@@ -0,0 +1,259 @@
+//! WSL integration helpers
+
+use anyhow::{bail, Result};
+use ini::Ini;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::utils::cli::is_executable;
+use crate::utils::shell::{run as shell_run, run_with_timeout, CommandTimeout};
+
+/// Windows-side wsl.exe, reachable only through interop
+pub const WSL_EXE: &str = "/mnt/c/Windows/System32/wsl.exe";
+
+/// How long `wsl.exe --mount` may take; it can hang forever when interop is wedged
+pub const WSL_MOUNT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Per-distro WSL settings, including the [boot] command that runs attach
+pub const WSL_CONF: &str = "/etc/wsl.conf";
+
+/// Copy of wsl.conf taken before wslarc rewrites it
+pub const WSL_CONF_BACKUP: &str = "/etc/wsl.conf.wslarc.bak";
+
+const BINFMT_DIR: &str = "/proc/sys/fs/binfmt_misc";
+
+/// binfmt_misc entries registered by WSL for Windows executables
+const INTEROP_ENTRIES: &[&str] = &["WSLInterop", "WSLInterop-late"];
+
+/// Whether Windows executables such as wsl.exe can be run from this distro
+pub fn interop_available() -> bool {
+    INTEROP_ENTRIES.iter().any(|entry| {
+        fs::read_to_string(Path::new(BINFMT_DIR).join(entry))
+            .map(|content| binfmt_entry_enabled(&content))
+            .unwrap_or(false)
+    })
+}
+
+/// Fail with an actionable message when wsl.exe cannot be executed
+pub fn ensure_interop() -> Result<()> {
+    if !interop_available() {
+        bail!(
+            "WSL interop is disabled; enable it in /etc/wsl.conf [interop] enabled=true \
+             and restart WSL (wsl --shutdown)"
+        );
+    }
+    Ok(())
+}
+
+/// Attach a VHDX as a bare block device (`wsl.exe --mount --vhd <path> --bare`)
+///
+/// `windows_path` must already be a Windows path (see `to_windows_path`).
+pub fn mount_bare_vhd(windows_path: &str) -> Result<()> {
+    let args = ["--mount", "--vhd", windows_path, "--bare"];
+    match run_with_timeout(WSL_EXE, &args, WSL_MOUNT_TIMEOUT) {
+        Ok(_) => Ok(()),
+        Err(e) if e.is::<CommandTimeout>() => Err(e.context(
+            "wsl.exe hung; WSL interop may be disabled or stuck. Check [interop] in \
+             /etc/wsl.conf and restart WSL (wsl --shutdown)",
+        )),
+        Err(e) => Err(e),
+    }
+}
+
+/// Why the wsl.conf [boot] command would not run this wslarc, or None if it would
+///
+/// Boot-time attach runs whatever binary the command names, so a stale copy
+/// left behind by an upgrade keeps running old code.
+pub fn boot_command_problem() -> Option<String> {
+    let conf = Ini::load_from_file(WSL_CONF).ok();
+    let command = conf
+        .as_ref()
+        .and_then(|conf| conf.section(Some("boot")))
+        .and_then(|boot| boot.get("command"));
+    let Some(binary) = command.and_then(boot_binary) else {
+        return Some(format!("{} has no wslarc [boot] command", WSL_CONF));
+    };
+
+    if !is_executable(binary) {
+        return Some(format!(
+            "boot command binary {} is missing or not executable",
+            binary
+        ));
+    }
+
+    match shell_run(binary, &["--version"]) {
+        Ok(output) if output.trim() == current_version() => None,
+        Ok(output) => Some(format!(
+            "{} is {}, this is {}",
+            binary,
+            output.trim(),
+            current_version()
+        )),
+        Err(_) => Some(format!("{} --version failed", binary)),
+    }
+}
+
+/// The wslarc binary a [boot] command runs, if it runs wslarc at all
+fn boot_binary(command: &str) -> Option<&str> {
+    command
+        .split_whitespace()
+        .next()
+        .filter(|binary| binary.ends_with("/wslarc"))
+}
+
+fn current_version() -> String {
+    format!("wslarc {}", env!("CARGO_PKG_VERSION"))
+}
+
+fn binfmt_entry_enabled(content: &str) -> bool {
+    content.lines().next().map(str::trim) == Some("enabled")
+}
+
+/// `content` with `command` set under [boot], every other line left as is
+///
+/// Edits the text instead of round-tripping it through an INI parser, so
+/// comments, blank lines and the order of sections and keys survive. An
+/// existing `command` line is replaced in place; otherwise the key is added at
+/// the end of [boot], or a [boot] section is appended.
+pub fn with_boot_command(content: &str, command: &str) -> String {
+    let new_line = format!("command={}", command);
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    let mut in_boot = false;
+    // Index after the last non-blank line of [boot], where a new key goes
+    let mut boot_end = None;
+
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') && trimmed.ends_with(']') {
+            in_boot = trimmed[1..trimmed.len() - 1].trim() == "boot";
+            if in_boot {
+                boot_end = Some(i + 1);
+            }
+            continue;
+        }
+        if !in_boot || trimmed.is_empty() {
+            continue;
+        }
+        boot_end = Some(i + 1);
+        if trimmed.starts_with('#') || trimmed.starts_with(';') {
+            continue;
+        }
+        if trimmed.split('=').next().map(str::trim) == Some("command") {
+            lines[i] = new_line;
+            return join_lines(&lines);
+        }
+    }
+
+    match boot_end {
+        Some(end) => lines.insert(end, new_line),
+        None => {
+            if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push("[boot]".to_string());
+            lines.push(new_line);
+        }
+    }
+    join_lines(&lines)
+}
+
+fn join_lines(lines: &[String]) -> String {
+    let mut content = lines.join("\n");
+    content.push('\n');
+    content
+}
+
+/// Windows form of a path for wsl.exe, accepting `/mnt/<drive>/...` too
+///
+/// `/mnt/c/Users/me/btrfs.vhdx` becomes `C:\Users\me\btrfs.vhdx`; any
+/// other path only has its slashes turned into backslashes.
+pub fn to_windows_path(path: &str) -> String {
+    if let Some(rest) = path.strip_prefix("/mnt/") {
+        let (drive, tail) = rest.split_once('/').unwrap_or((rest, ""));
+        let mut chars = drive.chars();
+        if let (Some(letter), None) = (chars.next(), chars.next()) {
+            if letter.is_ascii_alphabetic() {
+                return format!(
+                    "{}:\\{}",
+                    letter.to_ascii_uppercase(),
+                    tail.replace('/', "\\")
+                );
+            }
+        }
+    }
+    path.replace('/', "\\")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binfmt_entry_enabled_reads_first_line() {
+        assert!(binfmt_entry_enabled(
+            "enabled\ninterpreter /init\nflags: PF\noffset 0\nmagic 4d5a\n"
+        ));
+        assert!(!binfmt_entry_enabled("disabled\ninterpreter /init\n"));
+        assert!(!binfmt_entry_enabled(""));
+    }
+
+    #[test]
+    fn boot_binary_requires_wslarc_command() {
+        assert_eq!(
+            boot_binary("/usr/local/bin/wslarc attach"),
+            Some("/usr/local/bin/wslarc")
+        );
+        assert_eq!(boot_binary("mount -a"), None);
+        assert_eq!(boot_binary(""), None);
+    }
+
+    #[test]
+    fn with_boot_command_keeps_comments_and_order() {
+        let content = "# WSL settings\n[boot]\nsystemd=true # needed for mounts\ncommand=old\n\n[interop]\n; keep\nenabled=true\n";
+        assert_eq!(
+            with_boot_command(content, "/usr/local/bin/wslarc attach"),
+            "# WSL settings\n[boot]\nsystemd=true # needed for mounts\ncommand=/usr/local/bin/wslarc attach\n\n[interop]\n; keep\nenabled=true\n"
+        );
+    }
+
+    #[test]
+    fn with_boot_command_adds_key_or_section() {
+        assert_eq!(
+            with_boot_command("[boot]\nsystemd=true\n\n[user]\ndefault=me\n", "x"),
+            "[boot]\nsystemd=true\ncommand=x\n\n[user]\ndefault=me\n"
+        );
+        assert_eq!(
+            with_boot_command("[user]\ndefault=me", "x"),
+            "[user]\ndefault=me\n\n[boot]\ncommand=x\n"
+        );
+        assert_eq!(with_boot_command("", "x"), "[boot]\ncommand=x\n");
+        // A commented-out command is not the live key
+        assert_eq!(
+            with_boot_command("[boot]\n# command=old\n", "x"),
+            "[boot]\n# command=old\ncommand=x\n"
+        );
+    }
+
+    #[test]
+    fn to_windows_path_converts_mnt_drive_paths() {
+        assert_eq!(
+            to_windows_path("/mnt/c/Users/me/btrfs.vhdx"),
+            r"C:\Users\me\btrfs.vhdx"
+        );
+        assert_eq!(to_windows_path("/mnt/d/"), r"D:\");
+        assert_eq!(to_windows_path("/mnt/wsl/disk.vhdx"), r"\mnt\wsl\disk.vhdx");
+    }
+
+    #[test]
+    fn to_windows_path_keeps_windows_paths() {
+        assert_eq!(
+            to_windows_path(r"C:\Users\me\btrfs.vhdx"),
+            r"C:\Users\me\btrfs.vhdx"
+        );
+        assert_eq!(
+            to_windows_path("C:/Users/me/btrfs.vhdx"),
+            r"C:\Users\me\btrfs.vhdx"
+        );
+    }
+}